@@ -0,0 +1,182 @@
+use anyhow::anyhow;
+use serde_json::{Value, json};
+
+use time::UtcOffset;
+
+use crate::{
+    config::ActivityPub,
+    feed::{Entry, offset_suffix},
+};
+
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// The location of the actor, relative to the base URL.
+pub const ACTOR_PATH: &str = "/activitypub/actor.json";
+/// The location of the outbox, relative to the base URL.
+pub const OUTBOX_PATH: &str = "/activitypub/outbox.json";
+
+/// A static ActivityPub presence for the site.
+///
+/// Being static, we can't receive activities, or sign anything, but this is enough
+/// for fediverse software to discover the actor, and display its posts.
+pub struct Actor<'a> {
+    pub config: &'a ActivityPub,
+    /// The display name of the actor, usually the title of the site.
+    pub name: &'a str,
+    pub base_url: &'a str,
+    /// The timezone of the site, since entries only have a date.
+    pub offset: UtcOffset,
+}
+
+impl Actor<'_> {
+    fn url(&self, link: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), link)
+    }
+
+    /// The domain of the site, extracted from the base URL.
+    fn domain(&self) -> anyhow::Result<&str> {
+        let without_scheme = self
+            .base_url
+            .split_once("://")
+            .map(|(_, rest)| rest)
+            .unwrap_or(self.base_url);
+        without_scheme
+            .split('/')
+            .next()
+            .filter(|domain| !domain.is_empty())
+            .ok_or_else(|| anyhow!("failed to get domain from `{}`", self.base_url))
+    }
+
+    /// The WebFinger document, to be served at `/.well-known/webfinger`.
+    pub fn webfinger(&self) -> anyhow::Result<Value> {
+        Ok(json!({
+            "subject": format!("acct:{}@{}", self.config.username, self.domain()?),
+            "aliases": [self.url(ACTOR_PATH)],
+            "links": [
+                {
+                    "rel": "self",
+                    "type": "application/activity+json",
+                    "href": self.url(ACTOR_PATH),
+                },
+                {
+                    "rel": "http://webfinger.net/rel/profile-page",
+                    "type": "text/html",
+                    "href": self.url("/"),
+                }
+            ]
+        }))
+    }
+
+    /// The actor document itself.
+    pub fn actor(&self) -> Value {
+        let mut out = json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": self.url(ACTOR_PATH),
+            "type": "Person",
+            "preferredUsername": self.config.username,
+            "name": self.name,
+            "url": self.url("/"),
+            "inbox": self.url("/activitypub/inbox"),
+            "outbox": self.url(OUTBOX_PATH),
+        });
+        if let Some(summary) = &self.config.summary {
+            out["summary"] = json!(summary);
+        }
+        out
+    }
+
+    /// An outbox containing a `Create` activity for each entry.
+    pub fn outbox(&self, entries: &[Entry<'_>]) -> Value {
+        let actor = self.url(ACTOR_PATH);
+        let offset = offset_suffix(self.offset);
+        let items = entries
+            .iter()
+            .map(|entry| {
+                let url = self.url(entry.link);
+                json!({
+                    "id": format!("{url}#create"),
+                    "type": "Create",
+                    "actor": actor,
+                    "published": format!("{}T00:00:00{offset}", entry.published),
+                    "to": [PUBLIC],
+                    "object": {
+                        "id": url,
+                        "type": "Note",
+                        "name": entry.title,
+                        "url": url,
+                        "attributedTo": actor,
+                        "published": format!("{}T00:00:00{offset}", entry.published),
+                        "updated": format!("{}T00:00:00{offset}", entry.updated),
+                        "content": entry.content,
+                        "to": [PUBLIC],
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": self.url(OUTBOX_PATH),
+            "type": "OrderedCollection",
+            "totalItems": items.len(),
+            "orderedItems": items,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> ActivityPub {
+        ActivityPub {
+            username: "blog".into(),
+            summary: Some("A blog".into()),
+        }
+    }
+
+    #[test]
+    fn webfinger() {
+        let config = config();
+        let actor = Actor {
+            config: &config,
+            name: "Blog",
+            base_url: "https://example.com/",
+            offset: UtcOffset::UTC,
+        };
+        let webfinger = actor.webfinger().unwrap();
+        assert_eq!(webfinger["subject"], "acct:blog@example.com");
+        assert_eq!(
+            webfinger["links"][0]["href"],
+            "https://example.com/activitypub/actor.json"
+        );
+        assert_eq!(actor.actor()["summary"], "A blog");
+    }
+
+    #[test]
+    fn outbox() {
+        let config = config();
+        let actor = Actor {
+            config: &config,
+            name: "Blog",
+            base_url: "https://example.com",
+            offset: UtcOffset::UTC,
+        };
+        let entries = [Entry {
+            title: "Post",
+            link: "/post.html",
+            published: "2024-01-01",
+            updated: "2024-01-02",
+            authors: &[],
+            summary: None,
+            cover: None,
+            content: "<p>Hi</p>",
+        }];
+        let outbox = actor.outbox(&entries);
+        assert_eq!(outbox["totalItems"], 1);
+        let create = &outbox["orderedItems"][0];
+        assert_eq!(create["type"], "Create");
+        assert_eq!(create["object"]["id"], "https://example.com/post.html");
+        assert_eq!(create["object"]["published"], "2024-01-01T00:00:00Z");
+        assert_eq!(create["object"]["content"], "<p>Hi</p>");
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::hash_of,
+    diagnostic::Warnings,
+    fs_utils::{copy_if_changed, write_if_changed},
+};
+
+#[cfg(feature = "minify")]
+mod imp {
+    /// Minify some CSS, returning `None` if it couldn't be parsed.
+    pub fn minify_css(data: &str) -> Option<String> {
+        minifier::css::minify(data).ok().map(|x| x.to_string())
+    }
+
+    /// Minify some JavaScript, returning `None` if it couldn't be parsed.
+    pub fn minify_js(data: &str) -> Option<String> {
+        minifier::js::minify(data).ok().map(|x| x.to_string())
+    }
+}
+
+#[cfg(not(feature = "minify"))]
+mod imp {
+    /// Minify some CSS, returning `None` if this isn't possible.
+    pub fn minify_css(_data: &str) -> Option<String> {
+        None
+    }
+
+    /// Minify some JavaScript, returning `None` if this isn't possible.
+    pub fn minify_js(_data: &str) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(feature = "sass")]
+fn compile_sass(path: &Path) -> anyhow::Result<Option<String>> {
+    let css = grass::from_path(path, &grass::Options::default())
+        .map_err(|e| anyhow::anyhow!("failed to compile {}: {e}", path.display()))?;
+    Ok(Some(css))
+}
+
+/// Without the `sass` feature, Sass files can't be compiled, giving `None`.
+#[cfg(not(feature = "sass"))]
+fn compile_sass(_path: &Path) -> anyhow::Result<Option<String>> {
+    Ok(None)
+}
+
+/// Whether this build of clog is able to minify files.
+pub const CAN_MINIFY: bool = cfg!(feature = "minify");
+
+/// Minify a file, based on its extension, returning `None` for other kinds of files.
+fn minify(path: &Path, data: &str, warnings: &mut Warnings) -> Option<String> {
+    let minified = match path.extension().and_then(|x| x.to_str()) {
+        Some("css") => imp::minify_css(data),
+        Some("js") => imp::minify_js(data),
+        _ => return None,
+    };
+    if minified.is_none() && CAN_MINIFY {
+        warnings.push(path, "failed to minify".to_string());
+    }
+    minified
+}
+
+/// Copy a file from `static`, compiling Sass files to CSS, and minifying CSS and JS with `minify`.
+///
+/// Partials, like `_colors.scss`, only get imported by other files, and aren't copied.
+/// Processed files get compared with the last output, instead of by their metadata,
+/// since their output never matches the source, so that they're only written if they changed.
+pub fn copy_static_file(
+    from: &Path,
+    to: &Path,
+    minify_files: bool,
+    warnings: &mut Warnings,
+) -> anyhow::Result<()> {
+    let (to, data) = match from.extension().and_then(|x| x.to_str()) {
+        Some("scss" | "sass") => {
+            let is_partial = from
+                .file_name()
+                .is_some_and(|x| x.to_string_lossy().starts_with('_'));
+            if is_partial {
+                return Ok(());
+            }
+            let Some(css) = compile_sass(from)? else {
+                let warning = "requires clog to be built with the `sass` feature";
+                warnings.push(from, warning.to_string());
+                return copy_if_changed(from, to);
+            };
+            (to.with_extension("css"), css)
+        }
+        Some("css" | "js") if minify_files => (to.to_path_buf(), fs::read_to_string(from)?),
+        _ => return copy_if_changed(from, to),
+    };
+    let minified = minify_files.then(|| minify(&to, &data, warnings)).flatten();
+    write_if_changed(to, minified.unwrap_or(data))
+}
+
+/// The file name of a bundle, with a hash of its contents, like `site.1a2b3c4d.css`.
+fn fingerprinted(name: &Path, data: &[u8]) -> PathBuf {
+    let hash = format!("{:08x}", hash_of(&data) as u32);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match name.extension() {
+        Some(ext) => format!("{stem}.{hash}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{hash}"),
+    };
+    name.with_file_name(file_name)
+}
+
+/// The name of the file a fingerprinted copy was made from,
+/// like `style.css` for `style.1a2b3c4d.css`.
+fn fingerprinted_from(file_name: &str) -> Option<String> {
+    let is_hash = |x: &str| x.len() == 8 && x.bytes().all(|b| b.is_ascii_hexdigit());
+    if let Some((rest, ext)) = file_name.rsplit_once('.')
+        && let Some((stem, hash)) = rest.rsplit_once('.')
+        && is_hash(hash)
+    {
+        return Some(format!("{stem}.{ext}"));
+    }
+    let (stem, hash) = file_name.rsplit_once('.')?;
+    is_hash(hash).then(|| stem.to_string())
+}
+
+/// Remove the fingerprinted copies in a directory left over from earlier builds.
+///
+/// `current` maps the name of each file fingerprinted in the directory to the name
+/// of its current copy. Files with one of these names are kept, even if they look
+/// like a copy themselves.
+fn remove_old_fingerprints(dir: &Path, current: &HashMap<String, String>) -> anyhow::Result<()> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry?;
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if current.contains_key(&file_name) || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let old = fingerprinted_from(&file_name)
+            .and_then(|name| current.get(&name))
+            .is_some_and(|copy| *copy != file_name);
+        if old {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// The name of a file, as a string, for comparing with the names in a directory.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Make a fingerprinted copy of each file copied from `static`, like `style.1a2b3c4d.css`.
+///
+/// The files keep their original name as well, so that files referring to each other,
+/// like fonts in a stylesheet, keep working. This returns the URL of each copy, by
+/// the original name, where Sass files are named after the CSS they compile to.
+/// Copies from earlier builds, of files which changed since, get removed.
+pub fn fingerprint_dir(
+    static_dir: &Path,
+    out_dir: &Path,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut urls = BTreeMap::new();
+    if !static_dir.is_dir() {
+        return Ok(urls);
+    }
+    let mut q = vec![static_dir.to_path_buf()];
+    while let Some(dir) = q.pop() {
+        let mut current = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                q.push(path);
+                continue;
+            }
+            let mut name = path.strip_prefix(static_dir)?.to_path_buf();
+            if matches!(
+                name.extension().and_then(|x| x.to_str()),
+                Some("scss" | "sass")
+            ) {
+                name.set_extension("css");
+            }
+            let Ok(data) = fs::read(out_dir.join(&name)) else {
+                // Sass partials, or symlinks which didn't get copied.
+                continue;
+            };
+            let file = fingerprinted(&name, &data);
+            write_if_changed(out_dir.join(&file), &data)?;
+            current.insert(file_name(&name), file_name(&file));
+            urls.insert(url_name(&name), static_url(&file));
+        }
+        remove_old_fingerprints(&out_dir.join(dir.strip_prefix(static_dir)?), &current)?;
+    }
+    Ok(urls)
+}
+
+/// The name of an asset, with forward slashes, for templates to refer to.
+fn url_name(name: &Path) -> String {
+    name.to_string_lossy().replace('\\', "/")
+}
+
+fn static_url(file: &Path) -> String {
+    format!("/static/{}", url_name(file))
+}
+
+/// Write out the bundles of a site, each joining some files in `static`.
+///
+/// Bundles get a hash of their contents in their file name, so that browsers don't
+/// keep using an old version. This returns the URL of each bundle, by name.
+pub fn write_bundles(
+    static_dir: &Path,
+    out_dir: &Path,
+    bundles: &BTreeMap<PathBuf, Vec<PathBuf>>,
+    minify_bundles: bool,
+    warnings: &mut Warnings,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut urls = BTreeMap::new();
+    for (name, inputs) in bundles {
+        let mut data = String::new();
+        for input in inputs {
+            let path = static_dir.join(input);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+            data.push_str(&content);
+            if !data.ends_with('\n') {
+                data.push('\n');
+            }
+        }
+        if minify_bundles && let Some(minified) = minify(name, &data, warnings) {
+            data = minified;
+        }
+        let file = fingerprinted(name, data.as_bytes());
+        let out_path = out_dir.join(&file);
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_if_changed(&out_path, &data)?;
+        if let Some(parent) = out_path.parent() {
+            let current = HashMap::from([(file_name(name), file_name(&file))]);
+            remove_old_fingerprints(parent, &current)?;
+        }
+        urls.insert(url_name(name), static_url(&file));
+    }
+    Ok(urls)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    /// The names of the files in a directory, sorted.
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files = fs::read_dir(dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn bundles() {
+        let dir = TempDir::new("assets");
+        fs::create_dir_all(dir.join("static/css")).unwrap();
+        fs::write(dir.join("static/css/a.css"), "a { color: red; }").unwrap();
+        fs::write(dir.join("static/css/b.css"), "b { color: blue; }\n").unwrap();
+        let bundles = BTreeMap::from([(
+            PathBuf::from("css/site.css"),
+            vec![PathBuf::from("css/a.css"), PathBuf::from("css/b.css")],
+        )]);
+        let urls = write_bundles(
+            &dir.join("static"),
+            &dir.join("out"),
+            &bundles,
+            false,
+            &mut Warnings::default(),
+        )
+        .unwrap();
+        let url = &urls["css/site.css"];
+        let data = fs::read_to_string(dir.join("out").join(url.trim_start_matches("/static/")));
+        assert!(url.starts_with("/static/css/site.") && url.ends_with(".css"));
+        assert_eq!(data.unwrap(), "a { color: red; }\nb { color: blue; }\n");
+        // The bundle from before gets replaced, once it changes.
+        fs::write(dir.join("static/css/b.css"), "b { color: green; }\n").unwrap();
+        let urls = write_bundles(
+            &dir.join("static"),
+            &dir.join("out"),
+            &bundles,
+            false,
+            &mut Warnings::default(),
+        )
+        .unwrap();
+        assert_ne!(&urls["css/site.css"], url);
+        assert_eq!(
+            files(&dir.join("out/css")),
+            [file_name(Path::new(&urls["css/site.css"]))]
+        );
+    }
+
+    #[test]
+    fn fingerprints() {
+        let dir = TempDir::new("fingerprint");
+        for root in ["static", "out"] {
+            fs::create_dir_all(dir.join(root).join("img")).unwrap();
+            fs::write(dir.join(root).join("img/a.png"), "a").unwrap();
+        }
+        let urls = fingerprint_dir(&dir.join("static"), &dir.join("out")).unwrap();
+        let url = urls["img/a.png"].clone();
+        let copy = fs::read_to_string(dir.join("out").join(url.trim_start_matches("/static/")));
+        assert_ne!(url, "/static/img/a.png");
+        assert!(url.starts_with("/static/img/a.") && url.ends_with(".png"));
+        assert_eq!(copy.unwrap(), "a");
+        // Files which look like a copy, but come from `static`, are kept.
+        for root in ["static", "out"] {
+            fs::write(dir.join(root).join("img/b.0000abcd.png"), "b").unwrap();
+        }
+        fs::write(dir.join("out/img/a.png"), "b").unwrap();
+        let urls = fingerprint_dir(&dir.join("static"), &dir.join("out")).unwrap();
+        let mut expected = vec!["a.png".to_string(), "b.0000abcd.png".to_string()];
+        expected
+            .extend(["img/a.png", "img/b.0000abcd.png"].map(|x| file_name(Path::new(&urls[x]))));
+        expected.sort();
+        assert_ne!(urls["img/a.png"], url);
+        assert_eq!(files(&dir.join("out/img")), expected);
+    }
+
+    #[cfg(feature = "sass")]
+    #[test]
+    fn sass() {
+        let dir = TempDir::new("sass");
+        fs::create_dir_all(dir.join("static/css")).unwrap();
+        fs::write(dir.join("static/css/_colors.scss"), "$red: #f00;\n").unwrap();
+        fs::write(
+            dir.join("static/css/main.scss"),
+            "@use 'colors';\na { b { color: colors.$red; } }\n",
+        )
+        .unwrap();
+        crate::fs_utils::copy_dir_with(
+            &dir.join("static"),
+            &dir.join("out"),
+            &mut Warnings::default(),
+            &mut |from, to, warnings| copy_static_file(from, to, false, warnings),
+        )
+        .unwrap();
+        let css = fs::read_to_string(dir.join("out/css/main.css")).unwrap();
+        let left = fs::read_dir(dir.join("out/css")).unwrap().count();
+        assert_eq!(css, "a b {\n  color: #f00;\n}\n");
+        assert_eq!(left, 1);
+    }
+
+    #[cfg(feature = "minify")]
+    #[test]
+    fn minifying() {
+        let mut warnings = Warnings::default();
+        assert_eq!(
+            minify(Path::new("a.css"), "a {\n  color: red;\n}\n", &mut warnings).unwrap(),
+            "a{color:red;}"
+        );
+        let js = minify(Path::new("a.js"), "// hi\nlet x = 1;\n", &mut warnings).unwrap();
+        assert!(!js.contains("hi"));
+        assert_eq!(minify(Path::new("a.txt"), "x", &mut warnings), None);
+        assert!(warnings.is_empty());
+    }
+
+    #[cfg(feature = "minify")]
+    #[test]
+    fn skips_unchanged_minified_files() {
+        let dir = TempDir::new("minified");
+        let (from, to) = (dir.join("a.css"), dir.join("b.css"));
+        fs::write(&from, "a {\n  color: red;\n}\n").unwrap();
+        let mut warnings = Warnings::default();
+        copy_static_file(&from, &to, true, &mut warnings).unwrap();
+        let old = std::time::SystemTime::UNIX_EPOCH;
+        fs::File::options()
+            .write(true)
+            .open(&to)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        copy_static_file(&from, &to, true, &mut warnings).unwrap();
+        let modified = fs::metadata(&to).unwrap().modified().unwrap();
+        let minified = fs::read_to_string(&to).unwrap();
+        assert_eq!(minified, "a{color:red;}");
+        assert_eq!(modified, old);
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diagnostic::{Diagnostic, Warnings},
+    fs_utils::write_if_changed,
+};
+
+/// The name of the cache file, inside of the output directory.
+pub const CACHE_FILE: &str = ".clog-cache";
+
+/// Hash a value, for the purposes of the cache.
+pub fn hash_of(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hash the contents and names of all the files in a directory, recursively.
+///
+/// A missing directory is hashed just like an empty one.
+pub fn hash_dir(hasher: &mut impl Hasher, dir: &Path) -> anyhow::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        entry.file_name().hash(hasher);
+        if entry.file_type()?.is_dir() {
+            hash_dir(hasher, &path)?;
+        } else {
+            fs::read(&path)?.hash(hasher);
+        }
+    }
+    Ok(())
+}
+
+/// What we remember about a page from a previous build.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedPage {
+    /// The hash of the page's markdown, and front matter.
+    pub hash: u64,
+    /// The HTML for just the markdown content, needed for feeds.
+    pub body: String,
+    /// The HTML for the start of the content, needed for lists.
+    #[serde(default)]
+    pub excerpt: Option<String>,
+    pub warnings: Vec<Diagnostic>,
+    /// How many wikilinks in the page didn't resolve.
+    #[serde(default)]
+    pub broken_links: usize,
+    /// Whether the page contains math.
+    #[serde(default)]
+    pub math: bool,
+    /// The files included by shortcodes, along with their hash, if they existed.
+    #[serde(default)]
+    pub includes: Vec<(String, Option<u64>)>,
+}
+
+/// A cache of the pages in a previous build.
+///
+/// A page can be skipped if neither it, the files it includes, nor anything shared by all pages
+/// (the config, templates, data files, and the titles and links of other pages) changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    /// The hash of everything shared by all pages.
+    site: u64,
+    pages: BTreeMap<PathBuf, CachedPage>,
+}
+
+impl Cache {
+    /// Create an empty cache, for a site with a given hash.
+    pub fn new(site: u64) -> Self {
+        Self {
+            site,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Load the cache from a file, returning an empty cache if it's missing or invalid.
+    pub fn load(path: &Path, warnings: &mut Warnings) -> Self {
+        let Ok(data) = fs::read(path) else {
+            return Self::default();
+        };
+        match serde_json::from_slice(&data) {
+            Ok(cache) => cache,
+            Err(e) => {
+                warnings.push(path, format!("ignoring invalid cache: {e}"));
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the cache, leaving the file alone if nothing changed since the last build.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        write_if_changed(path, serde_json::to_vec(self)?)
+    }
+
+    /// Get a page, if it hasn't changed since this cache was made.
+    pub fn get(&self, site: u64, in_path: &Path, hash: u64) -> Option<&CachedPage> {
+        if site != self.site {
+            return None;
+        }
+        self.pages.get(in_path).filter(|page| page.hash == hash)
+    }
+
+    pub fn insert(&mut self, in_path: PathBuf, page: CachedPage) {
+        self.pages.insert(in_path, page);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invalidation() {
+        let mut cache = Cache::new(1);
+        let page = CachedPage {
+            hash: 2,
+            body: "body".into(),
+            excerpt: None,
+            warnings: Vec::new(),
+            broken_links: 0,
+            math: false,
+            includes: Vec::new(),
+        };
+        cache.insert("a.md".into(), page);
+        let data = serde_json::to_vec(&cache).unwrap();
+        let cache: Cache = serde_json::from_slice(&data).unwrap();
+        assert_eq!(
+            cache.get(1, Path::new("a.md"), 2).map(|x| x.body.as_str()),
+            Some("body")
+        );
+        assert!(cache.get(1, Path::new("a.md"), 3).is_none());
+        assert!(cache.get(0, Path::new("a.md"), 2).is_none());
+        assert!(cache.get(1, Path::new("b.md"), 2).is_none());
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{Config, DateSource, DuplicateNames},
+    diagnostic::Diagnostic,
+    frontmatter::DateSettings,
+    markdown::{MathContext, Options, adjust_headings, make_mdast, write_md_ast},
+    sitemap::{SiteMap, content_files, read_front_matter},
+};
+
+/// How bad a problem found by a check is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// Something which is likely a mistake, but which the site works fine with.
+    Warning,
+    /// Something definitely broken, like a link to nothing.
+    Error,
+}
+
+/// A problem with a specific file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub path: PathBuf,
+    pub diagnostic: Diagnostic,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "WARN",
+            Severity::Error => "ERROR",
+        };
+        write!(f, "{severity}: {}", self.diagnostic.show_in(&self.path))
+    }
+}
+
+/// All of the problems found while checking a site.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    fn push(&mut self, severity: Severity, path: &Path, diagnostic: impl Into<Diagnostic>) {
+        self.issues.push(Issue {
+            severity,
+            path: path.to_path_buf(),
+            diagnostic: diagnostic.into(),
+        });
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|x| x.severity == severity)
+            .count()
+    }
+}
+
+/// Check the content of a site, without writing any output.
+///
+/// This looks for markdown which fails to parse, pages missing a title or date,
+/// pages sharing a name, broken wikilinks, and problems with headings.
+pub fn check(config: &Config, content_dir: &Path, options: &Options) -> anyhow::Result<Report> {
+    let mut report = Report::default();
+    // Building the site map reads the git history for the dates, which only it needs.
+    let dates = DateSettings {
+        source: DateSource::Mtime,
+        ..config.date_settings()?
+    };
+    let mut file_warnings = Vec::new();
+    let files = content_files(config, content_dir, &mut file_warnings)?;
+    for path in files
+        .iter()
+        .filter(|x| x.extension().is_some_and(|x| x == "md"))
+    {
+        match read_front_matter(path, dates, config.inline_tags) {
+            Err(e) => report.push(Severity::Error, path, e),
+            Ok(front_matter) => {
+                for field in front_matter.inferred {
+                    report.push(Severity::Warning, path, format!("missing `{field}`"));
+                }
+            }
+        }
+    }
+    // The site map can't be built unless every page parses.
+    // Otherwise, it finds the same problems with files again, so these get reported once.
+    if report.count(Severity::Error) > 0 {
+        for (path, warning) in file_warnings {
+            report.push(Severity::Warning, &path, warning);
+        }
+        return Ok(report);
+    }
+    let site_map = SiteMap::build(config, content_dir, Path::new(""))?;
+    for (path, warning) in site_map.warnings() {
+        report.push(Severity::Warning, path, warning.clone());
+    }
+    let duplicates = match config.duplicate_names {
+        DuplicateNames::Newest => site_map.duplicate_names().collect(),
+        _ => Vec::new(),
+    };
+    for (name, pages) in duplicates {
+        for page in pages {
+            let message = format!("the name `{name}` is shared with other pages");
+            report.push(Severity::Error, &page.in_path, message);
+        }
+    }
+    let math_ctx = MathContext::default();
+    for page in site_map.pages() {
+        let source = fs::read_to_string(&page.in_path)?;
+        let mut ast = make_mdast(&source)?;
+        adjust_headings(&mut ast, page, options);
+        let log = match write_md_ast(
+            &mut io::sink(),
+            &site_map,
+            Some(page),
+            &math_ctx,
+            options,
+            &ast,
+        ) {
+            Ok(log) => log,
+            Err(e) => {
+                let diagnostic = Diagnostic::from(e).with_source(&source);
+                report.push(Severity::Error, &page.in_path, diagnostic);
+                continue;
+            }
+        };
+        for link in log.broken_links {
+            report.push(Severity::Error, &page.in_path, link.diagnostic(&source));
+        }
+        for issue in log.headings {
+            report.push(Severity::Warning, &page.in_path, issue.to_string());
+        }
+        for warning in log.warnings {
+            report.push(
+                Severity::Warning,
+                &page.in_path,
+                warning.with_source(&source),
+            );
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn finds_problems() {
+        let dir = TempDir::new("check");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(
+            dir.join("a/Note.md"),
+            "---\ntitle: A\ndate: 2024-01-01\n---\n[[Nope]]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("Note.md"),
+            "---\ndate: 2024-01-01\n---\n## A\n\n#### B\n",
+        )
+        .unwrap();
+        let report = check(&Config::default(), &dir, &Options::default()).unwrap();
+        let broken = report
+            .issues
+            .iter()
+            .find(|x| x.diagnostic.message.starts_with("unresolved"))
+            .unwrap();
+        assert_eq!(
+            broken.diagnostic.snippet.as_deref(),
+            Some("  |\n5 | [[Nope]]\n  | ^^^^^^^^")
+        );
+        let mut messages = report
+            .issues
+            .iter()
+            .map(|x| (x.severity, x.diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|x| x.1);
+        assert_eq!(
+            messages,
+            vec![
+                (Severity::Warning, "heading level skipped (h2 -> h4)"),
+                (Severity::Warning, "missing `title`"),
+                (
+                    Severity::Error,
+                    "the name `Note` is shared with other pages"
+                ),
+                (
+                    Severity::Error,
+                    "the name `Note` is shared with other pages"
+                ),
+                (Severity::Error, "unresolved wikilink `[[Nope]]`"),
+            ]
+        );
+    }
+}
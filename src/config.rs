@@ -1,9 +1,113 @@
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+use time::{UtcOffset, format_description};
+
+use crate::frontmatter::DateSettings;
+
+/// The author of a site.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Author {
+    pub name: String,
+    pub email: Option<String>,
+}
+
+/// Configuration for the static ActivityPub actor of a site.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ActivityPub {
+    /// The username of the actor, e.g. `blog` for `@blog@example.com`.
+    pub username: String,
+    /// A short description of the actor.
+    pub summary: Option<String>,
+}
+
+/// How a page should be described in `sitemap.xml`.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct SitemapSettings {
+    /// The priority of the page, between 0.0 and 1.0.
+    pub priority: Option<f64>,
+    /// How often the page changes, e.g. `weekly`.
+    pub changefreq: Option<String>,
+}
+
+impl SitemapSettings {
+    /// Fill in any missing settings from another, less specific, set of settings.
+    pub fn or(self, other: &Self) -> Self {
+        Self {
+            priority: self.priority.or(other.priority),
+            changefreq: self.changefreq.or_else(|| other.changefreq.clone()),
+        }
+    }
+}
+
+/// Where the dates of pages without any in their front matter come from.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DateSource {
+    /// When the file was last modified.
+    #[default]
+    Mtime,
+    /// The git history of the file, using its last commit, and its first for `published`.
+    Git,
+}
+
+/// A file listing the redirects from the aliases of pages, for hosts to read.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectsFile {
+    /// `_redirects`, as read by Netlify, and Cloudflare Pages.
+    Netlify,
+    /// `redirects.json`, mapping each old link to the new one.
+    Json,
+}
+
+/// How highlighted code gets its colors.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightStyle {
+    /// With inline styles, from the theme.
+    #[default]
+    Inline,
+    /// With classes, styled by a stylesheet for the theme, at `/highlight.css`.
+    Classes,
+}
+
+/// Which display equations should be numbered.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EquationNumbering {
+    /// Only number equations with a `\label{...}`.
+    #[default]
+    None,
+    /// Number every display equation.
+    All,
+    /// Number every display equation, restarting at each top-level heading, e.g. `2.1`.
+    Section,
+}
+
+/// Which page a wikilink goes to, when several pages share its name.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateNames {
+    /// The most recent page, with a warning.
+    #[default]
+    Newest,
+    /// The page with the shortest path, like `Idea.md` over `archive/Idea.md`.
+    Shortest,
+    /// The page in the earliest of `preferred_folders`, or the newest outside of them.
+    Folders,
+    /// None of them, so that links need a path, like `[[notes/Idea]]`.
+    Qualified,
+    /// None of them, failing the build instead.
+    Error,
+}
 
 /// Configuration for how to generate the site.
 #[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Config {
     /// Which folders to ignore.
     ///
@@ -17,13 +121,386 @@ pub struct Config {
     /// ignored_folders: ["Ignored/A"]
     /// ```
     pub ignored_folders: HashSet<PathBuf>,
-    /// Base URL for the site, used for sitemap generation.
+    /// Base URL for the site, like `https://example.com`.
+    ///
+    /// This is used to make absolute URLs, for sitemaps, feeds, and canonical links.
     pub base_url: Option<String>,
+    /// The title of the site, used in feeds.
+    pub title: Option<String>,
+    /// A short description of the site.
+    pub description: Option<String>,
+    /// The main author of the site.
+    ///
+    /// Pages without `authors` in their front matter are attributed to this author in feeds.
+    pub author: Option<Author>,
+    /// The language of the site, e.g. `en`.
+    pub language: Option<String>,
+    /// The timezone of the site, as an offset from UTC, like `+02:00`, or `UTC`.
+    ///
+    /// Dates taken from when files were modified are in this timezone, as are the
+    /// timestamps in feeds. Dates in the front matter with their own offset, like
+    /// `2024-01-01T23:30:00-05:00`, get converted to it. This defaults to UTC.
+    pub timezone: Option<String>,
+    /// Where to take the date of a page from, when its front matter doesn't have one.
+    ///
+    /// With `git`, files which haven't been committed yet still use when they were modified.
+    /// Shallow clones, which CI services often make, lack the history needed for this.
+    pub date_source: DateSource,
+    /// How the `dateformat` filter shows dates, when it isn't given a format, e.g.
+    /// `"[month repr:long] [day_ordinal], [year]"`, for `March 3rd, 2024`.
+    ///
+    /// This uses the format descriptions of the `time` crate, along with `[day_ordinal]`.
+    pub date_format: Option<String>,
+    /// The languages of a multilingual site, e.g. `[en, fr]`.
+    ///
+    /// Pages in a folder named after a language, like `content/fr/`, are in that language,
+    /// unless their front matter sets `lang`. Pages in a language other than `language`,
+    /// outside of its folder, get it added to the start of their URL, like `/fr/`.
+    /// Each language gets a list of its pages, and a feed, at `/fr/` and `/fr/atom.xml`.
+    pub languages: Vec<String>,
+    /// Follow symlinks inside of `content`, like symlinked attachment folders.
+    ///
+    /// Symlinks pointing to a folder containing them get skipped, since they'd loop forever.
+    /// Without this, symlinks are skipped, with a warning.
+    pub follow_symlinks: bool,
+    /// A pattern for the links to pages, replacing the default of following their path,
+    /// e.g. `/:folder/:slug/`, or `/:year/:month/:slug.html`.
+    ///
+    /// `:folder` is the slugified folder of a page, `:slug` its slug, or slugified name,
+    /// and `:year`, `:month`, and `:day` come from when it was published. A `permalink`
+    /// in the front matter of a page still takes precedence, and `index` pages keep
+    /// their location.
+    pub permalink: Option<String>,
+    /// Keep the names of pages and folders in their links, like `/Posts/Why%20Rust%3F.html`,
+    /// instead of slugifying them, like `/posts/why-rust.html`.
+    ///
+    /// Wikilinks still refer to pages by their names either way.
+    pub keep_paths: bool,
+    /// Keep rendered math between builds, in the output folder, so that formulas only
+    /// get rendered by KaTeX once.
+    ///
+    /// Formulas are only rendered once per build either way. This gets emptied with `--clean`.
+    pub math_cache: bool,
+    /// Write the redirects from aliases to a file, `netlify` or `json`, instead of writing
+    /// a page redirecting to the new location at each one.
+    pub redirects_file: Option<RedirectsFile>,
+    /// Build pages dated in the future, instead of skipping them until their date.
+    ///
+    /// Pages are dated by when they're published, if given, or by their date otherwise.
+    /// This can also be turned on with `--future`, e.g. to preview scheduled posts.
+    pub future: bool,
+    /// Commands transforming pages before they're written, like `python3 plugins/toc.py`.
+    ///
+    /// Each one is run from the site's folder, for each page, getting the page as JSON,
+    /// with its markdown as an `mdast` tree in `ast`, on its standard input. It can answer
+    /// with JSON containing a new `ast`, and values for the template, in `context`,
+    /// available as `plugin`. Changes to plugins themselves don't invalidate the cache.
+    pub plugins: Vec<String>,
+    /// Treat tags in the text of pages, like `#rust`, as tags of the page, like Obsidian does.
+    ///
+    /// These get linked to the page for their tag. A tag comes after whitespace, and needs
+    /// something other than numbers, so `#1` isn't one. Tags in links and code are left alone.
+    pub inline_tags: bool,
+    /// Arbitrary values for templates to use, available as `site.params`.
+    pub params: BTreeMap<String, serde_yaml::Value>,
+    /// Check that the HTML generated for each page is well-formed, warning otherwise.
+    ///
+    /// This is mainly useful to catch mistakes in raw HTML inside of markdown.
+    pub validate_html: bool,
+    /// Generate Atom, RSS, and JSON feeds of all pages, at `/atom.xml`, `/rss.xml`,
+    /// and `/feed.json`.
+    ///
+    /// This requires `base_url` to be set.
+    pub feeds: bool,
+    /// Wrap the body of each page in `h-entry` microformats markup.
+    ///
+    /// Combined with the `h_card` template variable, this makes the site IndieWeb compatible.
+    pub microformats: bool,
+    /// Publish a static ActivityPub actor and outbox for the site.
+    ///
+    /// This requires `base_url` to be set.
+    pub activitypub: Option<ActivityPub>,
+    /// Sitemap settings for the pages in particular folders, relative to `content`.
+    ///
+    /// The settings apply to sub-folders as well, with the most specific folder
+    /// taking precedence. Use `""` to set a default for the whole site, e.g.
+    ///
+    /// ```
+    /// sitemap:
+    ///   "": { changefreq: monthly }
+    ///   Posts: { priority: 0.8 }
+    /// ```
+    pub sitemap: HashMap<PathBuf, SitemapSettings>,
+    /// How to number display equations.
+    ///
+    /// Numbered equations can be referenced with `\eqref{label}`, and linked to from
+    /// other pages, using their label as an anchor.
+    pub equation_numbering: EquationNumbering,
+    /// The syntect theme used to highlight code blocks, e.g. `base16-ocean.dark`.
+    ///
+    /// This defaults to `InspiredGitHub`.
+    pub highlight_theme: Option<String>,
+    /// Whether to color code with `inline` styles, or with `classes`.
+    ///
+    /// With classes, the theme gets written to `/highlight.css`, its link available to
+    /// templates as `highlight_css`. A `static/highlight.css` of the site's own replaces
+    /// this stylesheet, with `highlight_css` linking to it instead.
+    pub highlight_style: HighlightStyle,
+    /// Another theme, used instead when the reader prefers a dark color scheme.
+    ///
+    /// This needs `highlight_style: classes`, and gets added to `/highlight.css`, for
+    /// `prefers-color-scheme: dark`.
+    pub highlight_dark_theme: Option<String>,
+    /// Leave out the first `# Heading` of pages using it as their title, since templates
+    /// usually show the title already.
+    ///
+    /// Pages without a title in their front matter are titled after their first `# Heading`,
+    /// and otherwise after their file name.
+    pub strip_title_heading: bool,
+    /// How many levels to demote headings by, e.g. `1` to write `# A` as an `<h2>`.
+    ///
+    /// This helps with notes starting with a `# Title`, when the template already has an
+    /// `<h1>` for the title. Headings past `<h6>` stay at `<h6>`. A page can override
+    /// this with `heading_offset` in its front matter.
+    pub heading_offset: u8,
+    /// Add a `#` link to each heading, pointing to the heading itself.
+    ///
+    /// Headings always get an `id`, slugified from their text, even without this.
+    pub heading_permalinks: bool,
+    /// Render images standing alone in a paragraph as a `<figure>`, with a caption.
+    ///
+    /// The caption is the title of the image, or its alt text, if it has no title.
+    pub figures: bool,
+    /// Render ```` ```dot ```` code blocks to inline SVG, with the `dot` command from Graphviz.
+    ///
+    /// Graphs are left as code blocks if Graphviz isn't installed.
+    pub graphviz: bool,
+    /// Turn straight quotes into curly ones, `--` and `---` into en and em dashes,
+    /// and `...` into an ellipsis, outside of code.
+    pub smart_punctuation: bool,
+    /// Remove anything which could run scripts from raw HTML in markdown, like `<script>`
+    /// or `onclick`, keeping a safe subset of elements and attributes.
+    ///
+    /// Links with URLs like `javascript:` get removed as well.
+    pub sanitize_html: bool,
+    /// How to resolve wikilinks to names shared by several pages, like `notes/Idea.md`,
+    /// and `archive/Idea.md`.
+    ///
+    /// This is `newest`, `shortest`, `folders`, `qualified`, or `error`. Links with a path,
+    /// like `[[archive/Idea]]`, always go to the page at the end of that path.
+    pub duplicate_names: DuplicateNames,
+    /// The folders preferred by `duplicate_names: folders`, from most to least preferred,
+    /// relative to `content`, e.g. `[notes, archive]`.
+    pub preferred_folders: Vec<PathBuf>,
+    /// Fail the build when a wikilink doesn't resolve, instead of just warning.
+    ///
+    /// This can also be enabled with `--strict`.
+    pub strict: bool,
+    /// Fail the build when there are any warnings, like unresolved wikilinks, or broken math.
+    pub warnings_as_errors: bool,
+    /// Empty the output directory before building, so that removed pages don't linger.
+    ///
+    /// This can also be enabled with `--clean`.
+    pub clean_output: bool,
+    /// Also generate archive pages for each month, like `/archive/2023/01/`.
+    ///
+    /// Archive pages for each year are always generated, using `list.html`.
+    pub monthly_archives: bool,
+    /// Write the wikilinks between pages to `/graph.json`, for drawing a graph view.
+    pub graph: bool,
+    /// Write the text of every page to `/search-index.json`, for searching on the client.
+    pub search_index: bool,
+    /// The image shown when sharing pages without an `image` of their own.
+    pub image: Option<String>,
+    /// The Twitter handle of the site, like `@example`.
+    pub twitter: Option<String>,
+    /// Minify the CSS and JS files in `static`, and in bundles.
+    pub minify: bool,
+    /// Files in `static` to join together into one, by the name of the bundle, e.g.
+    ///
+    /// ```
+    /// bundles:
+    ///   css/site.css: [css/reset.css, css/main.css]
+    /// ```
+    ///
+    /// Templates can refer to the bundle with `{{ assets["css/site.css"] }}`.
+    pub bundles: BTreeMap<PathBuf, Vec<PathBuf>>,
+    /// Add a copy of each file in `static` with a hash of its contents in the name.
+    ///
+    /// Templates can refer to these with `{{ asset("style.css") }}`, which gives
+    /// the original path, if this isn't enabled.
+    pub fingerprint: bool,
 }
 
 impl Config {
+    /// Create an absolute URL from a link relative to the root of the site.
+    ///
+    /// Without a `base_url`, this returns the link as is.
+    pub fn url(&self, link: &str) -> String {
+        let base = self.base_url.as_deref().unwrap_or_default();
+        format!("{}{link}", base.trim_end_matches('/'))
+    }
+
+    /// The absolute URL for a link, if the `base_url` is known.
+    pub fn absolute_url(&self, link: &str) -> Option<String> {
+        self.base_url.as_ref().map(|_| self.url(link))
+    }
+
+    /// The sitemap settings for a folder, cascading from the folders containing it.
+    pub fn sitemap_settings(&self, folder: &Path) -> SitemapSettings {
+        folder
+            .ancestors()
+            .filter_map(|ancestor| self.sitemap.get(ancestor))
+            .fold(SitemapSettings::default(), |acc, settings| acc.or(settings))
+    }
+
+    /// The offset of the site's timezone from UTC, if there is one.
+    pub fn utc_offset(&self) -> anyhow::Result<Option<UtcOffset>> {
+        let Some(timezone) = &self.timezone else {
+            return Ok(None);
+        };
+        if matches!(timezone.as_str(), "UTC" | "Z") {
+            return Ok(Some(UtcOffset::UTC));
+        }
+        let format = format_description::parse("[offset_hour sign:mandatory]:[offset_minute]")
+            .expect("valid format");
+        UtcOffset::parse(timezone, &format)
+            .map(Some)
+            .map_err(|_| anyhow!("invalid timezone `{timezone}`, expected an offset like `+02:00`"))
+    }
+
+    /// How to infer the dates of pages missing from their front matter.
+    ///
+    /// This leaves reading the git history, for `date_source: git`, to the caller.
+    pub fn date_settings(&self) -> anyhow::Result<DateSettings<'static>> {
+        Ok(DateSettings {
+            offset: self.utc_offset()?,
+            source: self.date_source,
+            git: None,
+        })
+    }
+
     /// Parse the config from a YAML string.
     pub fn try_from_yaml(yaml: &str) -> anyhow::Result<Self> {
         serde_yaml::from_str(yaml).map_err(|e| anyhow!("failed to parse config: {e}"))
     }
+
+    /// The keys in the YAML of this config which don't mean anything, like `author.nmae`.
+    ///
+    /// Parsing ignores these, which would hide typos otherwise.
+    pub fn unknown_keys(&self, yaml: &str) -> Vec<String> {
+        let (Ok(given), Ok(known)) = (
+            serde_yaml::from_str::<serde_yaml::Value>(yaml),
+            serde_yaml::to_value(self),
+        ) else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        unknown_keys_in(&given, &known, "", &mut out);
+        out
+    }
+}
+
+/// A key of a YAML mapping, as text, since keys like `2024` are numbers, until they become paths.
+fn key_name(key: &serde_yaml::Value) -> Option<String> {
+    match key {
+        serde_yaml::Value::String(x) => Some(x.clone()),
+        serde_yaml::Value::Number(x) => Some(x.to_string()),
+        serde_yaml::Value::Bool(x) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+/// Find the keys of some YAML missing from what it parsed into, once written back as YAML.
+fn unknown_keys_in(
+    given: &serde_yaml::Value,
+    known: &serde_yaml::Value,
+    prefix: &str,
+    out: &mut Vec<String>,
+) {
+    let (serde_yaml::Value::Mapping(given), serde_yaml::Value::Mapping(known)) = (given, known)
+    else {
+        return;
+    };
+    for (key, value) in given {
+        let Some(name) = key_name(key) else {
+            continue;
+        };
+        let path = match prefix {
+            "" => name.clone(),
+            _ => format!("{prefix}.{name}"),
+        };
+        match known
+            .iter()
+            .find(|(k, _)| key_name(k).as_ref() == Some(&name))
+        {
+            Some((_, known)) => unknown_keys_in(value, known, &path, out),
+            None => out.push(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        let mut config = Config::default();
+        assert_eq!(config.url("/a.html"), "/a.html");
+        assert_eq!(config.absolute_url("/a.html"), None);
+        config.base_url = Some("https://example.com/".into());
+        assert_eq!(
+            config.absolute_url("/a.html").as_deref(),
+            Some("https://example.com/a.html")
+        );
+    }
+
+    #[test]
+    fn timezones() {
+        let mut config = Config::default();
+        assert_eq!(config.utc_offset().unwrap(), None);
+        config.timezone = Some("-05:30".into());
+        assert_eq!(
+            config.utc_offset().unwrap(),
+            Some(UtcOffset::from_hms(-5, -30, 0).unwrap())
+        );
+        config.timezone = Some("UTC".into());
+        assert_eq!(config.utc_offset().unwrap(), Some(UtcOffset::UTC));
+        config.timezone = Some("Europe/Paris".into());
+        assert!(config.utc_offset().is_err());
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let yaml = "ignored_folder: [Drafts]\nauthor: { name: Me, emial: a@b.c }\nparams: { anything: 1 }\nsitemap:\n  2024: { priority: 0.5 }\n";
+        let config = Config::try_from_yaml(yaml).unwrap();
+        assert_eq!(
+            config.unknown_keys(yaml),
+            vec!["ignored_folder", "author.emial"]
+        );
+        assert!(config.unknown_keys("title: Blog\n").is_empty());
+    }
+
+    #[test]
+    fn sitemap_cascade() {
+        let config = Config::try_from_yaml(
+            "sitemap:\n  \"\": { changefreq: monthly, priority: 0.5 }\n  Posts: { priority: 0.8 }\n  Posts/Old: { changefreq: never }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.sitemap_settings(Path::new("Posts/Old")),
+            SitemapSettings {
+                priority: Some(0.8),
+                changefreq: Some("never".into())
+            }
+        );
+        assert_eq!(
+            config.sitemap_settings(Path::new("Other")),
+            SitemapSettings {
+                priority: Some(0.5),
+                changefreq: Some("monthly".into())
+            }
+        );
+    }
 }
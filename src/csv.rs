@@ -0,0 +1,86 @@
+use crate::escape::escape_html;
+
+/// Parse CSV data into rows of fields.
+///
+/// This follows RFC 4180: fields may be quoted, in which case they can contain
+/// commas, newlines, and quotes, which are escaped by doubling them.
+pub fn parse(data: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (in_quotes, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => in_quotes = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => in_quotes = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Render CSV data as an HTML table, using the first row as the header.
+pub fn to_table(data: &str) -> String {
+    let rows = parse(data);
+    let mut out = String::with_capacity(data.len() * 2);
+    out.push_str("\n<table>");
+    let mut rows = rows.iter();
+    if let Some(header) = rows.next() {
+        out.push_str("\n<thead>\n<tr>");
+        for field in header {
+            out.push_str(&format!("<th>{}</th>", escape_html(field)));
+        }
+        out.push_str("</tr>\n</thead>");
+    }
+    out.push_str("\n<tbody>");
+    for row in rows {
+        out.push_str("\n<tr>");
+        for field in row {
+            out.push_str(&format!("<td>{}</td>", escape_html(field)));
+        }
+        out.push_str("</tr>");
+    }
+    out.push_str("\n</tbody>\n</table>");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_quoted() {
+        assert_eq!(
+            parse("a,b,c\n1,\"two, \"\"2\"\"\",3\r\n\"multi\nline\",,x"),
+            vec![
+                vec!["a", "b", "c"],
+                vec!["1", "two, \"2\"", "3"],
+                vec!["multi\nline", "", "x"],
+            ]
+        );
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            to_table("name,score\nA&B,<1>\n"),
+            "\n<table>\n<thead>\n<tr><th>name</th><th>score</th></tr>\n</thead>\n<tbody>\n<tr><td>A&amp;B</td><td>&lt;1&gt;</td></tr>\n</tbody>\n</table>"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A place in a file, with lines and columns starting from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// The location of a byte in some text.
+    pub fn of(text: &str, offset: usize) -> Self {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// Show the line of some source a problem is on, underlining `len` characters of it.
+///
+/// The source can start partway through a file, at `first_line`, like front matter does.
+pub fn snippet(source: &str, first_line: usize, location: Location, len: usize) -> Option<String> {
+    let text = source.lines().nth(location.line.checked_sub(first_line)?)?;
+    let number = location.line.to_string();
+    let gutter = " ".repeat(number.len());
+    // Tabs are kept, so that the underline lines up, however wide they are.
+    let indent = text
+        .chars()
+        .take(location.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let rest = text.chars().count().saturating_sub(location.column - 1);
+    let underline = "^".repeat(len.min(rest).max(1));
+    Some(format!(
+        "{gutter} |\n{number} | {text}\n{gutter} | {indent}{underline}"
+    ))
+}
+
+/// A problem with a file, along with where it is in the file, when that's known.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub location: Option<Location>,
+    /// The line the problem is on, with the problem underlined.
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            location: None,
+            snippet: None,
+        }
+    }
+
+    /// A problem spanning `len` characters from some byte of the source of a file.
+    pub fn at(message: impl Into<String>, source: &str, offset: usize, len: usize) -> Self {
+        let location = Location::of(source, offset);
+        Self {
+            message: message.into(),
+            location: Some(location),
+            snippet: snippet(source, 1, location, len),
+        }
+    }
+
+    /// Show the source around this problem, if it has a location, but no snippet yet.
+    pub fn with_source(mut self, source: &str) -> Self {
+        if let Some(location) = self.location
+            && self.snippet.is_none()
+        {
+            self.snippet = snippet(source, 1, location, 1);
+        }
+        self
+    }
+
+    /// Show this problem, after the file it's in, like `a.md:3:5: message`, with its snippet.
+    pub fn show_in(&self, path: &Path) -> String {
+        let mut out = match self.location {
+            Some(Location { line, column }) => {
+                format!("{}:{line}:{column}: {}", path.display(), self.message)
+            }
+            None => format!("{}: {}", path.display(), self.message),
+        };
+        if let Some(snippet) = &self.snippet {
+            out.push('\n');
+            out.push_str(snippet);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(Location { line, column }) => {
+                write!(f, "{}, at line {line}, column {column}", self.message)
+            }
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for Diagnostic {}
+
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+/// Errors which are already diagnostics keep their location, while others only have a message.
+impl From<anyhow::Error> for Diagnostic {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<Diagnostic>() {
+            Ok(diagnostic) => diagnostic,
+            Err(e) => Self::new(format!("{e}")),
+        }
+    }
+}
+
+/// The warnings of a whole build, shown together at the end, rather than between other output.
+#[derive(Debug, Default)]
+pub struct Warnings {
+    /// Each warning, along with the file it's about, if there is one.
+    warnings: Vec<(Option<PathBuf>, Diagnostic)>,
+}
+
+impl Warnings {
+    /// Add a warning about a file.
+    pub fn push(&mut self, path: &Path, diagnostic: impl Into<Diagnostic>) {
+        self.warnings
+            .push((Some(path.to_path_buf()), diagnostic.into()));
+    }
+
+    /// Add a warning about the site as a whole.
+    pub fn push_site(&mut self, diagnostic: impl Into<Diagnostic>) {
+        self.warnings.push((None, diagnostic.into()));
+    }
+
+    /// Each warning, along with the file it's about, if there is one.
+    pub fn iter(&self) -> impl Iterator<Item = (Option<&Path>, &Diagnostic)> {
+        self.warnings.iter().map(|(path, x)| (path.as_deref(), x))
+    }
+
+    pub fn len(&self) -> usize {
+        self.warnings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+/// Every warning, ordered by file, then by where it is, followed by how many there were.
+impl fmt::Display for Warnings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sorted = self.warnings.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|(path, x)| (path, x.location.map(|x| (x.line, x.column))));
+        for (path, diagnostic) in &sorted {
+            match path {
+                Some(path) => writeln!(f, "WARN: {}", diagnostic.show_in(path))?,
+                None => writeln!(f, "WARN: {}", diagnostic.message)?,
+            }
+        }
+        let mut files = sorted
+            .iter()
+            .filter_map(|(path, _)| path.as_ref())
+            .collect::<Vec<_>>();
+        files.dedup();
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        writeln!(
+            f,
+            "{} warning{}, in {} file{}",
+            self.len(),
+            plural(self.len()),
+            files.len(),
+            plural(files.len())
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn snippets() {
+        let source = "---\ntitle: A\n---\nSee\n\t[[Nope]] here\n";
+        let offset = source.find("[[").unwrap();
+        let diagnostic = Diagnostic::at("unresolved wikilink", source, offset, 8);
+        assert_eq!(diagnostic.location, Some(Location { line: 5, column: 2 }));
+        assert_eq!(
+            diagnostic.show_in(Path::new("a.md")),
+            "a.md:5:2: unresolved wikilink\n  |\n5 | \t[[Nope]] here\n  | \t^^^^^^^^"
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            "unresolved wikilink, at line 5, column 2"
+        );
+        // Underlines stop at the end of the line.
+        let location = Location { line: 2, column: 7 };
+        assert_eq!(
+            snippet("title: [a]\n", 2, location, 100).as_deref(),
+            Some("  |\n2 | title: [a]\n  |       ^^^^")
+        );
+        assert_eq!(snippet("a\n", 1, Location { line: 3, column: 1 }, 1), None);
+    }
+
+    #[test]
+    fn summary() {
+        let mut warnings = Warnings::default();
+        warnings.push(Path::new("b.md"), "second".to_string());
+        warnings.push_site("site".to_string());
+        warnings.push(Path::new("a.md"), "first".to_string());
+        warnings.push(Path::new("b.md"), "third".to_string());
+        assert_eq!(
+            warnings.to_string(),
+            "WARN: site\nWARN: a.md: first\nWARN: b.md: second\nWARN: b.md: third\n4 warnings, in 2 files\n"
+        );
+    }
+}
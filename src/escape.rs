@@ -0,0 +1,165 @@
+use std::borrow::Cow;
+
+/// Escape the characters with special meaning in HTML text.
+///
+/// This avoids allocating when there's nothing to escape, which is the common case.
+pub fn escape_html(s: &str) -> Cow<'_, str> {
+    if !s.contains(['<', '>', '&']) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len() + 16);
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Escape a string for use inside of a quoted attribute value.
+///
+/// This is also suitable for XML, both in text and attributes.
+pub fn escape_attr(s: &str) -> Cow<'_, str> {
+    if !s.contains(['<', '>', '&', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len() + 16);
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Whether a byte can be part of a segment of a URL path as is.
+fn is_path_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&b)
+}
+
+/// Percent-encode one segment of a URL path, like a file name, so that `#`, `?`, `%`,
+/// `/`, spaces, and non-ASCII characters don't change what the URL points to.
+pub fn escape_path_segment(s: &str) -> Cow<'_, str> {
+    if s.bytes().all(is_path_byte) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len() + 16);
+    for b in s.bytes() {
+        if is_path_byte(b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// The characters of the named references kept by `decode_entities`.
+const NAMED_REFERENCES: [(&str, char); 7] = [
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("colon", ':'),
+];
+
+/// The character a reference like `&amp;`, `&#58;`, or `&#x3a;` stands for.
+fn decode_reference(name: &str) -> Option<char> {
+    let code = match name.strip_prefix('#') {
+        None => {
+            return NAMED_REFERENCES
+                .iter()
+                .find(|(x, _)| *x == name)
+                .map(|x| x.1);
+        }
+        Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok()?,
+        Some(decimal) => decimal.parse().ok()?,
+    };
+    Some(
+        char::from_u32(code)
+            .filter(|&c| c != '\0')
+            .unwrap_or('\u{fffd}'),
+    )
+}
+
+/// Replace the character references in HTML, like in attribute values, with their characters.
+///
+/// Only numeric references, and the most common named ones, get decoded. Others are kept as is.
+pub fn decode_entities(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 32)
+            .and_then(|end| Some((decode_reference(&rest[1..end + 1])?, end + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(escape_html("Vec<String>"), "Vec&lt;String&gt;");
+        assert_eq!(escape_html("a && b"), "a &amp;&amp; b");
+        assert_eq!(escape_html("&lt;"), "&amp;lt;");
+    }
+
+    #[test]
+    fn escapes_attributes() {
+        assert_eq!(
+            escape_attr("say \"hi\" & 'bye'"),
+            "say &quot;hi&quot; &amp; &#39;bye&#39;"
+        );
+    }
+
+    #[test]
+    fn escapes_path_segments() {
+        assert_eq!(escape_path_segment("a b#1?%.png"), "a%20b%231%3F%25.png");
+        assert_eq!(escape_path_segment("café/x"), "caf%C3%A9%2Fx");
+        assert!(matches!(escape_path_segment("a-b_c.png"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(decode_entities("a &amp; b &lt;&#x41;&#66;"), "a & b <AB");
+        assert_eq!(decode_entities("javascript&colon;x"), "javascript:x");
+        assert_eq!(decode_entities("&copy; & &#0; &;"), "&copy; & \u{fffd} &;");
+        assert!(matches!(decode_entities("plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn borrows_when_unchanged() {
+        assert!(matches!(escape_html("plain text"), Cow::Borrowed(_)));
+        assert!(matches!(escape_attr("plain text"), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,456 @@
+use anyhow::anyhow;
+use serde_json::json;
+use std::fmt::Write as _;
+use time::{Date, Month, OffsetDateTime, UtcOffset, format_description::well_known::Rfc2822};
+
+use crate::{config::Author, escape::escape_attr};
+
+/// Wrap a string in a CDATA section.
+///
+/// Any `]]>` in the string would end the section early, so we split the section around it.
+fn cdata(s: &str) -> String {
+    format!("<![CDATA[{}]]>", s.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Parse a `YYYY-MM-DD` date, as produced by our front matter.
+fn parse_date(date: &str) -> anyhow::Result<Date> {
+    let bad_date = || anyhow!("invalid date `{date}`");
+    let mut parts = date.splitn(3, '-');
+    let mut next = || -> anyhow::Result<&str> { parts.next().ok_or_else(bad_date) };
+    let year: i32 = next()?.parse()?;
+    let month: u8 = next()?.parse()?;
+    let day: u8 = next()?.parse()?;
+    Ok(Date::from_calendar_date(
+        year,
+        Month::try_from(month)?,
+        day,
+    )?)
+}
+
+/// The end of an RFC 3339 timestamp in a timezone, like `Z`, or `+02:00`.
+pub fn offset_suffix(offset: UtcOffset) -> String {
+    if offset.is_utc() {
+        return "Z".into();
+    }
+    let (hours, minutes, _) = offset.as_hms();
+    let sign = if offset.is_negative() { '-' } else { '+' };
+    format!("{sign}{:02}:{:02}", hours.abs(), minutes.abs())
+}
+
+/// Format a date as an RFC 3339 timestamp, as required by Atom.
+fn rfc3339(date: &str, offset: UtcOffset) -> anyhow::Result<String> {
+    let date = parse_date(date)?;
+    Ok(format!("{date}T00:00:00{}", offset_suffix(offset)))
+}
+
+/// Format a date as an RFC 2822 timestamp, as required by RSS.
+fn rfc2822(date: &str, offset: UtcOffset) -> anyhow::Result<String> {
+    let dt = OffsetDateTime::new_in_offset(parse_date(date)?, time::Time::MIDNIGHT, offset);
+    Ok(dt.format(&Rfc2822)?)
+}
+
+/// A single entry in a feed, usually one page.
+pub struct Entry<'a> {
+    pub title: &'a str,
+    /// The link to the page, relative to the base URL of the site.
+    pub link: &'a str,
+    pub published: &'a str,
+    pub updated: &'a str,
+    pub authors: &'a [String],
+    /// A short description of the page, written by hand.
+    pub summary: Option<&'a str>,
+    /// The link to the cover image of the page, which can also be a URL.
+    pub cover: Option<&'a str>,
+    /// The rendered HTML content of the page.
+    pub content: &'a str,
+}
+
+/// A feed of pages, which can be written in multiple formats.
+pub struct Feed<'a> {
+    pub title: &'a str,
+    /// A short description of the site, which RSS requires, falling back to the title.
+    pub description: Option<&'a str>,
+    pub base_url: &'a str,
+    /// The author of the site, used for entries without their own authors.
+    pub author: Option<&'a Author>,
+    pub language: Option<&'a str>,
+    /// The timezone of the site, since entries only have a date.
+    pub offset: UtcOffset,
+    /// The location of the feed itself, relative to the base URL, e.g. `/atom.xml`.
+    pub path: &'a str,
+    /// The entries in this feed, with the most recent first.
+    pub entries: Vec<Entry<'a>>,
+}
+
+impl<'a> Feed<'a> {
+    /// The URL for a link, which can already be one, e.g. for covers hosted elsewhere.
+    fn url(&self, link: &str) -> String {
+        if link.starts_with("https://") || link.starts_with("http://") {
+            return link.to_string();
+        }
+        format!("{}{}", self.base_url.trim_end_matches('/'), link)
+    }
+
+    /// The authors of an entry, falling back to the site's author.
+    fn entry_authors<'e>(&'e self, entry: &'e Entry<'_>) -> impl Iterator<Item = &'e str> {
+        let fallback = self
+            .author
+            .filter(|_| entry.authors.is_empty())
+            .map(|author| author.name.as_str());
+        entry.authors.iter().map(String::as_str).chain(fallback)
+    }
+
+    /// The most recent update of any entry in this feed.
+    fn updated(&self) -> &'a str {
+        self.entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or("1970-01-01")
+    }
+
+    /// Write this feed in the Atom format.
+    ///
+    /// Entry ids are the canonical URL of each page, which stays stable across builds.
+    ///
+    /// Entries without authors inherit the author of the feed, as per the spec.
+    /// Without an author for the site, the feed is attributed to its title,
+    /// since every entry needs an author, one way or another.
+    pub fn to_atom(&self) -> anyhow::Result<String> {
+        let mut out = String::with_capacity(1 << 14);
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        match self.language {
+            Some(language) => writeln!(
+                out,
+                "<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">",
+                escape_attr(language)
+            )?,
+            None => writeln!(out, "<feed xmlns=\"http://www.w3.org/2005/Atom\">")?,
+        }
+        writeln!(out, "<title>{}</title>", escape_attr(self.title))?;
+        match self.author {
+            Some(author) => {
+                write!(out, "<author><name>{}</name>", escape_attr(&author.name))?;
+                if let Some(email) = &author.email {
+                    write!(out, "<email>{}</email>", escape_attr(email))?;
+                }
+                writeln!(out, "</author>")?;
+            }
+            None => writeln!(
+                out,
+                "<author><name>{}</name></author>",
+                escape_attr(self.title)
+            )?,
+        }
+        writeln!(out, "<id>{}</id>", escape_attr(&self.url("/")))?;
+        writeln!(
+            out,
+            "<link rel=\"self\" href=\"{}\"/>",
+            escape_attr(&self.url(self.path))
+        )?;
+        writeln!(
+            out,
+            "<link rel=\"alternate\" href=\"{}\"/>",
+            escape_attr(&self.url("/"))
+        )?;
+        writeln!(
+            out,
+            "<updated>{}</updated>",
+            rfc3339(self.updated(), self.offset)?
+        )?;
+        for entry in &self.entries {
+            let url = escape_attr(&self.url(entry.link)).into_owned();
+            writeln!(out, "<entry>")?;
+            writeln!(out, "<title>{}</title>", escape_attr(entry.title))?;
+            writeln!(out, "<id>{url}</id>")?;
+            writeln!(out, "<link rel=\"alternate\" href=\"{url}\"/>")?;
+            if let Some(cover) = entry.cover {
+                writeln!(
+                    out,
+                    "<link rel=\"enclosure\" href=\"{}\"/>",
+                    escape_attr(&self.url(cover))
+                )?;
+            }
+            writeln!(
+                out,
+                "<published>{}</published>",
+                rfc3339(entry.published, self.offset)?
+            )?;
+            writeln!(
+                out,
+                "<updated>{}</updated>",
+                rfc3339(entry.updated, self.offset)?
+            )?;
+            for author in entry.authors {
+                writeln!(out, "<author><name>{}</name></author>", escape_attr(author))?;
+            }
+            if let Some(summary) = entry.summary {
+                writeln!(out, "<summary>{}</summary>", escape_attr(summary))?;
+            }
+            writeln!(
+                out,
+                "<content type=\"html\">{}</content>",
+                escape_attr(entry.content)
+            )?;
+            writeln!(out, "</entry>")?;
+        }
+        writeln!(out, "</feed>")?;
+        Ok(out)
+    }
+
+    /// Write this feed in the RSS 2.0 format.
+    pub fn to_rss(&self) -> anyhow::Result<String> {
+        let mut out = String::with_capacity(1 << 14);
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        writeln!(
+            out,
+            "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">"
+        )?;
+        writeln!(out, "<channel>")?;
+        writeln!(out, "<title>{}</title>", escape_attr(self.title))?;
+        writeln!(out, "<link>{}</link>", escape_attr(&self.url("/")))?;
+        writeln!(
+            out,
+            "<description>{}</description>",
+            escape_attr(self.description.unwrap_or(self.title))
+        )?;
+        if let Some(language) = self.language {
+            writeln!(out, "<language>{}</language>", escape_attr(language))?;
+        }
+        if let Some(Author {
+            name,
+            email: Some(email),
+        }) = self.author
+        {
+            writeln!(
+                out,
+                "<managingEditor>{} ({})</managingEditor>",
+                escape_attr(email),
+                escape_attr(name)
+            )?;
+        }
+        writeln!(
+            out,
+            "<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>",
+            escape_attr(&self.url(self.path))
+        )?;
+        writeln!(
+            out,
+            "<lastBuildDate>{}</lastBuildDate>",
+            rfc2822(self.updated(), self.offset)?
+        )?;
+        for entry in &self.entries {
+            let url = escape_attr(&self.url(entry.link)).into_owned();
+            writeln!(out, "<item>")?;
+            writeln!(out, "<title>{}</title>", escape_attr(entry.title))?;
+            writeln!(out, "<link>{url}</link>")?;
+            writeln!(out, "<guid isPermaLink=\"true\">{url}</guid>")?;
+            writeln!(
+                out,
+                "<pubDate>{}</pubDate>",
+                rfc2822(entry.published, self.offset)?
+            )?;
+            for author in self.entry_authors(entry) {
+                writeln!(out, "<dc:creator>{}</dc:creator>", escape_attr(author))?;
+            }
+            writeln!(out, "<description>{}</description>", cdata(entry.content))?;
+            writeln!(out, "</item>")?;
+        }
+        writeln!(out, "</channel>")?;
+        writeln!(out, "</rss>")?;
+        Ok(out)
+    }
+
+    /// Write this feed in the JSON Feed 1.1 format.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        let items = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let url = self.url(entry.link);
+                let mut item = json!({
+                    "id": url,
+                    "url": url,
+                    "title": entry.title,
+                    "content_html": entry.content,
+                    "date_published": rfc3339(entry.published, self.offset)?,
+                    "date_modified": rfc3339(entry.updated, self.offset)?,
+                    "authors": self
+                        .entry_authors(entry)
+                        .map(|name| json!({ "name": name }))
+                        .collect::<Vec<_>>(),
+                });
+                if let Some(summary) = entry.summary {
+                    item["summary"] = json!(summary);
+                }
+                if let Some(cover) = entry.cover {
+                    item["image"] = json!(self.url(cover));
+                }
+                Ok(item)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut feed = json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.title,
+            "home_page_url": self.url("/"),
+            "feed_url": self.url(self.path),
+            "items": items,
+        });
+        if let Some(language) = self.language {
+            feed["language"] = json!(language);
+        }
+        if let Some(author) = self.author {
+            feed["authors"] = json!([{ "name": author.name }]);
+        }
+        Ok(serde_json::to_string_pretty(&feed)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed<'a>(authors: &'a [String], author: Option<&'a Author>) -> Feed<'a> {
+        Feed {
+            title: "Blog & Co",
+            description: None,
+            base_url: "https://example.com/",
+            author,
+            language: Some("en"),
+            offset: UtcOffset::UTC,
+            path: "/atom.xml",
+            entries: vec![
+                Entry {
+                    title: "Second <Post>",
+                    link: "/posts/second.html",
+                    published: "2024-01-10",
+                    updated: "2024-02-01",
+                    authors,
+                    summary: Some("A <second> post"),
+                    cover: Some("/posts/cover.png"),
+                    content: "<p>a ]]> b</p>",
+                },
+                Entry {
+                    title: "First",
+                    link: "/posts/first.html",
+                    published: "2023-12-25",
+                    updated: "2023-12-25",
+                    authors: &[],
+                    summary: None,
+                    cover: Some("https://cdn.example.com/first.png"),
+                    content: "<p>first</p>",
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn atom_entries() {
+        let authors = vec!["Alice".to_string()];
+        let atom = feed(&authors, None).to_atom().unwrap();
+        assert!(atom.contains("<title>Blog &amp; Co</title>"));
+        assert!(atom.contains("<id>https://example.com/</id>"));
+        assert!(atom.contains("<link rel=\"self\" href=\"https://example.com/atom.xml\"/>"));
+        assert!(atom.contains("<updated>2024-02-01T00:00:00Z</updated>\n<entry>"));
+        assert!(atom.contains("<title>Second &lt;Post&gt;</title>"));
+        assert!(atom.contains("<id>https://example.com/posts/second.html</id>"));
+        assert!(atom.contains("<published>2024-01-10T00:00:00Z</published>"));
+        assert!(atom.contains("<author><name>Alice</name></author>"));
+        assert!(atom.contains("<summary>A &lt;second&gt; post</summary>"));
+        assert_eq!(atom.matches("<summary>").count(), 1);
+        assert!(
+            atom.contains("<link rel=\"enclosure\" href=\"https://example.com/posts/cover.png\"/>")
+        );
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;a ]]&gt; b&lt;/p&gt;</content>"));
+        assert_eq!(atom.matches("<entry>").count(), 2);
+    }
+
+    #[test]
+    fn atom_without_authors() {
+        let atom = feed(&[], None).to_atom().unwrap();
+        assert!(
+            atom.contains(
+                "<title>Blog &amp; Co</title>\n<author><name>Blog &amp; Co</name></author>"
+            )
+        );
+        assert_eq!(atom.matches("<author>").count(), 1);
+    }
+
+    #[test]
+    fn rss_items() {
+        let rss = feed(&[], None).to_rss().unwrap();
+        assert!(rss.contains("<description>Blog &amp; Co</description>"));
+        assert!(
+            rss.contains("<guid isPermaLink=\"true\">https://example.com/posts/second.html</guid>")
+        );
+        assert!(rss.contains("<pubDate>Wed, 10 Jan 2024 00:00:00 +0000</pubDate>"));
+        assert!(rss.contains("<lastBuildDate>Thu, 01 Feb 2024 00:00:00 +0000</lastBuildDate>"));
+        assert!(rss.contains("<description><![CDATA[<p>a ]]]]><![CDATA[> b</p>]]></description>"));
+    }
+
+    #[test]
+    fn site_metadata() {
+        let authors = vec!["Alice".to_string()];
+        let site_author = Author {
+            name: "Bob".into(),
+            email: Some("bob@example.com".into()),
+        };
+        let feed = Feed {
+            description: Some("Notes & thoughts"),
+            ..feed(&authors, Some(&site_author))
+        };
+        let atom = feed.to_atom().unwrap();
+        assert!(atom.contains("xml:lang=\"en\""));
+        assert!(
+            atom.contains("<author><name>Bob</name><email>bob@example.com</email></author>\n<id>")
+        );
+        let rss = feed.to_rss().unwrap();
+        assert!(rss.contains("<description>Notes &amp; thoughts</description>"));
+        assert!(rss.contains("<language>en</language>"));
+        assert!(rss.contains("<managingEditor>bob@example.com (Bob)</managingEditor>"));
+        assert!(rss.contains("<dc:creator>Alice</dc:creator>"));
+        assert!(rss.contains("<dc:creator>Bob</dc:creator>"));
+        assert!(!rss.contains("<dc:creator>Bob</dc:creator>\n<dc:creator>Alice"));
+    }
+
+    #[test]
+    fn json_feed() {
+        let authors = vec!["Alice".to_string()];
+        let site_author = Author {
+            name: "Bob".into(),
+            email: None,
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&feed(&authors, Some(&site_author)).to_json().unwrap()).unwrap();
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(json["language"], "en");
+        assert_eq!(json["authors"][0]["name"], "Bob");
+        let items = json["items"].as_array().unwrap();
+        assert_eq!(items[0]["id"], "https://example.com/posts/second.html");
+        assert_eq!(items[0]["date_modified"], "2024-02-01T00:00:00Z");
+        assert_eq!(items[0]["authors"][0]["name"], "Alice");
+        assert_eq!(items[1]["authors"][0]["name"], "Bob");
+        assert_eq!(items[0]["summary"], "A <second> post");
+        assert!(items[1].get("summary").is_none());
+        assert_eq!(items[1]["image"], "https://cdn.example.com/first.png");
+    }
+
+    #[test]
+    fn rejects_bad_dates() {
+        assert!(rfc3339("2024-13-01", UtcOffset::UTC).is_err());
+        assert!(rfc3339("yesterday", UtcOffset::UTC).is_err());
+        assert_eq!(
+            rfc3339("2024-01-05", UtcOffset::UTC).unwrap(),
+            "2024-01-05T00:00:00Z"
+        );
+        let offset = UtcOffset::from_hms(-5, -30, 0).unwrap();
+        assert_eq!(
+            rfc3339("2024-01-05", offset).unwrap(),
+            "2024-01-05T00:00:00-05:30"
+        );
+        assert_eq!(
+            rfc2822("2024-01-05", offset).unwrap(),
+            "Fri, 05 Jan 2024 00:00:00 -0530"
+        );
+    }
+}
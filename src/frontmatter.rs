@@ -1,21 +1,175 @@
 use anyhow::anyhow;
 use regex::Regex;
-use serde::{Deserialize, Deserializer, Serialize};
-use std::{path::Path, sync::LazyLock, time::SystemTime};
-use time::{OffsetDateTime, UtcOffset, format_description::well_known::Iso8601};
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::LazyLock,
+    time::SystemTime,
+};
+use time::{
+    OffsetDateTime, UtcOffset,
+    format_description::well_known::{Iso8601, Rfc3339},
+};
 
-fn systemtime_to_date_str(t: SystemTime) -> anyhow::Result<String> {
-    let dt = OffsetDateTime::from(t).to_offset(UtcOffset::UTC);
+use crate::{
+    config::{DateSource, SitemapSettings},
+    diagnostic::{Diagnostic, Location, snippet},
+};
+
+/// The source of the front matter of a page, in one of the supported formats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source<'a> {
+    /// YAML, delimited by `---`.
+    Yaml(&'a str),
+    /// TOML, delimited by `+++`, as used by Hugo.
+    Toml(&'a str),
+}
+
+/// Convert TOML into YAML, so that both formats go through the same deserialization.
+///
+/// TOML dates become strings, like they would be in YAML.
+fn toml_to_yaml(value: toml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value as Y;
+    use toml::Value as T;
+    match value {
+        T::String(x) => Y::String(x),
+        T::Integer(x) => Y::Number(x.into()),
+        T::Float(x) => Y::Number(x.into()),
+        T::Boolean(x) => Y::Bool(x),
+        T::Datetime(x) => Y::String(x.to_string()),
+        T::Array(xs) => Y::Sequence(xs.into_iter().map(toml_to_yaml).collect()),
+        T::Table(table) => Y::Mapping(
+            table
+                .into_iter()
+                .map(|(k, v)| (Y::String(k), toml_to_yaml(v)))
+                .collect(),
+        ),
+    }
+}
+
+fn systemtime_to_date_str(t: SystemTime, offset: Option<UtcOffset>) -> anyhow::Result<String> {
+    let dt = OffsetDateTime::from(t).to_offset(offset.unwrap_or(UtcOffset::UTC));
     Ok(dt.format(&Iso8601::DATE)?)
 }
 
-fn mtime_date(path: &Path) -> anyhow::Result<String> {
+/// Today's date, in the given timezone, or UTC, like `2024-01-31`.
+pub fn today(offset: Option<UtcOffset>) -> anyhow::Result<String> {
+    systemtime_to_date_str(SystemTime::now(), offset)
+}
+
+fn mtime_date(path: &Path, offset: Option<UtcOffset>) -> anyhow::Result<String> {
     let meta = std::fs::metadata(path)?;
     let mtime = meta.modified()?;
-    let date = systemtime_to_date_str(mtime)?;
+    let date = systemtime_to_date_str(mtime, offset)?;
     Ok(date)
 }
 
+/// How to fill in the dates of a page, when they're missing from its front matter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DateSettings<'a> {
+    /// The timezone of the site, or UTC, if there isn't one.
+    pub offset: Option<UtcOffset>,
+    pub source: DateSource,
+    /// The history to take dates from with `date_source: git`, read once for every page.
+    pub git: Option<&'a GitHistory>,
+}
+
+/// The dates of the last and first commits changing each file in a folder, following renames.
+#[derive(Debug, Default)]
+pub struct GitHistory {
+    dates: HashMap<PathBuf, (String, String)>,
+    /// Problems reading the history, like git failing to run.
+    pub warnings: Vec<String>,
+}
+
+impl GitHistory {
+    /// Read the history of a folder, in a single run of `git log`, with dates in a timezone.
+    ///
+    /// This is empty if git isn't installed, or the folder isn't in a repository.
+    pub fn load(dir: &Path, offset: Option<UtcOffset>) -> Self {
+        let mut out = Self::default();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(["-c", "core.quotePath=false"])
+                .args(args)
+                .current_dir(dir)
+                .output()
+        };
+        let shallow = match git(&["rev-parse", "--is-shallow-repository"]) {
+            Ok(output) if output.status.success() => output.stdout.starts_with(b"true"),
+            Ok(_) => return out,
+            Err(e) => {
+                let warning = format!("failed to run git, using modification times for dates: {e}");
+                out.warnings.push(warning);
+                return out;
+            }
+        };
+        if shallow {
+            out.warnings.push(
+                "the git repository is a shallow clone, so pages may get dates which are too recent"
+                    .to_string(),
+            );
+        }
+        let log = [
+            "log",
+            "--format=%x00%cI",
+            "--name-status",
+            "-M",
+            "--relative",
+        ];
+        let Ok(output) = git(&log) else {
+            return out;
+        };
+        let offset = offset.unwrap_or(UtcOffset::UTC);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // Commits come from the newest to the oldest, so renames apply to the ones after them.
+        let mut renamed = HashMap::<&str, &str>::new();
+        let mut date = None;
+        for line in stdout.lines() {
+            if let Some(timestamp) = line.strip_prefix('\0') {
+                date = date_in_offset(timestamp, offset);
+                continue;
+            }
+            let Some(date) = &date else {
+                continue;
+            };
+            let mut parts = line.split('\t');
+            let (Some(status), Some(path)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let path = match parts.next() {
+                Some(new) if status.starts_with('R') => {
+                    let current = renamed.get(new).copied().unwrap_or(new);
+                    renamed.insert(path, current);
+                    current
+                }
+                // Copies leave the original file alone.
+                Some(new) => renamed.get(new).copied().unwrap_or(new),
+                None => renamed.get(path).copied().unwrap_or(path),
+            };
+            let dates = out.dates.entry(dir.join(path));
+            dates.or_insert_with(|| (date.clone(), date.clone())).1 = date.clone();
+        }
+        out
+    }
+
+    /// The dates of the last and first commits changing a file, if it was ever committed.
+    fn dates(&self, path: &Path) -> Option<(String, String)> {
+        self.dates.get(path).cloned()
+    }
+}
+
+/// The date of a timestamp like `2024-01-01T23:30:00-05:00`, in another timezone.
+///
+/// This returns `None` for plain dates, which are already in the timezone of the site.
+fn date_in_offset(s: &str, offset: UtcOffset) -> Option<String> {
+    let dt = OffsetDateTime::parse(s, &Rfc3339).ok()?;
+    dt.to_offset(offset).date().format(&Iso8601::DATE).ok()
+}
+
 fn date_prefix(s: &str) -> Option<&str> {
     // Matches YYYY-MM-DD at the start of the string
     // \d{4}-\d{2}-\d{2}
@@ -36,7 +190,9 @@ where
     D: Deserializer<'de>,
 {
     // First try to deserialize as Option<...>
-    let opt = Option::<StringOrVec>::deserialize(deserializer)?;
+    // Untagged enums fail with a message about the enum, which means nothing to users.
+    let opt = Option::<StringOrVec>::deserialize(deserializer)
+        .map_err(|_| D::Error::custom("expected a string, or a list of strings"))?;
 
     Ok(opt.map(|v| match v {
         StringOrVec::One(s) => vec![s],
@@ -44,6 +200,27 @@ where
     }))
 }
 
+/// Allow flags like `draft` to be written `true`, as TOML does, or `"true"`.
+fn opt_bool_or_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let opt = Option::<BoolOrString>::deserialize(deserializer)
+        .map_err(|_| D::Error::custom("expected true or false"))?;
+
+    Ok(opt.map(|v| match v {
+        BoolOrString::Bool(b) => b.to_string(),
+        BoolOrString::String(s) => s,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BoolOrString {
+    Bool(bool),
+    String(String),
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum StringOrVec {
@@ -60,24 +237,58 @@ struct Raw {
     published: Option<String>,
     #[serde(default, deserialize_with = "opt_string_or_vec")]
     authors: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "opt_bool_or_string")]
     draft: Option<String>,
+    #[serde(default, deserialize_with = "opt_bool_or_string")]
+    unlisted: Option<String>,
     link: Option<String>,
     #[serde(default, deserialize_with = "opt_string_or_vec")]
     tags: Option<Vec<String>>,
+    priority: Option<f64>,
+    changefreq: Option<String>,
+    slug: Option<String>,
+    permalink: Option<String>,
+    #[serde(default, deserialize_with = "opt_string_or_vec")]
+    aliases: Option<Vec<String>>,
+    /// The name Jekyll uses for aliases, which get merged with them.
+    #[serde(default, deserialize_with = "opt_string_or_vec")]
+    redirect_from: Option<Vec<String>>,
+    series: Option<String>,
+    #[serde(alias = "description")]
+    summary: Option<String>,
+    image: Option<String>,
+    cover: Option<String>,
+    lang: Option<String>,
+    translation_key: Option<String>,
+    heading_offset: Option<u8>,
 }
 
 impl Raw {
-    fn date(&self, path: &Path) -> anyhow::Result<String> {
-        match self
-            .modified
-            .as_ref()
-            .and_then(|x| date_prefix(x))
-            .or(self.created.as_ref().and_then(|x| date_prefix(x)))
-            .or(self.date.as_ref().and_then(|x| date_prefix(x)))
-        {
-            Some(x) => Ok(x.to_owned()),
-            None => mtime_date(path),
+    fn date(&self, offset: Option<UtcOffset>) -> Option<String> {
+        let date = |x: &Option<String>| {
+            let x = x.as_deref()?;
+            offset
+                .and_then(|offset| date_in_offset(x, offset))
+                .or_else(|| date_prefix(x).map(|x| x.to_owned()))
+        };
+        date(&self.modified)
+            .or_else(|| date(&self.created))
+            .or_else(|| date(&self.date))
+    }
+
+    /// The fields which are missing, and need to be inferred from the file.
+    fn inferred(&self) -> Vec<&'static str> {
+        let has_date = [&self.modified, &self.created, &self.date]
+            .into_iter()
+            .any(|x| x.as_deref().and_then(date_prefix).is_some());
+        let mut out = Vec::new();
+        if self.title.is_none() {
+            out.push("title");
+        }
+        if !has_date {
+            out.push("date");
         }
+        out
     }
 
     fn title(&self, path: &Path) -> anyhow::Result<String> {
@@ -99,6 +310,13 @@ impl Raw {
             .unwrap_or(false)
     }
 
+    fn unlisted(&self) -> bool {
+        self.unlisted
+            .as_ref()
+            .map(|x| x.to_lowercase() == "true")
+            .unwrap_or(false)
+    }
+
     fn authors(&self) -> Vec<String> {
         self.authors.clone().unwrap_or_default()
     }
@@ -117,42 +335,447 @@ impl Raw {
     fn tags(&self) -> Vec<String> {
         self.tags.clone().unwrap_or_default()
     }
+
+    fn slug(&self) -> Option<String> {
+        self.slug.clone()
+    }
+
+    fn permalink(&self) -> Option<String> {
+        self.permalink.clone()
+    }
+
+    fn aliases(&self) -> Vec<String> {
+        let aliases = self.aliases.iter().chain(&self.redirect_from);
+        aliases.flatten().cloned().collect()
+    }
+
+    fn series(&self) -> Option<String> {
+        self.series.clone()
+    }
+
+    fn summary(&self) -> Option<String> {
+        self.summary.clone()
+    }
+
+    fn image(&self) -> Option<String> {
+        self.image.clone()
+    }
+
+    fn cover(&self) -> Option<String> {
+        self.cover.clone()
+    }
+
+    fn lang(&self) -> Option<String> {
+        self.lang.clone()
+    }
+
+    fn translation_key(&self) -> Option<String> {
+        self.translation_key.clone()
+    }
+
+    fn sitemap(&self) -> SitemapSettings {
+        SitemapSettings {
+            priority: self.priority,
+            changefreq: self.changefreq.clone(),
+        }
+    }
+}
+
+/// The location of the value of a top level field, like `title: ...`, or `title = ...`.
+fn field_location(text: &str, field: &str) -> Option<Location> {
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let rest = line
+            .strip_prefix(field)
+            .or_else(|| line.strip_prefix(&format!("\"{field}\"")));
+        if let Some(rest) = rest
+            && let Some(value) = rest.trim_start().strip_prefix([':', '='])
+        {
+            let column = line.len() - value.trim_start().len();
+            return Some(Location::of(text, offset + column));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Describe invalid front matter, with where the problem is in the file, and the field it's in.
+///
+/// The location is in the front matter, which starts on the line after the `---`,
+/// or `+++`, opening it.
+fn invalid(
+    text: &str,
+    location: Option<Location>,
+    field: Option<&str>,
+    message: impl fmt::Display,
+) -> anyhow::Error {
+    let message = match field {
+        Some(field) => format!("invalid front matter, in `{field}`: {message}"),
+        None => format!("invalid front matter: {message}"),
+    };
+    let location = location.map(|x| Location {
+        line: x.line + 1,
+        ..x
+    });
+    // Fields get their whole value underlined.
+    let len = if field.is_some() { usize::MAX } else { 1 };
+    anyhow::Error::new(Diagnostic {
+        message,
+        location,
+        snippet: location.and_then(|x| snippet(text, 2, x, len)),
+    })
+}
+
+/// The message of a YAML error, without the location at its end, which is in the front
+/// matter, rather than the file.
+fn yaml_message(e: &serde_yaml::Error) -> String {
+    let message = e.to_string();
+    match e.location() {
+        Some(_) => message
+            .rsplit_once(" at line ")
+            .map_or(message.as_str(), |(x, _)| x)
+            .to_string(),
+        None => message,
+    }
+}
+
+/// Parse front matter as one format or the other.
+///
+/// YAML gets parsed from text, which is more lenient than going through a value,
+/// reading `draft: true` as a string, for example.
+fn parse_value(source: Source<'_>, value: serde_yaml::Value) -> Result<Raw, serde_yaml::Error> {
+    match source {
+        Source::Yaml(_) => serde_yaml::from_str(&serde_yaml::to_string(&value)?),
+        Source::Toml(_) => serde_yaml::from_value(value),
+    }
+}
+
+/// Reject dates which aren't dates, rather than inferring one from the file instead.
+fn check_dates(text: &str, raw: Raw) -> anyhow::Result<Raw> {
+    let fields = [
+        ("date", &raw.date),
+        ("modified", &raw.modified),
+        ("created", &raw.created),
+        ("published", &raw.published),
+    ];
+    for (field, value) in fields {
+        if let Some(value) = value
+            && date_prefix(value).is_none()
+        {
+            let message = format!("invalid date `{value}`, expected one like `2024-01-31`");
+            return Err(invalid(
+                text,
+                field_location(text, field),
+                Some(field),
+                message,
+            ));
+        }
+    }
+    Ok(raw)
+}
+
+/// Parse front matter, explaining where it's invalid, if it is.
+///
+/// When the front matter as a whole doesn't fit, each field gets parsed alone,
+/// to find the one at fault, since serde doesn't always tell us.
+fn parse_raw(source: Source<'_>) -> anyhow::Result<Raw> {
+    let (text, value) = match source {
+        Source::Yaml(y) => match serde_yaml::from_str::<serde_yaml::Value>(y) {
+            Ok(value) => (y, value),
+            Err(e) => {
+                let location = e.location().map(|x| Location {
+                    line: x.line(),
+                    column: x.column(),
+                });
+                return Err(invalid(y, location, None, yaml_message(&e)));
+            }
+        },
+        Source::Toml(t) => match t.parse::<toml::Table>() {
+            Ok(table) => (t, toml_to_yaml(toml::Value::Table(table))),
+            Err(e) => {
+                let location = e.span().map(|x| Location::of(t, x.start));
+                return Err(invalid(t, location, None, e.message()));
+            }
+        },
+    };
+    let parsed = match source {
+        Source::Yaml(y) => serde_yaml::from_str::<Raw>(y),
+        Source::Toml(_) => serde_yaml::from_value::<Raw>(value.clone()),
+    };
+    let e = match parsed {
+        Ok(raw) => return check_dates(text, raw),
+        Err(e) => e,
+    };
+    if let serde_yaml::Value::Mapping(mapping) = value {
+        for (key, value) in mapping {
+            let Some(field) = key.as_str().map(str::to_string) else {
+                continue;
+            };
+            let alone = serde_yaml::Mapping::from_iter([(key, value)]);
+            if let Err(e) = parse_value(source, serde_yaml::Value::Mapping(alone)) {
+                let location = field_location(text, &field);
+                let message = yaml_message(&e);
+                let message = message
+                    .strip_prefix(&format!("{field}: "))
+                    .unwrap_or(&message);
+                return Err(invalid(text, location, Some(&field), message));
+            }
+        }
+    }
+    Err(invalid(text, None, None, yaml_message(&e)))
 }
 
 #[derive(Clone, Debug)]
 pub struct FrontMatter {
     pub title: String,
     pub draft: bool,
+    /// Rendered, but left out of lists, feeds, and the like, so only those with the link find it.
+    pub unlisted: bool,
     pub date: String,
     pub authors: Vec<String>,
     pub published: Option<String>,
     pub link: Option<String>,
     pub tags: Vec<String>,
+    /// Overrides for the sitemap settings of this page.
+    pub sitemap: SitemapSettings,
+    /// Replaces the file name in the URL of this page, keeping the folder.
+    pub slug: Option<String>,
+    /// Replaces the whole URL of this page, e.g. `/about/`.
+    pub permalink: Option<String>,
+    /// Old paths of this page, which should redirect to it.
+    pub aliases: Vec<String>,
+    /// The name of the series this page is a part of.
+    pub series: Option<String>,
+    /// A short description of the page, used instead of the start of the content.
+    pub summary: Option<String>,
+    /// An image for the page, from the root of the site, shown when the page is shared.
+    pub image: Option<String>,
+    /// A cover image for the page, relative to it, like `attachments/cover.png`.
+    ///
+    /// Unlike `image`, this gets checked, and shown in listings and feeds as well.
+    pub cover: Option<String>,
+    /// The language of the page, e.g. `fr`, if it's not the one of its folder, or the site.
+    pub lang: Option<String>,
+    /// Identifies the translations of a page, which share the same key.
+    ///
+    /// Pages at the same path in different language folders are translations by default.
+    pub translation_key: Option<String>,
+    /// Whether the title came from the first `# Heading` of the page.
+    pub heading_title: bool,
+    /// How many levels to demote the headings of this page by, instead of `heading_offset`
+    /// in the config.
+    pub heading_offset: Option<u8>,
+    /// The fields which weren't given, and got inferred from the file instead.
+    pub inferred: Vec<&'static str>,
 }
 
 impl FrontMatter {
-    /// Attempt to parse a yaml string into this front matter.
+    /// Whether the page can show up in lists, feeds, backlinks, and so on.
+    ///
+    /// This leaves out drafts, as well as unlisted pages.
+    pub fn listed(&self) -> bool {
+        !self.draft && !self.unlisted
+    }
+
+    /// Use the first `# Heading` of a page as its title, instead of its file name,
+    /// if the front matter doesn't have one.
+    pub fn use_heading_title(&mut self, heading: Option<String>) {
+        if let Some(heading) = heading
+            && self.inferred.contains(&"title")
+        {
+            self.title = heading;
+            self.heading_title = true;
+            self.inferred.retain(|x| *x != "title");
+        }
+    }
+
+    /// Attempt to parse front matter, in YAML or TOML.
     ///
     /// We use the full path to populate some of the missing fields.
     ///
     /// In detail, if not present in the frontmatter:
     /// - the title will be extracted from the end of the path,
-    /// - the date will be extracted from the modified time of the file.
-    pub fn try_from_yaml(path: &Path, yaml: Option<&str>) -> anyhow::Result<Self> {
-        let raw: Raw = match yaml {
-            Some(y) => serde_yaml::from_str(y)?,
-            None => Default::default(),
+    /// - the date will be extracted from the modified time of the file,
+    ///   or its git history, along with the publication date.
+    ///
+    /// Dates are in the timezone of the settings, or UTC.
+    pub fn try_from_source(
+        path: &Path,
+        source: Option<Source<'_>>,
+        dates: DateSettings<'_>,
+    ) -> anyhow::Result<Self> {
+        let raw = match source {
+            Some(source) => parse_raw(source)?,
+            None => Raw::default(),
         };
         let mut tags = raw.tags();
         tags.sort();
+        let (date, published) = match raw.date(dates.offset) {
+            Some(date) => (date, raw.published()),
+            None => {
+                let git = match dates.source {
+                    DateSource::Git => dates.git.and_then(|git| git.dates(path)),
+                    DateSource::Mtime => None,
+                };
+                match git {
+                    Some((last, first)) => (last, raw.published().or(Some(first))),
+                    None => (mtime_date(path, dates.offset)?, raw.published()),
+                }
+            }
+        };
         Ok(Self {
             title: raw.title(path)?,
             draft: raw.draft(),
-            date: raw.date(path)?,
+            unlisted: raw.unlisted(),
+            date,
             authors: raw.authors(),
-            published: raw.published(),
+            published,
             link: raw.link(),
             tags,
+            sitemap: raw.sitemap(),
+            slug: raw.slug(),
+            permalink: raw.permalink(),
+            aliases: raw.aliases(),
+            series: raw.series(),
+            summary: raw.summary(),
+            image: raw.image(),
+            cover: raw.cover(),
+            lang: raw.lang(),
+            translation_key: raw.translation_key(),
+            heading_offset: raw.heading_offset,
+            heading_title: false,
+            inferred: raw.inferred(),
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn toml_and_yaml_agree() {
+        let path = Path::new("content/Post.md");
+        let yaml = "title: Hi\ndate: 2024-01-02\ndraft: \"true\"\nunlisted: true\ntags: [b, a]\npriority: 0.5\ndescription: Short\n";
+        let toml = "title = \"Hi\"\ndate = 2024-01-02\ndraft = true\nunlisted = true\ntags = [\"b\", \"a\"]\npriority = 0.5\nsummary = \"Short\"\n";
+        let from_yaml =
+            FrontMatter::try_from_source(path, Some(Source::Yaml(yaml)), DateSettings::default())
+                .unwrap();
+        let from_toml =
+            FrontMatter::try_from_source(path, Some(Source::Toml(toml)), DateSettings::default())
+                .unwrap();
+        assert_eq!(format!("{from_yaml:?}"), format!("{from_toml:?}"));
+        assert_eq!(from_toml.date, "2024-01-02");
+        assert!(from_toml.draft);
+        assert!(from_toml.unlisted);
+        assert_eq!(from_toml.tags, vec!["a", "b"]);
+        assert_eq!(from_yaml.summary.as_deref(), Some("Short"));
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let path = Path::new("content/Post.md");
+        let error = |source| {
+            let e = FrontMatter::try_from_source(path, Some(source), DateSettings::default())
+                .unwrap_err();
+            Diagnostic::from(e).show_in(path)
+        };
+        assert_eq!(
+            error(Source::Yaml("date: 2024-01-01\ntitle:  [a]\n")),
+            "content/Post.md:3:9: invalid front matter, in `title`: invalid type: sequence, expected a string\n  |\n3 | title:  [a]\n  |         ^^^"
+        );
+        let first_line = |source| error(source).lines().next().unwrap().to_string();
+        assert_eq!(
+            first_line(Source::Yaml("tags: {a: b}\n")),
+            "content/Post.md:2:7: invalid front matter, in `tags`: expected a string, or a list of strings"
+        );
+        assert!(
+            first_line(Source::Yaml("title: a\n  b: c: d\n"))
+                .starts_with("content/Post.md:3:4: invalid front matter: mapping values")
+        );
+        assert_eq!(
+            first_line(Source::Yaml("title: a\ndate: yesterday\n")),
+            "content/Post.md:3:7: invalid front matter, in `date`: invalid date `yesterday`, expected one like `2024-01-31`"
+        );
+        assert_eq!(
+            first_line(Source::Toml("title = \"a\"\npriority = \"high\"\n")),
+            "content/Post.md:3:12: invalid front matter, in `priority`: invalid type: string \"high\", expected f64"
+        );
+        assert_eq!(
+            first_line(Source::Toml("tags = [\n")),
+            "content/Post.md:2:9: invalid front matter: unclosed array, expected `]`"
+        );
+    }
+
+    #[test]
+    fn timezones() {
+        let path = Path::new("content/Post.md");
+        let yaml = Source::Yaml("date: 2024-01-01T23:30:00-05:00\n");
+        let date = |offset| {
+            let dates = DateSettings {
+                offset,
+                ..Default::default()
+            };
+            FrontMatter::try_from_source(path, Some(yaml), dates)
+                .unwrap()
+                .date
+        };
+        assert_eq!(date(None), "2024-01-01");
+        assert_eq!(date(Some(UtcOffset::UTC)), "2024-01-02");
+        let plain = Source::Yaml("date: 2024-01-01\n");
+        let dates = DateSettings {
+            offset: Some(UtcOffset::UTC),
+            ..Default::default()
+        };
+        let front_matter = FrontMatter::try_from_source(path, Some(plain), dates);
+        assert_eq!(front_matter.unwrap().date, "2024-01-01");
+    }
+
+    #[test]
+    fn git_dates_from_commits() {
+        let dir = TempDir::new("git-dates");
+        let git = |args: &[&str], date: &str| {
+            Command::new("git")
+                .args(["-c", "user.name=A", "-c", "user.email=a@example.com"])
+                .args(args)
+                .env("GIT_COMMITTER_DATE", date)
+                .current_dir(&dir)
+                .output()
+                .is_ok_and(|x| x.status.success())
+        };
+        if !git(&["init", "-q"], "") {
+            // Without git, this can't be tested.
+            return;
+        }
+        let path = dir.join("Post.md");
+        std::fs::write(&path, "a").unwrap();
+        git(&["add", "Post.md"], "");
+        git(&["commit", "-qm", "a"], "2024-01-01T12:00:00+00:00");
+        std::fs::write(&path, "b").unwrap();
+        git(&["commit", "-qam", "b"], "2024-03-01T23:30:00-05:00");
+        std::fs::write(dir.join("Old.md"), "some longer text, to find the rename").unwrap();
+        git(&["add", "Old.md"], "");
+        git(&["commit", "-qm", "c"], "2024-04-01T12:00:00+00:00");
+        git(&["mv", "Old.md", "New.md"], "");
+        git(&["commit", "-qm", "d"], "2024-05-01T12:00:00+00:00");
+        let history = GitHistory::load(&dir, None);
+        // Renamed files keep the dates from before.
+        assert_eq!(
+            history.dates(&dir.join("New.md")),
+            Some(("2024-05-01".to_string(), "2024-04-01".to_string()))
+        );
+        assert!(history.warnings.is_empty());
+        let dates = DateSettings {
+            offset: None,
+            source: DateSource::Git,
+            git: Some(&history),
+        };
+        let front_matter = FrontMatter::try_from_source(&path, None, dates).unwrap();
+        assert_eq!(front_matter.date, "2024-03-02");
+        assert_eq!(front_matter.published.as_deref(), Some("2024-01-01"));
+        let yaml = Source::Yaml("date: 2024-05-01\n");
+        let front_matter = FrontMatter::try_from_source(&path, Some(yaml), dates).unwrap();
+        assert_eq!(front_matter.published, None);
+    }
+}
@@ -1,25 +1,309 @@
-use std::{fs, path::Path};
+use std::{
+    collections::HashSet,
+    fs::{self, DirEntry, FileType},
+    path::{Path, PathBuf},
+};
 
-/// Copy the files in a directory to another directory.
+use crate::diagnostic::{Diagnostic, Warnings};
+
+/// The type of an entry in a directory, for finding content, following symlinks if `follow` is set.
+///
+/// Symlinks which aren't followed, are broken, point to a directory containing
+/// them, or point to a directory already in `visited`, give `None`, with a warning
+/// added to `warnings`.
+/// Each directory given back gets added to `visited`, by its canonical path,
+/// so that symlinks pointing at each other can't be followed forever.
+pub fn entry_type(
+    entry: &DirEntry,
+    follow: bool,
+    visited: &mut HashSet<PathBuf>,
+    warnings: &mut Vec<(PathBuf, Diagnostic)>,
+) -> anyhow::Result<Option<FileType>> {
+    let file_type = entry.file_type()?;
+    if file_type.is_dir() {
+        // A directory already reached through a symlink has had its content found.
+        let visit = visited.insert(fs::canonicalize(entry.path())?);
+        return Ok(visit.then_some(file_type));
+    }
+    if !file_type.is_symlink() {
+        return Ok(Some(file_type));
+    }
+    let path = entry.path();
+    let mut skip = |message: String| -> anyhow::Result<Option<FileType>> {
+        warnings.push((path.clone(), Diagnostic::new(message)));
+        Ok(None)
+    };
+    if !follow {
+        return skip("skipping symlink, set `follow_symlinks` to include it".to_string());
+    }
+    let meta = match fs::metadata(&path) {
+        Ok(meta) => meta,
+        Err(e) => return skip(format!("skipping broken symlink: {e}")),
+    };
+    if meta.is_dir() {
+        let target = fs::canonicalize(&path)?;
+        let parent = fs::canonicalize(path.parent().unwrap_or(Path::new(".")))?;
+        if parent.starts_with(&target) {
+            return skip("skipping symlink, which points to a directory containing it".to_string());
+        }
+        if !visited.insert(target) {
+            return skip(
+                "skipping symlink, which points to a directory already included".to_string(),
+            );
+        }
+    }
+    Ok(Some(meta.file_type()))
+}
+
+/// Copy a file, unless the copy is already there, with the same size, and newer.
+///
+/// Comparing metadata avoids rewriting large files, like images, on every build.
+pub fn copy_if_changed(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let (Ok(source), Ok(copy)) = (fs::metadata(from), fs::metadata(to))
+        && source.len() == copy.len()
+        && copy.modified()? >= source.modified()?
+    {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    Ok(())
+}
+
+/// Write a file, unless it already has exactly these contents.
 ///
-/// This does not follow symlinks, or enter sub-directories.
-pub fn copy_dir(in_dir: &Path, out_dir: &Path) -> anyhow::Result<()> {
+/// Leaving unchanged files alone keeps their modification times, so that syncing
+/// the output somewhere only sends what changed.
+pub fn write_if_changed(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let (path, contents) = (path.as_ref(), contents.as_ref());
+    let same_len = fs::metadata(path).is_ok_and(|x| x.len() == contents.len() as u64);
+    if same_len && fs::read(path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Copy the files in a directory to another directory, recursively.
+///
+/// Symlinks to files are followed, copying the file they point to. Symlinks to
+/// directories are skipped, with a warning, since these could form a cycle.
+/// Files which haven't changed since they were last copied are left alone.
+pub fn copy_dir(in_dir: &Path, out_dir: &Path, warnings: &mut Warnings) -> anyhow::Result<()> {
+    copy_dir_with(in_dir, out_dir, warnings, &mut |from, to, _| {
+        copy_if_changed(from, to)
+    })
+}
+
+/// Copy the files in a directory to another directory, like `copy_dir`,
+/// with `copy` copying each file, to the path it would be copied to.
+pub fn copy_dir_with<F>(
+    in_dir: &Path,
+    out_dir: &Path,
+    warnings: &mut Warnings,
+    copy: &mut F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(&Path, &Path, &mut Warnings) -> anyhow::Result<()>,
+{
     let mut out_path = out_dir.to_path_buf();
     fs::create_dir_all(&out_path)?;
     for entry in fs::read_dir(in_dir)? {
         let entry = entry?;
-        let file_type = entry.file_type()?;
-        if file_type.is_file() {
-            out_path.push(entry.file_name());
-            fs::copy(entry.path(), &out_path)?;
-            out_path.pop();
-            continue;
+        let path = entry.path();
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            match fs::metadata(&path) {
+                Ok(meta) if meta.is_dir() => {
+                    warnings.push(&path, "skipping symlinked directory".to_string());
+                    continue;
+                }
+                Ok(meta) => file_type = meta.file_type(),
+                Err(e) => {
+                    warnings.push(&path, format!("skipping broken symlink: {e}"));
+                    continue;
+                }
+            }
         }
+        out_path.push(entry.file_name());
         if file_type.is_dir() {
-            out_path.push(entry.file_name());
-            copy_dir(&entry.path(), &out_path)?;
-            out_path.pop();
+            copy_dir_with(&path, &out_path, warnings, copy)?;
+        } else if file_type.is_file() {
+            copy(&path, &out_path, warnings)?;
+        }
+        out_path.pop();
+    }
+    Ok(())
+}
+
+/// Remove everything inside of a directory, keeping the directory itself.
+///
+/// A missing directory is left missing.
+pub fn clean_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
         }
     }
     Ok(())
 }
+
+/// An empty directory for a test, removed again when dropped, even if the test panics.
+#[cfg(test)]
+pub struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// Create the directory, named after `name`, and the current process.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("clog-{name}-test-{}", std::process::id()));
+        // Left over from a run which was killed, before it could clean up.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Some tests remove the directory themselves.
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn copying() {
+        let dir = TempDir::new("copy");
+        fs::create_dir_all(dir.join("in/css")).unwrap();
+        fs::write(dir.join("in/css/style.css"), "a").unwrap();
+        fs::write(dir.join("in/index.js"), "b").unwrap();
+        copy_dir(&dir.join("in"), &dir.join("out"), &mut Warnings::default()).unwrap();
+        let style = fs::read_to_string(dir.join("out/css/style.css")).unwrap();
+        let script = fs::read_to_string(dir.join("out/index.js")).unwrap();
+        assert_eq!((style.as_str(), script.as_str()), ("a", "b"));
+    }
+
+    #[test]
+    fn skips_unchanged_files() {
+        let dir = TempDir::new("unchanged");
+        let (from, to) = (dir.join("a.png"), dir.join("b.png"));
+        fs::write(&from, "a").unwrap();
+        copy_if_changed(&from, &to).unwrap();
+        // A newer copy with the same size counts as unchanged.
+        fs::write(&to, "b").unwrap();
+        copy_if_changed(&from, &to).unwrap();
+        let unchanged = fs::read_to_string(&to).unwrap();
+        fs::write(&from, "aa").unwrap();
+        copy_if_changed(&from, &to).unwrap();
+        let changed = fs::read_to_string(&to).unwrap();
+        assert_eq!((unchanged.as_str(), changed.as_str()), ("b", "aa"));
+    }
+
+    #[test]
+    fn skips_identical_writes() {
+        let dir = TempDir::new("identical");
+        let path = dir.join("a.html");
+        write_if_changed(&path, "a").unwrap();
+        let old = SystemTime::UNIX_EPOCH;
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        write_if_changed(&path, "a").unwrap();
+        let unchanged = fs::metadata(&path).unwrap().modified().unwrap();
+        write_if_changed(&path, "b").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(unchanged, old);
+        assert_eq!(contents, "b");
+    }
+
+    #[test]
+    fn cleaning() {
+        let dir = TempDir::new("clean");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/c.html"), "").unwrap();
+        fs::write(dir.join("d.html"), "").unwrap();
+        clean_dir(&dir).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+        clean_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        let dir = TempDir::new("symlink");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("attachments")).unwrap();
+        std::os::unix::fs::symlink(dir.join("attachments"), dir.join("a/files")).unwrap();
+        std::os::unix::fs::symlink(dir.join("attachments"), dir.join("a/more")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("a/loop")).unwrap();
+        let types = |follow| {
+            let mut visited = HashSet::from([
+                fs::canonicalize(&dir).unwrap(),
+                fs::canonicalize(dir.join("a")).unwrap(),
+            ]);
+            let mut entries = fs::read_dir(dir.join("a"))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            entries.sort_by_key(|entry| entry.file_name());
+            entries
+                .iter()
+                .map(|entry| {
+                    let file_type =
+                        entry_type(entry, follow, &mut visited, &mut Vec::new()).unwrap();
+                    (entry.file_name(), file_type.map(|x| x.is_dir()))
+                })
+                .collect::<Vec<_>>()
+        };
+        let followed = types(true);
+        let skipped = types(false);
+        // The second symlink to the same directory would include its files twice.
+        assert_eq!(
+            followed,
+            vec![
+                ("files".into(), Some(true)),
+                ("loop".into(), None),
+                ("more".into(), None)
+            ]
+        );
+        assert_eq!(
+            skipped,
+            vec![
+                ("files".into(), None),
+                ("loop".into(), None),
+                ("more".into(), None)
+            ]
+        );
+    }
+}
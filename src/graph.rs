@@ -0,0 +1,66 @@
+use serde_json::{Value, json};
+
+use crate::sitemap::SiteMap;
+
+/// The wikilink graph of a site, as JSON.
+///
+/// Each listed page is a node, identified by its link, and each wikilink
+/// between two of these pages is an edge, from the linking page to the linked one.
+pub fn graph(site_map: &SiteMap) -> Value {
+    let pages = site_map
+        .pages()
+        .filter(|page| page.front_matter.listed())
+        .collect::<Vec<_>>();
+    let nodes = pages
+        .iter()
+        .map(|page| {
+            json!({
+                "id": page.link,
+                "title": page.front_matter.title,
+                "tags": page.front_matter.tags,
+            })
+        })
+        .collect::<Vec<_>>();
+    let edges = pages
+        .iter()
+        .flat_map(|page| {
+            site_map.backlinks(page).map(|linking| {
+                json!({
+                    "source": linking.link,
+                    "target": page.link,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({ "nodes": nodes, "edges": edges })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::fs_utils::TempDir;
+    use std::{fs, path::Path};
+
+    #[test]
+    fn nodes_and_edges() {
+        let dir = TempDir::new("graph");
+        fs::write(dir.join("A.md"), "---\ntags: [x]\n---\n[[B]] [[Hidden]]\n").unwrap();
+        fs::write(dir.join("B.md"), "").unwrap();
+        fs::write(dir.join("Hidden.md"), "---\ndraft: true\n---\n[[B]]\n").unwrap();
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let graph = graph(&site_map);
+        let mut ids = graph["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec!["/a.html", "/b.html"]);
+        assert_eq!(
+            graph["edges"],
+            json!([{ "source": "/a.html", "target": "/b.html" }])
+        );
+    }
+}
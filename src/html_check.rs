@@ -0,0 +1,197 @@
+use std::fmt;
+
+/// Elements which never have a closing tag.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose contents are raw text, and shouldn't be scanned for tags.
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// A problem with the structure of some HTML.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HtmlIssue {
+    /// A closing tag which doesn't match the innermost open tag.
+    Mismatched {
+        expected: Option<String>,
+        found: String,
+        line: usize,
+    },
+    /// A tag which was opened, but never closed.
+    Unclosed { tag: String, line: usize },
+}
+
+impl fmt::Display for HtmlIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mismatched {
+                expected: Some(expected),
+                found,
+                line,
+            } => write!(
+                f,
+                "line {line}: found `</{found}>`, but expected `</{expected}>`"
+            ),
+            Self::Mismatched {
+                expected: None,
+                found,
+                line,
+            } => write!(f, "line {line}: found `</{found}>` with no open tag"),
+            Self::Unclosed { tag, line } => write!(f, "line {line}: `<{tag}>` is never closed"),
+        }
+    }
+}
+
+fn line_of(html: &str, pos: usize) -> usize {
+    1 + html[..pos].bytes().filter(|&b| b == b'\n').count()
+}
+
+/// Read a tag name starting at `start`, returning it lowercased.
+pub fn tag_name(html: &str, start: usize) -> String {
+    html[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// Find the end of a tag starting at `start`, skipping over quoted attribute values.
+pub fn tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html[start..].char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(start + i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Check that some HTML has properly nested and closed tags.
+///
+/// This is not a full parser: it only tracks the stack of open elements,
+/// which is enough to catch the mistakes raw HTML in markdown tends to introduce.
+pub fn check_well_formed(html: &str) -> Vec<HtmlIssue> {
+    let mut issues = Vec::new();
+    let mut stack: Vec<(String, usize)> = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            pos = rest
+                .find("-->")
+                .map(|i| start + i + 3)
+                .unwrap_or(html.len());
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos = tag_end(html, start).map(|i| i + 1).unwrap_or(html.len());
+            continue;
+        }
+        let closing = rest.starts_with("</");
+        let name_start = start + if closing { 2 } else { 1 };
+        let name = tag_name(html, name_start);
+        if name.is_empty() {
+            // A stray `<`, like in `a < b`.
+            pos = start + 1;
+            continue;
+        }
+        let Some(end) = tag_end(html, name_start) else {
+            break;
+        };
+        pos = end + 1;
+        if closing {
+            match stack.iter().rposition(|(open, _)| *open == name) {
+                Some(i) if i + 1 == stack.len() => {
+                    stack.pop();
+                }
+                Some(i) => {
+                    for (tag, line) in stack.drain(i + 1..).rev() {
+                        issues.push(HtmlIssue::Unclosed { tag, line });
+                    }
+                    stack.pop();
+                }
+                None => issues.push(HtmlIssue::Mismatched {
+                    expected: stack.last().map(|(open, _)| open.clone()),
+                    found: name,
+                    line: line_of(html, start),
+                }),
+            }
+            continue;
+        }
+        if html[..end].ends_with('/') || VOID_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+        if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            let close = format!("</{name}");
+            match html[pos..].to_ascii_lowercase().find(&close) {
+                Some(i) => pos += i,
+                None => {
+                    issues.push(HtmlIssue::Unclosed {
+                        tag: name,
+                        line: line_of(html, start),
+                    });
+                    break;
+                }
+            }
+        }
+        stack.push((name, line_of(html, start)));
+    }
+    for (tag, line) in stack.into_iter().rev() {
+        issues.push(HtmlIssue::Unclosed { tag, line });
+    }
+    issues
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn well_formed() {
+        assert_eq!(check_well_formed("<p>Hello <em>World</em></p>"), vec![]);
+        assert_eq!(
+            check_well_formed("<img src=\"a.png\">\n<br/><hr />"),
+            vec![]
+        );
+        assert_eq!(check_well_formed("<!-- <div> -->a < b"), vec![]);
+        assert_eq!(
+            check_well_formed("<a title=\"x > y\">z</a><script>if (a<b) {}</script>"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn unclosed() {
+        assert_eq!(
+            check_well_formed("<div>\n<p>text</div>"),
+            vec![HtmlIssue::Unclosed {
+                tag: "p".into(),
+                line: 2
+            }]
+        );
+        assert_eq!(
+            check_well_formed("<div>"),
+            vec![HtmlIssue::Unclosed {
+                tag: "div".into(),
+                line: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn mismatched() {
+        assert_eq!(
+            check_well_formed("<p>a</span></p>"),
+            vec![HtmlIssue::Mismatched {
+                expected: Some("p".into()),
+                found: "span".into(),
+                line: 1
+            }]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use regex::Regex;
+use std::{fs, path::Path};
+
+use crate::diagnostic::{Diagnostic, Location};
+
+/// The name of the file listing what to leave out of a site, inside of `content`.
+pub const IGNORE_FILE: &str = ".clogignore";
+
+/// A single line of an ignore file.
+#[derive(Debug)]
+struct Rule {
+    regex: Regex,
+    /// Whether this is a `!pattern`, including paths a previous rule ignored.
+    negated: bool,
+    /// Whether this only matches folders, like `drafts/`.
+    dir_only: bool,
+}
+
+/// Convert a glob, like `**/*.md`, into a regex matching the whole of a path.
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.next_if_eq(&'*').is_some() => {
+                if chars.next_if_eq(&'/').is_some() {
+                    // `a/**/b` matches `a/b`, along with deeper paths.
+                    out.push_str("(?:.*/)?");
+                } else {
+                    out.push_str(".*");
+                }
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    out.push('$');
+    out
+}
+
+/// Patterns in the syntax of a `.gitignore`, for files and folders to leave out of a site.
+///
+/// This supports `*`, `?`, `**`, negation with `!`, folder patterns ending with `/`,
+/// and anchoring patterns to the root with a `/`. Patterns without a `/`, like
+/// `*.tmp`, match at any depth.
+#[derive(Debug, Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+    /// The patterns which were invalid, and got skipped.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Ignore {
+    /// Parse the lines of an ignore file, skipping blanks, comments, and invalid patterns.
+    pub fn parse(source: &str) -> Self {
+        let mut rules = Vec::new();
+        let mut warnings = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, pattern),
+            };
+            let glob = match pattern.strip_prefix('/') {
+                Some(anchored) => anchored.to_string(),
+                None if pattern.contains('/') => pattern.to_string(),
+                None => format!("**/{pattern}"),
+            };
+            match Regex::new(&glob_to_regex(&glob)) {
+                Ok(regex) => rules.push(Rule {
+                    regex,
+                    negated,
+                    dir_only,
+                }),
+                Err(e) => {
+                    let mut warning = Diagnostic::new(format!("invalid pattern `{line}`: {e}"));
+                    warning.location = Some(Location {
+                        line: i + 1,
+                        column: 1,
+                    });
+                    warnings.push(warning.with_source(source));
+                }
+            }
+        }
+        Self { rules, warnings }
+    }
+
+    /// Read the ignore file inside of a folder, which may not exist.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(IGNORE_FILE);
+        if !fs::exists(&path)? {
+            return Ok(Self::default());
+        }
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Check if a path, relative to the folder of the ignore file, should be left out.
+    ///
+    /// The last matching pattern wins, so that `!` can include paths again.
+    pub fn is_ignored(&self, rel_path: &Path, is_dir: bool) -> bool {
+        let path = rel_path
+            .iter()
+            .map(|x| x.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.regex.is_match(&path))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        let ignore = Ignore::parse(
+            "# comment\n*.tmp\n/Private\nTemplates/\nnotes/**/scratch.md\n!keep.tmp\n",
+        );
+        let ignored = |path: &str, is_dir| ignore.is_ignored(Path::new(path), is_dir);
+        assert!(ignored("a/b.tmp", false));
+        assert!(!ignored("keep.tmp", false));
+        assert!(ignored("Private", true));
+        assert!(!ignored("a/Private", true));
+        assert!(ignored("a/Templates", true));
+        assert!(!ignored("Templates", false));
+        assert!(ignored("notes/scratch.md", false));
+        assert!(ignored("notes/a/b/scratch.md", false));
+        assert!(!ignored("Post.md", false));
+    }
+}
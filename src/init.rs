@@ -0,0 +1,68 @@
+use std::{fs, path::Path};
+
+/// The files making up a new site, with a minimal working theme.
+const FILES: [(&str, &str); 8] = [
+    ("config.yaml", include_str!("../scaffold/config.yaml")),
+    (
+        "templates/index.html",
+        include_str!("../scaffold/templates/index.html"),
+    ),
+    (
+        "templates/list.html",
+        include_str!("../scaffold/templates/list.html"),
+    ),
+    (
+        "templates/tags.html",
+        include_str!("../scaffold/templates/tags.html"),
+    ),
+    (
+        "static/index.css",
+        include_str!("../scaffold/static/index.css"),
+    ),
+    (
+        "content/index.md",
+        include_str!("../scaffold/content/index.md"),
+    ),
+    ("content/404.md", include_str!("../scaffold/content/404.md")),
+    (
+        "content/Hello World.md",
+        include_str!("../scaffold/content/Hello World.md"),
+    ),
+];
+
+/// Create the skeleton of a new site in a directory.
+///
+/// Existing files are left alone, so that this can be run over a partial site.
+pub fn init(dir: &Path) -> anyhow::Result<()> {
+    for (name, contents) in FILES {
+        let path = dir.join(name);
+        if path.exists() {
+            println!("skipping {}, which already exists", path.display());
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, contents)?;
+        println!("created {}", path.display());
+    }
+    fs::create_dir_all(dir.join("data"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn keeps_existing_files() {
+        let dir = TempDir::new("init");
+        fs::write(dir.join("config.yaml"), "title: Mine\n").unwrap();
+        init(&dir).unwrap();
+        let config = fs::read_to_string(dir.join("config.yaml")).unwrap();
+        let template_exists = dir.join("templates/list.html").is_file();
+        assert_eq!(config, "title: Mine\n");
+        assert!(template_exists);
+    }
+}
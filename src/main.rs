@@ -1,58 +1,256 @@
 use anyhow::anyhow;
-use minijinja::{Environment, context};
+use minijinja::context;
 use std::{
     borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::{self},
-    io::{BufWriter, Write},
-    path::PathBuf,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
+use time::UtcOffset;
 
+mod activitypub;
+mod assets;
+mod cache;
+mod check;
 mod config;
+mod csv;
+mod diagnostic;
+mod escape;
+mod feed;
 mod frontmatter;
 mod fs_utils;
+mod graph;
+mod html_check;
+mod ignore;
+mod init;
 mod markdown;
+mod meta;
+mod microformats;
+mod new_post;
+mod pipeline;
+mod plugin;
+mod redirect;
+mod sanitize;
+mod search;
+mod serve;
+mod shortcode;
 mod sitemap;
 mod slug;
+mod tags;
+mod templates;
+mod timings;
+mod watch;
 mod wikilink;
 
-use fs_utils::copy_dir;
-use sitemap::SiteMap;
+use fs_utils::{clean_dir, copy_dir, copy_dir_with, copy_if_changed, write_if_changed};
+use sitemap::{NOT_FOUND_LINK, SiteMap, output_path, path_link};
 
 use crate::{
-    config::Config,
-    markdown::{extract_description, make_mdast, write_md_ast},
+    activitypub::{ACTOR_PATH, Actor, OUTBOX_PATH},
+    cache::{CACHE_FILE, Cache, CachedPage, hash_dir},
+    check::{Issue, Severity},
+    config::{Config, DuplicateNames, HighlightStyle},
+    diagnostic::{Diagnostic, Warnings},
+    escape::escape_attr,
+    feed::{Entry, Feed},
+    markdown::{
+        HIGHLIGHT_CSS, KATEX_CDN_CSS, MATH_CACHE_FILE, MathCache, MathContext, Options,
+        adjust_headings, excerpt_ast, extract_description, highlight_stylesheet, make_mdast,
+        write_md_ast,
+    },
+    microformats::{EntryData, h_card, h_entry},
+    redirect::{redirect_page, redirects_file},
+    search::{SearchEntry, html_to_text},
     sitemap::Page,
-    slug::{slugify, slugify_path},
+    slug::slugify,
+    tags::{tag_slug, tag_tree, tag_url},
+    timings::{PageTimings, Timings, timed},
 };
 
 /// A static string for usage errors.
-const USAGE: &str = "usage: clog <input_dir> <output_dir>";
+const USAGE: &str = "usage: clog <input_dir> <output_dir> [--strict] [--clean] [--future] [--timings]
+       clog serve <input_dir> [output_dir] [--port <port>] [--strict] [--clean] [--future] [--timings]
+       clog check <input_dir> [--strict] [--future]
+       clog init <input_dir>
+       clog new <title> [input_dir] [--folder <folder>]";
+
+/// The port used by `clog serve`, unless another one is given.
+const DEFAULT_PORT: u16 = 8080;
+
+/// The valid values for `changefreq` in a sitemap.
+const CHANGEFREQS: [&str; 7] = [
+    "always", "hourly", "daily", "weekly", "monthly", "yearly", "never",
+];
+
+/// What the program should do.
+#[derive(Clone, Debug, PartialEq)]
+enum Command {
+    /// Generate the site, and exit.
+    Build,
+    /// Generate the site, and then serve it locally.
+    Serve { port: u16 },
+    /// Report problems with the site, without generating it.
+    Check,
+    /// Create the skeleton of a new site.
+    Init,
+    /// Create a new draft post, inside of a folder in `content`.
+    New {
+        title: String,
+        folder: Option<PathBuf>,
+    },
+}
 
 /// Arguments to the program.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct Args {
+    pub command: Command,
     /// The input directory for the blog's files.
     pub input_dir: PathBuf,
     /// Where the site should be generated.
     pub output_dir: PathBuf,
+    /// Whether to fail on problems like broken links.
+    pub strict: bool,
+    /// Whether to empty the output directory before building.
+    pub clean: bool,
+    /// Whether to include pages dated in the future.
+    pub future: bool,
+    /// Whether to report how long each stage of the build took.
+    pub timings: bool,
 }
 
 impl Args {
     fn parse() -> anyhow::Result<Self> {
-        let mut args = std::env::args().skip(1);
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut args = args.into_iter().peekable();
+        let name = args.next_if(|x| ["serve", "check", "init", "new"].contains(&x.as_str()));
+        let serve = name.as_deref() == Some("serve");
+        let mut port = DEFAULT_PORT;
+        let mut strict = false;
+        let mut clean = false;
+        let mut future = false;
+        let mut timings = false;
+        let mut folder = None;
+        let mut positional = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--port" if serve => {
+                    let value = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                    port = value
+                        .parse()
+                        .map_err(|_| anyhow!("invalid port: {value}\n{USAGE}"))?;
+                }
+                "--folder" if name.as_deref() == Some("new") => {
+                    folder = Some(args.next().ok_or_else(|| anyhow!(USAGE))?.into());
+                }
+                "--strict" => strict = true,
+                "--future" if matches!(name.as_deref(), None | Some("serve" | "check")) => {
+                    future = true;
+                }
+                "--clean" if matches!(name.as_deref(), None | Some("serve")) => clean = true,
+                "--timings" if matches!(name.as_deref(), None | Some("serve")) => timings = true,
+                _ => positional.push(PathBuf::from(arg)),
+            }
+        }
+        let mut positional = positional.into_iter();
+        let title = match name.as_deref() {
+            Some("new") => Some(positional.next().ok_or_else(|| anyhow!(USAGE))?),
+            _ => None,
+        };
+        let input_dir = match (&title, positional.next()) {
+            (_, Some(dir)) => dir,
+            // Posts usually get created from inside of the site.
+            (Some(_), None) => PathBuf::from("."),
+            (None, None) => return Err(anyhow!(USAGE)),
+        };
+        let command = match name.as_deref() {
+            Some("serve") => Command::Serve { port },
+            Some("check") => Command::Check,
+            Some("init") => Command::Init,
+            _ => match title {
+                Some(title) => Command::New {
+                    title: title.to_string_lossy().into_owned(),
+                    folder,
+                },
+                None => Command::Build,
+            },
+        };
+        let output_dir = match (&command, positional.next()) {
+            (Command::Check | Command::Init | Command::New { .. }, Some(_)) => {
+                return Err(anyhow!(USAGE));
+            }
+            (_, Some(dir)) => dir,
+            // Nothing gets deployed when previewing, so a scratch directory does fine.
+            (Command::Serve { .. }, None) => std::env::temp_dir().join("clog-serve"),
+            // Nothing gets generated when checking, or creating a site or post.
+            (Command::Check | Command::Init | Command::New { .. }, None) => PathBuf::new(),
+            (Command::Build, None) => return Err(anyhow!(USAGE)),
+        };
+        if positional.next().is_some() {
+            return Err(anyhow!(USAGE));
+        }
         Ok(Self {
-            input_dir: args.next().ok_or_else(|| anyhow!(USAGE))?.into(),
-            output_dir: args.next().ok_or_else(|| anyhow!(USAGE))?.into(),
+            command,
+            input_dir,
+            output_dir,
+            strict,
+            clean,
+            future,
+            timings,
         })
     }
 }
 
+/// A page after rendering, ready to be written out.
+struct Rendered {
+    /// The full HTML of the page, after applying the template.
+    ///
+    /// This is missing if the page was in the cache, and the output is up to date.
+    html: Option<String>,
+    /// The HTML for just the markdown content.
+    body: String,
+    /// The HTML for the start of the content, shown in lists.
+    excerpt: Option<String>,
+    warnings: Vec<Diagnostic>,
+    /// How many wikilinks in the page didn't resolve.
+    broken_links: usize,
+    /// Whether the page contains math, needing the KaTeX stylesheet.
+    math: bool,
+    /// The files included by shortcodes, along with their hash, for the cache.
+    includes: Vec<(String, Option<u64>)>,
+    /// How long each stage of rendering took.
+    timings: PageTimings,
+    /// The hash of the page, for the cache.
+    hash: u64,
+}
+
 struct Processor {
     config_file: PathBuf,
     content_dir: PathBuf,
+    data_dir: PathBuf,
     static_dir: PathBuf,
     template_dir: PathBuf,
+    shortcode_dir: PathBuf,
+    /// A copy of the `dist` folder of KaTeX, with its stylesheet and fonts, if the site has one.
+    ///
+    /// clog doesn't come with KaTeX, so this only gets copied into the output, when a page
+    /// uses math, if the site provides it. Otherwise, pages use the stylesheet from a CDN.
+    katex_dir: PathBuf,
     output_dir: PathBuf,
+    /// Fail the build on problems like broken links, instead of just warning.
+    strict: bool,
+    /// Empty the output directory before building.
+    clean: bool,
+    /// Include pages dated in the future, whatever the config says.
+    future: bool,
+    /// Report how long each stage of the build took.
+    timings: bool,
 }
 
 impl Processor {
@@ -60,170 +258,986 @@ impl Processor {
         Self {
             config_file: args.input_dir.join("config.yaml"),
             content_dir: args.input_dir.join("content"),
+            data_dir: args.input_dir.join("data"),
             static_dir: args.input_dir.join("static"),
             template_dir: args.input_dir.join("templates"),
+            shortcode_dir: args.input_dir.join("shortcodes"),
+            katex_dir: args.input_dir.join("katex"),
             output_dir: args.output_dir,
+            strict: args.strict,
+            clean: args.clean,
+            future: args.future,
+            timings: args.timings,
+        }
+    }
+
+    /// Read the config, adding a warning for each key it doesn't know.
+    fn config(&self, warnings: &mut Warnings) -> anyhow::Result<Config> {
+        let mut config = if fs::exists(&self.config_file)? {
+            let yaml = fs::read_to_string(&self.config_file)?;
+            let config = Config::try_from_yaml(&yaml)?;
+            for key in config.unknown_keys(&yaml) {
+                warnings.push(&self.config_file, format!("ignoring unknown key `{key}`"));
+            }
+            config
+        } else {
+            Config::default()
+        };
+        config.future |= self.future;
+        Ok(config)
+    }
+
+    /// Hash everything which all pages depend on, for the cache.
+    fn hash_site(
+        &self,
+        site_map: &SiteMap,
+        assets: &BTreeMap<String, String>,
+    ) -> anyhow::Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        cfg!(feature = "math").hash(&mut hasher);
+        cfg!(feature = "highlight").hash(&mut hasher);
+        fs::read(&self.config_file).ok().hash(&mut hasher);
+        hash_dir(&mut hasher, &self.template_dir)?;
+        hash_dir(&mut hasher, &self.data_dir)?;
+        hash_dir(&mut hasher, &self.shortcode_dir)?;
+        self.katex_dir.is_dir().hash(&mut hasher);
+        site_map.hash_links(&mut hasher);
+        assets.hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    fn options(&self, config: &Config, warnings: &mut Warnings) -> anyhow::Result<Options> {
+        let include_dirs = vec![self.data_dir.clone(), self.content_dir.clone()];
+        let mut config_warnings = Vec::new();
+        let mut options = Options::from_config(config, include_dirs, &mut config_warnings);
+        for warning in config_warnings {
+            warnings.push(&self.config_file, warning);
         }
+        options.shortcodes = templates::shortcodes(&self.shortcode_dir, config)?;
+        Ok(options)
     }
 
-    fn config(&self) -> anyhow::Result<Config> {
-        if !fs::exists(&self.config_file)? {
-            return Ok(Default::default());
+    /// Check the site for problems, printing them out.
+    ///
+    /// Errors make this fail, and so do warnings, in strict mode.
+    fn check(&self) -> anyhow::Result<()> {
+        let mut warnings = Warnings::default();
+        let config = self.config(&mut warnings)?;
+        let options = self.options(&config, &mut warnings)?;
+        let mut report = check::check(&config, &self.content_dir, &options)?;
+        // Problems with the config come first, since they affect everything else.
+        let config_issues = warnings.iter().map(|(path, warning)| Issue {
+            severity: Severity::Warning,
+            path: path.unwrap_or(&self.config_file).to_path_buf(),
+            diagnostic: warning.clone(),
+        });
+        report.issues.splice(0..0, config_issues);
+        for issue in &report.issues {
+            eprintln!("{issue}");
         }
-        let yaml = fs::read_to_string(&self.config_file)?;
-        Config::try_from_yaml(&yaml)
+        let errors = report.count(Severity::Error);
+        let warnings = report.count(Severity::Warning);
+        println!("{errors} errors, {warnings} warnings");
+        if errors > 0 || ((self.strict || config.strict) && warnings > 0) {
+            return Err(anyhow!("check failed"));
+        }
+        Ok(())
     }
 
-    fn copy_static_files(&self) -> anyhow::Result<()> {
+    fn copy_static_files(&self, config: &Config, warnings: &mut Warnings) -> anyhow::Result<()> {
         if self.static_dir.is_dir() {
-            copy_dir(&self.static_dir, &self.output_dir.join("static"))?;
+            copy_dir_with(
+                &self.static_dir,
+                &self.output_dir.join("static"),
+                warnings,
+                &mut |from, to, warnings| {
+                    assets::copy_static_file(from, to, config.minify, warnings)
+                },
+            )?;
         }
         Ok(())
     }
 
+    /// Empty the output directory, making sure that this won't delete the site itself.
+    fn clean_output(&self) -> anyhow::Result<()> {
+        if !self.output_dir.is_dir() {
+            return Ok(());
+        }
+        let output_dir = self.output_dir.canonicalize()?;
+        let inputs = [
+            ("content", &self.content_dir),
+            ("static files", &self.static_dir),
+            ("config", &self.config_file),
+            ("templates", &self.template_dir),
+            ("data", &self.data_dir),
+            ("shortcodes", &self.shortcode_dir),
+            ("KaTeX files", &self.katex_dir),
+        ];
+        for (name, input) in inputs {
+            // Inputs which don't exist can't be deleted.
+            let Ok(input) = input.canonicalize() else {
+                continue;
+            };
+            if input.starts_with(&output_dir) || output_dir.starts_with(&input) {
+                return Err(anyhow!(
+                    "refusing to clean {}, since it overlaps with the {name}",
+                    self.output_dir.display()
+                ));
+            }
+        }
+        clean_dir(&output_dir)
+    }
+
     fn run(self) -> anyhow::Result<()> {
-        let config = self.config()?;
+        let start = Instant::now();
+        let mut timings = Timings::default();
+        // Shown together at the end, where they're harder to miss.
+        let mut warnings = Warnings::default();
+        let config = self.config(&mut warnings)?;
+        if self.clean || config.clean_output {
+            self.clean_output()?;
+        }
 
-        let env = Environment::new();
+        if config.minify && !assets::CAN_MINIFY {
+            warnings.push_site(
+                "`minify` requires clog to be built with the `minify` feature".to_string(),
+            );
+        }
+        // Templates refer to the processed files, so these need to be ready first.
+        self.copy_static_files(&config, &mut warnings)?;
+        let out_static_dir = self.output_dir.join("static");
+        let mut assets = BTreeMap::new();
+        if config.fingerprint {
+            assets = assets::fingerprint_dir(&self.static_dir, &out_static_dir)?;
+        }
+        assets.extend(assets::write_bundles(
+            &self.static_dir,
+            &out_static_dir,
+            &config.bundles,
+            config.minify,
+            &mut warnings,
+        )?);
+        // A stylesheet of the site's own replaces the one for the highlighting theme.
+        let own_highlight_css = self.static_dir.join(&HIGHLIGHT_CSS[1..]).is_file();
+        if own_highlight_css {
+            assets
+                .entry(HIGHLIGHT_CSS[1..].to_string())
+                .or_insert_with(|| format!("/static{HIGHLIGHT_CSS}"));
+        }
+        let env = templates::environment(&self.template_dir, &config, &assets)?;
+        let content_template = env.get_template("index.html")?;
+        let list_template = templates::optional_template(&env, "list.html")?;
+        let tags_template = templates::optional_template(&env, "tags.html")?;
+        let not_found_template = templates::optional_template(&env, "404.html")?;
 
-        let content_template_data = fs::read_to_string(self.template_dir.join("index.html"))?;
-        let list_template_data = {
-            let path = self.template_dir.join("list.html");
-            if fs::exists(&path)? {
-                Some(fs::read_to_string(&path)?)
-            } else {
-                None
-            }
+        let site_map = timed(&mut timings.sitemap, || {
+            SiteMap::build(&config, &self.content_dir, &self.output_dir)
+        })?;
+        for (path, warning) in site_map.warnings() {
+            warnings.push(path, warning.clone());
+        }
+        // Other policies choose between pages on purpose, or report the links needing a path.
+        let duplicates = match config.duplicate_names {
+            DuplicateNames::Newest => site_map.duplicate_names().collect(),
+            _ => Vec::new(),
         };
-        let content_template = env.template_from_str(&content_template_data)?;
-        let list_template = list_template_data
-            .as_ref()
-            .map(|x| env.template_from_str(x))
-            .transpose()?;
-
-        let site_map = SiteMap::build(&config, &self.content_dir, &self.output_dir)?;
+        for (name, pages) in duplicates {
+            for page in pages {
+                let warning = format!("the name `{name}` is shared with other pages");
+                warnings.push(&page.in_path, warning);
+            }
+        }
 
         for file in site_map.statics() {
             if let Some(parent) = file.out_path.parent() {
                 fs::create_dir_all(parent)?;
             }
-            fs::copy(&file.in_path, &file.out_path)?;
+            copy_if_changed(&file.in_path, &file.out_path)?;
+        }
+        if let Some(tags_template) = tags_template {
+            let mut tags = site_map
+                .pages_by_tag()
+                .map(|(tag, pages)| {
+                    let count = pages.filter(|page| page.front_matter.listed()).count();
+                    (tag, count)
+                })
+                .filter(|(_, count)| *count > 0)
+                .collect::<Vec<_>>();
+            tags.sort_by_key(|(tag, _)| tag.to_lowercase());
+            let tree = tag_tree(&tags);
+            let tags = tags
+                .into_iter()
+                .map(|(tag, count)| {
+                    context! {
+                        name => tag,
+                        count => count,
+                        url => tag_url(tag)
+                    }
+                })
+                .collect::<Vec<_>>();
+            let out_path = self.output_dir.join("tags").join("index.html");
+            fs::create_dir_all(self.output_dir.join("tags"))?;
+            let url = "/tags/";
+            let ctx = context! {
+              title => "Tags",
+              tags => tags,
+              tree => tree,
+              base_url => config.base_url,
+              canonical_url => config.absolute_url(url),
+              url => url
+            };
+            write_if_changed(out_path, tags_template.render(ctx)?)?;
+        }
+        // A `content/404.md` gets rendered like any other page instead.
+        if let Some(not_found_template) = not_found_template
+            && !site_map.pages().any(|page| page.link == NOT_FOUND_LINK)
+        {
+            let ctx = context! {
+              title => "Page not found",
+              base_url => config.base_url,
+              url => NOT_FOUND_LINK
+            };
+            let out_path = self.output_dir.join(NOT_FOUND_LINK.trim_start_matches('/'));
+            write_if_changed(out_path, not_found_template.render(ctx)?)?;
+        }
+
+        let offset = config.utc_offset()?.unwrap_or(UtcOffset::UTC);
+        let feed_base_url = config.base_url.as_deref().filter(|_| config.feeds);
+        if config.feeds && feed_base_url.is_none() {
+            warnings.push_site("`feeds` requires `base_url` to be set".to_string());
         }
+        let activitypub_base_url = config
+            .base_url
+            .as_deref()
+            .filter(|_| config.activitypub.is_some());
+        if config.activitypub.is_some() && activitypub_base_url.is_none() {
+            warnings.push_site("`activitypub` requires `base_url` to be set".to_string());
+        }
+        let keep_bodies =
+            feed_base_url.is_some() || activitypub_base_url.is_some() || config.search_index;
+        let mut page_bodies = HashMap::new();
+        let mut page_excerpts = HashMap::new();
+        let h_card = config
+            .author
+            .as_ref()
+            .map(|author| h_card(author, &config.url("/")));
+        let cache_path = self.output_dir.join(CACHE_FILE);
+        let old_cache = Cache::load(&cache_path, &mut warnings);
+        let site_hash = self.hash_site(&site_map, &assets)?;
+        let mut cache = Cache::new(site_hash);
+        let mut broken_links = 0;
+        let mut needs_math = false;
+        let katex_css = if self.katex_dir.is_dir() {
+            "/static/katex/katex.min.css"
+        } else {
+            KATEX_CDN_CSS
+        };
+        let math_cache_path = self.output_dir.join(MATH_CACHE_FILE);
+        let math_cache = Arc::new(if config.math_cache {
+            MathCache::load(&math_cache_path, &mut warnings)
+        } else {
+            MathCache::default()
+        });
+        let pages = site_map.pages().collect::<Vec<_>>();
+        let options = self.options(&config, &mut warnings)?;
+        if config.highlight_style == HighlightStyle::Classes
+            && !own_highlight_css
+            && let Some(css) = highlight_stylesheet(
+                &options.highlight_theme,
+                options.highlight_dark_theme.as_deref(),
+            )
+        {
+            write_if_changed(self.output_dir.join(&HIGHLIGHT_CSS[1..]), css)?;
+        }
+        let site_dir = self.config_file.parent().unwrap_or(Path::new("."));
+        pipeline::run(
+            &pages,
+            |page| {
+                let mut read = Duration::ZERO;
+                let content = timed(&mut read, || fs::read_to_string(&page.in_path))?;
+                Ok((content, read))
+            },
+            || MathContext::with_cache(Arc::clone(&math_cache)),
+            |math_ctx, page, (content, read)| {
+                let mut times = PageTimings {
+                    read,
+                    ..Default::default()
+                };
+                let hash = {
+                    let mut hasher = DefaultHasher::new();
+                    (&content, format!("{:?}", page.front_matter)).hash(&mut hasher);
+                    site_map.hash_embeds(page, &mut hasher)?;
+                    hasher.finish()
+                };
+                // Files included by shortcodes aren't part of the page, so they get checked apart.
+                if let Some(cached) = old_cache
+                    .get(site_hash, &page.in_path, hash)
+                    .filter(|_| page.out_path.is_file())
+                    .filter(|cached| {
+                        cached
+                            .includes
+                            .iter()
+                            .all(|(name, hash)| options.hash_include(name) == *hash)
+                    })
+                {
+                    return Ok(Rendered {
+                        html: None,
+                        body: cached.body.clone(),
+                        excerpt: cached.excerpt.clone(),
+                        warnings: cached.warnings.clone(),
+                        broken_links: cached.broken_links,
+                        math: cached.math,
+                        includes: cached.includes.clone(),
+                        timings: times,
+                        hash,
+                    });
+                }
+                let mut md = timed(&mut times.parse, || make_mdast(&content))?;
+                let plugin_context = timed(&mut times.plugins, || {
+                    plugin::run(&config.plugins, site_dir, page, &mut md)
+                })?;
+                adjust_headings(&mut md, page, &options);
+                let (log, body) = timed(&mut times.markdown, || {
+                    let mut buf = Vec::with_capacity(1 << 14);
+                    let log =
+                        write_md_ast(&mut buf, &site_map, Some(page), math_ctx, &options, &md)?;
+                    anyhow::Ok((log, String::from_utf8(buf)?))
+                })
+                .map_err(|e| {
+                    let diagnostic = Diagnostic::from(e).with_source(&content);
+                    anyhow!("{}", diagnostic.show_in(&page.in_path))
+                })?;
+                let excerpt = timed(&mut times.markdown, || {
+                    let Some(excerpt) = excerpt_ast(&md) else {
+                        return anyhow::Ok(None);
+                    };
+                    let mut buf = Vec::with_capacity(1 << 10);
+                    write_md_ast(
+                        &mut buf,
+                        &site_map,
+                        Some(page),
+                        math_ctx,
+                        &options,
+                        &excerpt,
+                    )?;
+                    anyhow::Ok(Some(String::from_utf8(buf)?.trim().to_string()))
+                })?;
+                times.math = math_ctx.take_elapsed();
+                times.markdown = times.markdown.saturating_sub(times.math);
+                let mut warnings = log
+                    .headings
+                    .iter()
+                    .map(|issue| Diagnostic::new(issue.to_string()))
+                    .chain(
+                        log.broken_links
+                            .iter()
+                            .map(|link| link.diagnostic(&content)),
+                    )
+                    .chain(log.warnings.into_iter().map(|x| x.with_source(&content)))
+                    .collect::<Vec<_>>();
+                if config.validate_html {
+                    warnings.extend(
+                        html_check::check_well_formed(&body)
+                            .into_iter()
+                            .map(|issue| Diagnostic::new(format!("malformed HTML: {issue}"))),
+                    );
+                }
+                let page_body = if config.microformats {
+                    let url = config.url(&page.link);
+                    let data = EntryData {
+                        title: &page.front_matter.title,
+                        url: &url,
+                        published: page
+                            .front_matter
+                            .published
+                            .as_deref()
+                            .unwrap_or(&page.front_matter.date),
+                        authors: &page.front_matter.authors,
+                        site_author: config.author.as_ref(),
+                    };
+                    Cow::Owned(h_entry(&data, &body))
+                } else {
+                    Cow::Borrowed(body.as_str())
+                };
+                let backlinks = site_map
+                    .backlinks(page)
+                    .map(|linking_page| {
+                        context! {
+                            title => linking_page.front_matter.title,
+                            link => linking_page.link,
+                            date => linking_page.front_matter.date
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let related = site_map
+                    .related(page)
+                    .map(|other| {
+                        context! {
+                            title => other.front_matter.title,
+                            link => other.link,
+                            date => other.front_matter.date
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let translations = site_map
+                    .translations(page)
+                    .map(|other| {
+                        context! {
+                            lang => other.lang,
+                            title => other.front_matter.title,
+                            link => other.link
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let series = site_map.series_of(page);
+                let position = series.iter().position(|x| x.in_path == page.in_path);
+                let series_link = |other: &Page| {
+                    context! {
+                        title => other.front_matter.title,
+                        link => other.link,
+                        current => other.in_path == page.in_path
+                    }
+                };
+                let series_prev = position
+                    .and_then(|i| i.checked_sub(1))
+                    .map(|i| series_link(series[i]));
+                let series_next = position
+                    .and_then(|i| series.get(i + 1))
+                    .map(|other| series_link(other));
+                let series_pages = series.iter().map(|x| series_link(x)).collect::<Vec<_>>();
+                let description = match &page.front_matter.summary {
+                    Some(summary) => summary.clone(),
+                    None => extract_description(&md, 160),
+                };
+                let ctx = context! {
+                  body => page_body,
+                  needs_math => log.math,
+                  katex_css => katex_css,
+                  equations => log.equations,
+                  toc => log.toc,
+                  title => page.front_matter.title,
+                  date => page.front_matter.date,
+                  authors => page.front_matter.authors,
+                  published => page.front_matter.published,
+                  link => page.front_matter.link,
+                  tags => page.front_matter.tags,
+                  unlisted => page.front_matter.unlisted,
+                  summary => page.front_matter.summary,
+                  cover => page.cover.as_deref().map(|x| meta::image_url(&config, x)),
+                  plugin => plugin_context,
+                  lang => page.lang,
+                  translations => translations,
+                  backlinks => backlinks,
+                  related => related,
+                  series => page.front_matter.series,
+                  series_url => page.front_matter.series.as_deref().map(series_url),
+                  series_pages => series_pages,
+                  series_prev => series_prev,
+                  series_next => series_next,
+                  url => page.link,
+                  base_url => config.base_url,
+                  canonical_url => config.absolute_url(&page.link),
+                  meta => meta::page_meta(&config, page, &description),
+                  description => description,
+                  h_card => h_card
+                };
+                let html = timed(&mut times.template, || content_template.render(ctx))?;
+                Ok(Rendered {
+                    html: Some(html),
+                    body,
+                    excerpt,
+                    warnings,
+                    broken_links: log.broken_links.len(),
+                    math: log.math,
+                    includes: log
+                        .includes
+                        .iter()
+                        .map(|name| (name.clone(), options.hash_include(name)))
+                        .collect(),
+                    timings: times,
+                    hash,
+                })
+            },
+            |page, rendered| {
+                for warning in &rendered.warnings {
+                    warnings.push(&page.in_path, warning.clone());
+                }
+                let mut times = rendered.timings;
+                if let Some(html) = rendered.html {
+                    timed(&mut times.write, || {
+                        if let Some(parent) = page.out_path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        write_if_changed(&page.out_path, html)
+                    })?;
+                }
+                timings.add_page(&page.in_path, times);
+                if keep_bodies && page.front_matter.listed() {
+                    page_bodies.insert(page.in_path.as_path(), rendered.body.clone());
+                }
+                if let Some(excerpt) = &rendered.excerpt {
+                    page_excerpts.insert(page.in_path.as_path(), excerpt.clone());
+                }
+                broken_links += rendered.broken_links;
+                needs_math |= rendered.math;
+                let cached = CachedPage {
+                    hash: rendered.hash,
+                    body: rendered.body,
+                    excerpt: rendered.excerpt,
+                    warnings: rendered.warnings,
+                    broken_links: rendered.broken_links,
+                    math: rendered.math,
+                    includes: rendered.includes,
+                };
+                cache.insert(page.in_path.clone(), cached);
+                Ok(())
+            },
+        )?;
+        if needs_math && self.katex_dir.is_dir() {
+            copy_dir(
+                &self.katex_dir,
+                &self.output_dir.join("static").join("katex"),
+                &mut warnings,
+            )?;
+        }
+
         if let Some(list_template) = list_template {
+            let archives = site_map.archives(config.monthly_archives);
+            let is_language = |folder: &Path| {
+                config
+                    .languages
+                    .iter()
+                    .any(|lang| folder == Path::new(lang))
+            };
             let work = site_map
                 .folders()
+                // Language folders get a list of all the pages in the language instead.
+                .filter(|(folder, _)| !is_language(folder))
                 .map(|(folder, pages)| {
-                    let slugified = slugify_path(folder);
-                    let out_path = self.output_dir.join(&slugified).join("index.html");
-                    let url = format!("/{}/", slugified.display());
+                    let rel_path = output_path(folder, config.keep_paths);
+                    let out_path = self.output_dir.join(&rel_path).join("index.html");
+                    let url = format!("{}/", path_link(&rel_path));
                     let iter: Box<dyn Iterator<Item = &'_ Page>> = Box::new(pages);
                     (out_path, folder.to_string_lossy(), url, iter)
                 })
+                .chain(
+                    site_map
+                        .pages_by_language()
+                        .filter(|(lang, _)| config.languages.iter().any(|x| x == lang))
+                        .map(|(lang, pages)| {
+                            let slugified = slugify(lang);
+                            let out_path = self.output_dir.join(&slugified).join("index.html");
+                            let url = format!("/{slugified}/");
+                            let iter: Box<dyn Iterator<Item = &'_ Page>> = Box::new(pages);
+                            (out_path, Cow::Borrowed(lang), url, iter)
+                        }),
+                )
                 .chain(site_map.pages_by_tag().map(|(tag, pages)| {
-                    let slugified_tag = slugify(tag);
                     let out_path = self
                         .output_dir
                         .join("tag")
-                        .join(&slugified_tag)
+                        .join(tag_slug(tag))
                         .join("index.html");
-                    let url = format!("/tag/{}/", slugified_tag);
+                    let url = tag_url(tag);
                     let iter: Box<dyn Iterator<Item = &'_ Page>> = Box::new(pages);
                     (out_path, Cow::Owned(format!("Tag - #{tag}")), url, iter)
+                }))
+                .chain(site_map.pages_by_series().map(|(series, pages)| {
+                    let url = series_url(series);
+                    let out_path = self
+                        .output_dir
+                        .join("series")
+                        .join(slugify(series))
+                        .join("index.html");
+                    let iter: Box<dyn Iterator<Item = &'_ Page>> = Box::new(pages);
+                    (
+                        out_path,
+                        Cow::Owned(format!("Series - {series}")),
+                        url,
+                        iter,
+                    )
+                }))
+                .chain(archives.into_iter().map(|(key, pages)| {
+                    let out_path = self
+                        .output_dir
+                        .join("archive")
+                        .join(&key)
+                        .join("index.html");
+                    let url = format!("/archive/{key}/");
+                    let iter: Box<dyn Iterator<Item = &'_ Page>> = Box::new(pages.into_iter());
+                    (out_path, Cow::Owned(format!("Archive - {key}")), url, iter)
                 }));
             for (out_path, title, url, pages) in work {
                 let items = pages
                     .filter_map(|page| {
-                        if page.front_matter.draft {
+                        if !page.front_matter.listed() {
                             return None;
                         }
                         Some(context! {
                             title => page.front_matter.title,
                             date => page.front_matter.date,
                             link => page.link,
-                            tags => page.front_matter.tags
+                            tags => page.front_matter.tags,
+                            summary => page.front_matter.summary,
+                            cover => page.cover.as_deref().map(|x| meta::image_url(&config, x)),
+                            excerpt => page_excerpts.get(page.in_path.as_path())
                         })
                     })
                     .collect::<Vec<_>>();
                 if let Some(parent) = out_path.parent() {
                     fs::create_dir_all(parent)?;
                 }
-                let file = fs::File::create(&out_path)?;
-                let mut writer = BufWriter::new(file);
                 let ctx = context! {
                   title => title,
                   items => items,
+                  base_url => config.base_url,
+                  canonical_url => config.absolute_url(&url),
                   url => url
                 };
-                list_template.render_to_write(ctx, &mut writer)?;
-                writer.flush()?;
+                write_if_changed(&out_path, list_template.render(ctx)?)?;
             }
         }
-
-        let mut buf = Vec::with_capacity(1 << 14);
-        let katex_ctx = katex::KatexContext::default();
+        let page_out_paths = site_map
+            .pages()
+            .map(|page| page.out_path.as_path())
+            .collect::<HashSet<_>>();
+        let mut redirects = Vec::new();
         for page in site_map.pages() {
-            let content = fs::read_to_string(&page.in_path)?;
-            let md = make_mdast(&content)?;
-            let (log, body) = {
-                buf.clear();
-                let log = write_md_ast(&mut buf, &site_map, &katex_ctx, &md)?;
-                (log, String::from_utf8_lossy(&buf))
+            for redirect in &page.redirects {
+                if page_out_paths.contains(redirect.out_path.as_path()) {
+                    let warning = format!(
+                        "alias would overwrite another page at {}",
+                        redirect.out_path.display()
+                    );
+                    warnings.push(&page.in_path, warning);
+                    continue;
+                }
+                if config.redirects_file.is_some() {
+                    redirects.push((redirect.link.as_str(), page.link.as_str()));
+                    continue;
+                }
+                if let Some(parent) = redirect.out_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_if_changed(&redirect.out_path, redirect_page(&config.url(&page.link)))?;
+            }
+        }
+        if let Some(kind) = config.redirects_file {
+            let (name, contents) = redirects_file(kind, &redirects)?;
+            write_if_changed(self.output_dir.join(name), contents)?;
+        }
+        // Pages dated in the future might be there from a build with `--future`, or an earlier date.
+        for path in site_map.scheduled_outputs() {
+            if path.is_file() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        let mut pages_by_date = site_map.pages().collect::<Vec<_>>();
+        pages_by_date.sort_by(|a, b| b.front_matter.date.cmp(&a.front_matter.date));
+
+        if let Some(base_url) = feed_base_url {
+            let feed = |path| Feed {
+                title: config.title.as_deref().unwrap_or(base_url),
+                description: config.description.as_deref(),
+                base_url,
+                author: config.author.as_ref(),
+                language: config.language.as_deref(),
+                offset,
+                path,
+                entries: feed_entries(pages_by_date.iter().copied(), &page_bodies),
             };
-            let backlinks = site_map
-                .backlinks(page)
-                .map(|linking_page| {
-                    context! {
-                        title => linking_page.front_matter.title,
-                        link => linking_page.link
-                    }
-                })
-                .collect::<Vec<_>>();
-            if let Some(parent) = page.out_path.parent() {
-                fs::create_dir_all(parent)?;
+            write_if_changed(
+                self.output_dir.join("atom.xml"),
+                feed("/atom.xml").to_atom()?,
+            )?;
+            write_if_changed(self.output_dir.join("rss.xml"), feed("/rss.xml").to_rss()?)?;
+            write_if_changed(
+                self.output_dir.join("feed.json"),
+                feed("/feed.json").to_json()?,
+            )?;
+            for (author, pages) in site_map.pages_by_author() {
+                let slugified_author = slugify(author);
+                let path = format!("/author/{slugified_author}/atom.xml");
+                let title = format!("{} - {author}", config.title.as_deref().unwrap_or(base_url));
+                let feed = Feed {
+                    title: &title,
+                    description: config.description.as_deref(),
+                    base_url,
+                    author: None,
+                    language: config.language.as_deref(),
+                    offset,
+                    path: &path,
+                    entries: feed_entries(pages, &page_bodies),
+                };
+                let out_dir = self.output_dir.join("author").join(&slugified_author);
+                fs::create_dir_all(&out_dir)?;
+                write_if_changed(out_dir.join("atom.xml"), feed.to_atom()?)?;
             }
-            let file = fs::File::create(&page.out_path)?;
-            let mut writer = BufWriter::new(file);
-            let description = extract_description(&md, 160);
-            let ctx = context! {
-              body => body,
-              math => log.math,
-              title => page.front_matter.title,
-              date => page.front_matter.date,
-              authors => page.front_matter.authors,
-              published => page.front_matter.published,
-              link => page.front_matter.link,
-              tags => page.front_matter.tags,
-              backlinks => backlinks,
-              url => page.link,
-              description => description
+            for (lang, pages) in site_map.pages_by_language() {
+                if !config.languages.iter().any(|x| x == lang) {
+                    continue;
+                }
+                let slugified_lang = slugify(lang);
+                let path = format!("/{slugified_lang}/atom.xml");
+                let title = format!("{} - {lang}", config.title.as_deref().unwrap_or(base_url));
+                let feed = Feed {
+                    title: &title,
+                    description: config.description.as_deref(),
+                    base_url,
+                    author: config.author.as_ref(),
+                    language: Some(lang),
+                    offset,
+                    path: &path,
+                    entries: feed_entries(pages, &page_bodies),
+                };
+                let out_dir = self.output_dir.join(&slugified_lang);
+                fs::create_dir_all(&out_dir)?;
+                write_if_changed(out_dir.join("atom.xml"), feed.to_atom()?)?;
+            }
+        }
+
+        if let (Some(base_url), Some(activitypub)) = (activitypub_base_url, &config.activitypub) {
+            let actor = Actor {
+                config: activitypub,
+                name: config.title.as_deref().unwrap_or(&activitypub.username),
+                base_url,
+                offset,
             };
-            content_template.render_to_write(ctx, &mut writer)?;
-            writer.flush()?;
+            let entries = feed_entries(pages_by_date.iter().copied(), &page_bodies);
+            let well_known = self.output_dir.join(".well-known");
+            fs::create_dir_all(&well_known)?;
+            write_if_changed(
+                well_known.join("webfinger"),
+                serde_json::to_string_pretty(&actor.webfinger()?)?,
+            )?;
+            let actor_path = self.output_dir.join(ACTOR_PATH.trim_start_matches('/'));
+            if let Some(parent) = actor_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_if_changed(actor_path, serde_json::to_string_pretty(&actor.actor())?)?;
+            write_if_changed(
+                self.output_dir.join(OUTBOX_PATH.trim_start_matches('/')),
+                serde_json::to_string_pretty(&actor.outbox(&entries))?,
+            )?;
         }
 
-        self.copy_static_files()?;
+        if config.search_index {
+            let entries = site_map
+                .pages()
+                .filter_map(|page| {
+                    let body = page_bodies.get(page.in_path.as_path())?;
+                    Some(SearchEntry {
+                        title: &page.front_matter.title,
+                        link: &page.link,
+                        tags: &page.front_matter.tags,
+                        date: &page.front_matter.date,
+                        body: html_to_text(body),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let index = serde_json::to_string(&entries)?;
+            write_if_changed(self.output_dir.join("search-index.json"), index)?;
+        }
 
-        if let Some(base_url) = &config.base_url {
-            let mut sitemap = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+        if config.graph {
+            let graph = serde_json::to_string(&graph::graph(&site_map))?;
+            write_if_changed(self.output_dir.join("graph.json"), graph)?;
+        }
+
+        if config.base_url.is_some() {
+            let mut sitemap = String::from(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+            );
             for page in site_map.pages() {
-                if page.front_matter.draft {
+                if !page.front_matter.listed() {
                     continue;
                 }
                 sitemap.push_str(&format!(
-                    "<url><loc>{}{}</loc><lastmod>{}</lastmod></url>\n",
-                    base_url, page.link, page.front_matter.date
+                    "<url><loc>{}</loc><lastmod>{}</lastmod>",
+                    escape_attr(&config.url(&page.link)),
+                    escape_attr(&page.front_matter.date)
                 ));
+                let folder = page.folder(&self.content_dir)?.unwrap_or_default();
+                let settings = page
+                    .front_matter
+                    .sitemap
+                    .clone()
+                    .or(&config.sitemap_settings(&folder));
+                if let Some(changefreq) = settings.changefreq {
+                    if CHANGEFREQS.contains(&changefreq.as_str()) {
+                        sitemap.push_str(&format!("<changefreq>{changefreq}</changefreq>"));
+                    } else {
+                        let warning = format!("invalid changefreq `{changefreq}`");
+                        warnings.push(&page.in_path, warning);
+                    }
+                }
+                if let Some(priority) = settings.priority {
+                    if (0.0..=1.0).contains(&priority) {
+                        sitemap.push_str(&format!("<priority>{priority}</priority>"));
+                    } else {
+                        let warning = format!("sitemap priority {priority} is not between 0 and 1");
+                        warnings.push(&page.in_path, warning);
+                    }
+                }
+                sitemap.push_str("</url>\n");
             }
             sitemap.push_str("</urlset>\n");
-            fs::write(self.output_dir.join("sitemap.xml"), sitemap)?;
+            write_if_changed(self.output_dir.join("sitemap.xml"), sitemap)?;
+        }
+
+        cache.save(&cache_path)?;
+        if config.math_cache {
+            math_cache.save(&math_cache_path)?;
+        }
+
+        if self.timings {
+            timings.total = start.elapsed();
+            eprint!("{timings}");
+        }
+
+        if !warnings.is_empty() {
+            eprint!("{warnings}");
+        }
+        if (self.strict || config.strict) && broken_links > 0 {
+            return Err(anyhow!("strict mode: {broken_links} unresolved wikilinks"));
+        }
+        if config.warnings_as_errors && !warnings.is_empty() {
+            return Err(anyhow!("`warnings_as_errors`: {} warnings", warnings.len()));
         }
 
         Ok(())
     }
 }
 
+/// The URL of the index page for a series.
+fn series_url(series: &str) -> String {
+    format!("/series/{}/", slugify(series))
+}
+
+/// Create feed entries for some pages, skipping pages without a body, like drafts and unlisted pages.
+fn feed_entries<'a>(
+    pages: impl Iterator<Item = &'a Page>,
+    bodies: &'a HashMap<&Path, String>,
+) -> Vec<Entry<'a>> {
+    pages
+        .filter_map(|page| {
+            let body = bodies.get(page.in_path.as_path())?;
+            Some(Entry {
+                title: &page.front_matter.title,
+                link: &page.link,
+                published: page
+                    .front_matter
+                    .published
+                    .as_deref()
+                    .unwrap_or(&page.front_matter.date),
+                updated: &page.front_matter.date,
+                authors: &page.front_matter.authors,
+                summary: page.front_matter.summary.as_deref(),
+                cover: page.cover.as_deref(),
+                content: body,
+            })
+        })
+        .collect()
+}
+
 fn main() -> anyhow::Result<()> {
     let args = Args::parse()?;
-    let processor = Processor::new(args);
-    processor.run()
+    match args.command.clone() {
+        Command::Build => Processor::new(args).run(),
+        Command::Serve { port } => {
+            let output_dir = args.output_dir.clone();
+            Processor::new(args.clone()).run()?;
+            let reload = Arc::new(serve::Reload::default());
+            {
+                let reload = Arc::clone(&reload);
+                thread::spawn(move || {
+                    watch::watch(&args.input_dir, &args.output_dir, || {
+                        println!("rebuilding...");
+                        match Processor::new(args.clone()).run() {
+                            Ok(()) => reload.notify(),
+                            // Keep serving the last build, until the problem gets fixed.
+                            Err(e) => eprintln!("Error: {e:?}"),
+                        }
+                    })
+                });
+            }
+            serve::serve(&output_dir, port, reload)
+        }
+        Command::Check => Processor::new(args).check(),
+        Command::Init => init::init(&args.input_dir),
+        Command::New { title, folder } => {
+            let processor = Processor::new(args);
+            // Problems with the config get shown by building, or checking, instead.
+            let offset = processor.config(&mut Warnings::default())?.utc_offset()?;
+            let path =
+                new_post::new_post(&processor.content_dir, &title, folder.as_deref(), offset)?;
+            println!("created {}", path.display());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Args> {
+        Args::parse_from(args.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn args() {
+        let build = parse(&["in", "out"]).unwrap();
+        assert_eq!(build.command, Command::Build);
+        assert_eq!(build.output_dir, PathBuf::from("out"));
+        assert!(parse(&["in"]).is_err());
+        assert!(!build.strict);
+        assert!(parse(&["--strict", "in", "out"]).unwrap().strict);
+        assert!(parse(&["in", "out", "--clean"]).unwrap().clean);
+        assert!(parse(&["check", "in", "--clean"]).is_err());
+        assert!(parse(&["in", "out", "--future"]).unwrap().future);
+        assert!(parse(&["in", "out", "--timings"]).unwrap().timings);
+        let serve = parse(&["serve", "in", "--port", "3000"]).unwrap();
+        assert_eq!(serve.command, Command::Serve { port: 3000 });
+        assert_eq!(serve.input_dir, PathBuf::from("in"));
+        assert!(parse(&["serve", "in", "--port", "x"]).is_err());
+        assert_eq!(parse(&["check", "in"]).unwrap().command, Command::Check);
+        assert!(parse(&["check", "in", "out"]).is_err());
+        assert!(parse(&["check", "in", "--port", "1"]).is_err());
+        let new = parse(&["new", "My Post", "--folder", "Posts"]).unwrap();
+        assert_eq!(
+            new.command,
+            Command::New {
+                title: "My Post".into(),
+                folder: Some("Posts".into())
+            }
+        );
+        assert_eq!(new.input_dir, PathBuf::from("."));
+    }
+
+    #[test]
+    fn clean_overlap() {
+        let dir = TempDir::new("clean-overlap");
+        let inputs = ["static", "data", "shortcodes", "katex"];
+        for sub in ["content", "templates", "out"].iter().chain(&inputs) {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("config.yaml"), "").unwrap();
+        fs::write(dir.join("out/old.html"), "").unwrap();
+        let site = dir.to_str().unwrap();
+        let clean = |out: &str| Processor::new(parse(&[site, out]).unwrap()).clean_output();
+        let whole_site = clean(site);
+        let inside_inputs = inputs.map(|input| clean(dir.join(input).to_str().unwrap()).is_err());
+        let cleaned = clean(dir.join("out").to_str().unwrap());
+        let old_removed = !dir.join("out/old.html").exists();
+        let kept = dir.join("config.yaml").exists();
+        assert!(whole_site.is_err());
+        assert_eq!(inside_inputs, [true; 4]);
+        assert!(cleaned.is_ok());
+        assert!(old_removed);
+        assert!(kept);
+    }
 }
@@ -1,22 +1,129 @@
 use anyhow::anyhow;
 use markdown::{ParseOptions, mdast, to_mdast};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
 
+mod anchors;
+mod code;
 mod counter;
+mod equations;
+mod graphviz;
+mod hashtags;
+mod highlight;
+mod marks;
+mod math;
+mod outline;
+mod smart;
 
+pub use anchors::TocEntry;
+use anchors::{Anchors, anchor_id, block_anchor, plain_text, split_block_id};
+use code::CodeAttrs;
 use counter::Sequential;
+use equations::{Equations, as_reference, split_references, strip_label};
+pub use hashtags::collect_hashtags;
+use hashtags::split_hashtags;
+pub use highlight::{HIGHLIGHT_CSS, stylesheet as highlight_stylesheet};
+use marks::split_marks;
+pub use math::{KATEX_CDN_CSS, MATH_CACHE_FILE, MathCache, MathContext};
+pub use outline::HeadingIssue;
+use outline::Outline;
 
-use crate::sitemap::SiteMap;
+use crate::cache::hash_of;
+use crate::config::{Config, EquationNumbering, HighlightStyle};
+use crate::csv;
+use crate::diagnostic::{Diagnostic, Location};
+use crate::escape::{escape_attr, escape_html};
+use crate::frontmatter::Source;
+use crate::sanitize::{is_safe_url, sanitize_html};
+use crate::shortcode::{Piece, Shortcode};
+use crate::sitemap::{Page, SiteMap};
+use crate::tags::tag_url;
+use crate::templates;
 use crate::wikilink::{Segment, WikiLink};
 
 #[derive(Default)]
 pub struct Log {
     pub math: bool,
+    /// Whether the page has any footnotes, written in a section at the end.
+    pub has_footnotes: bool,
+    /// The number of each labelled equation on the page.
+    pub equations: BTreeMap<String, String>,
+    /// Problems with the structure of the headings on the page.
+    pub headings: Vec<HeadingIssue>,
+    /// The headings of the page, along with their ids.
+    pub toc: Vec<TocEntry>,
+    /// The wikilinks which didn't resolve to a page, or a file.
+    pub broken_links: Vec<BrokenLink>,
+    /// Other problems with the page, like math which failed to render.
+    pub warnings: Vec<Diagnostic>,
+    /// The files included by shortcodes, like `{{< csv data.csv >}}`, by the name used.
+    pub includes: Vec<String>,
 }
 
 impl Log {
-    pub fn merge(&mut self, other: &Self) {
-        self.math |= other.math;
+    /// Warn about a problem with some node.
+    fn warn_at(&mut self, message: String, node: &mdast::Node) {
+        let mut diagnostic = Diagnostic::new(message);
+        diagnostic.location = node.position().map(|x| Location {
+            line: x.start.line,
+            column: x.start.column,
+        });
+        self.warnings.push(diagnostic);
+    }
+
+    /// Remember that the page includes a file, returning its name.
+    fn include<'a>(&mut self, name: &'a str) -> &'a str {
+        if !self.includes.iter().any(|x| x == name) {
+            self.includes.push(name.to_string());
+        }
+        name
+    }
+
+    /// Warn about math which failed to render, at the node it's in.
+    fn math_failed(&mut self, math_ctx: &MathContext, node: &mdast::Node) {
+        if let Some(error) = math_ctx.take_error() {
+            self.warn_at(format!("failed to render math: {error}"), node);
+        }
+    }
+}
+
+/// A wikilink which didn't resolve to a page, or a file.
+pub struct BrokenLink {
+    pub name: String,
+    /// Where the text containing the link starts, in the source of the page.
+    offset: Option<usize>,
+    /// Whether the name is shared by several pages, needing a path to tell them apart.
+    ambiguous: bool,
+}
+
+impl BrokenLink {
+    /// Point at this link in the source of its page.
+    ///
+    /// Plugins can change the markdown of a page, so this falls back to the start
+    /// of the text containing the link, or nothing, when the link can't be found.
+    pub fn diagnostic(&self, source: &str) -> Diagnostic {
+        let message = if self.ambiguous {
+            format!(
+                "ambiguous wikilink `[[{}]]`, since several pages have that name, add a path, like `[[folder/{}]]`",
+                self.name, self.name
+            )
+        } else {
+            format!("unresolved wikilink `[[{}]]`", self.name)
+        };
+        let Some(start) = self.offset.filter(|&x| source.is_char_boundary(x)) else {
+            return Diagnostic::new(message);
+        };
+        let needle = format!("[[{}", self.name);
+        let Some(offset) = source[start..].find(&needle).map(|i| start + i) else {
+            return Diagnostic::at(message, source, start, 1);
+        };
+        let end = source[offset..]
+            .find("]]")
+            .map_or(offset + needle.len(), |i| offset + i + 2);
+        Diagnostic::at(message, source, offset, source[offset..end].chars().count())
     }
 }
 
@@ -28,26 +135,417 @@ pub fn make_mdast(data: &str) -> anyhow::Result<mdast::Node> {
         out.constructs.frontmatter = true;
         out
     };
-    let ast = to_mdast(data, &options).map_err(|e| anyhow!("failed to parse markdown: {e}"))?;
+    let mut ast = to_mdast(data, &options).map_err(|e| anyhow!("failed to parse markdown: {e}"))?;
+    split_escaped_wikilinks(&mut ast, data);
     Ok(ast)
 }
 
-pub fn write_md_ast<'root>(
+/// Options controlling how markdown gets written to HTML.
+#[derive(Clone)]
+pub struct Options {
+    pub equation_numbering: EquationNumbering,
+    /// Where to look for files included by shortcodes, in order.
+    pub include_dirs: Vec<PathBuf>,
+    /// The theme to highlight code blocks with.
+    pub highlight_theme: String,
+    /// Whether highlighted code uses inline styles, or classes.
+    pub highlight_style: HighlightStyle,
+    /// The theme for code when the reader prefers a dark color scheme, with classes.
+    pub highlight_dark_theme: Option<String>,
+    /// Add a link to itself inside of each heading.
+    pub heading_permalinks: bool,
+    /// Render images on their own as figures, with a caption.
+    pub figures: bool,
+    /// Render `dot` code blocks as SVG, with Graphviz.
+    pub graphviz: bool,
+    /// Use curly quotes, dashes, and ellipses in text.
+    pub smart_punctuation: bool,
+    /// Sanitize raw HTML, and links, removing anything which could run scripts.
+    pub sanitize_html: bool,
+    /// Link inline tags in text, like `#rust`, to the page for their tag.
+    pub inline_tags: bool,
+    /// Leave out the first `# Heading` of pages, when it's their title.
+    pub strip_title_heading: bool,
+    /// How many levels to demote headings by, unless a page has its own offset.
+    pub heading_offset: u8,
+    /// The templates for the shortcodes of the site, like `youtube.html`.
+    pub shortcodes: minijinja::Environment<'static>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            equation_numbering: Default::default(),
+            include_dirs: Vec::new(),
+            highlight_theme: highlight::DEFAULT_THEME.to_string(),
+            highlight_style: HighlightStyle::Inline,
+            highlight_dark_theme: None,
+            heading_permalinks: false,
+            figures: false,
+            graphviz: false,
+            smart_punctuation: false,
+            sanitize_html: false,
+            inline_tags: false,
+            strip_title_heading: false,
+            heading_offset: 0,
+            shortcodes: minijinja::Environment::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Problems with the config, like unknown themes, get added to `warnings`.
+    pub fn from_config(
+        config: &Config,
+        include_dirs: Vec<PathBuf>,
+        warnings: &mut Vec<String>,
+    ) -> Self {
+        let highlight_theme = match &config.highlight_theme {
+            Some(theme) if !highlight::has_theme(theme) => {
+                warnings.push(format!(
+                    "unknown highlight theme `{theme}`, using the default"
+                ));
+                highlight::DEFAULT_THEME.to_string()
+            }
+            Some(theme) => theme.clone(),
+            None => highlight::DEFAULT_THEME.to_string(),
+        };
+        let highlight_dark_theme = match &config.highlight_dark_theme {
+            Some(theme) if !highlight::has_theme(theme) => {
+                warnings.push(format!("unknown highlight theme `{theme}`, ignoring it"));
+                None
+            }
+            Some(_) if config.highlight_style != HighlightStyle::Classes => {
+                let warning = "`highlight_dark_theme` needs `highlight_style: classes`";
+                warnings.push(warning.to_string());
+                None
+            }
+            theme => theme.clone(),
+        };
+        Self {
+            equation_numbering: config.equation_numbering,
+            include_dirs,
+            highlight_theme,
+            highlight_style: config.highlight_style,
+            highlight_dark_theme,
+            heading_permalinks: config.heading_permalinks,
+            figures: config.figures,
+            graphviz: config.graphviz,
+            smart_punctuation: config.smart_punctuation,
+            sanitize_html: config.sanitize_html,
+            inline_tags: config.inline_tags,
+            strip_title_heading: config.strip_title_heading,
+            heading_offset: config.heading_offset,
+            shortcodes: minijinja::Environment::new(),
+        }
+    }
+
+    /// Find a file included by a shortcode.
+    ///
+    /// Paths which lead outside of the directory they're found in, with `..`,
+    /// an absolute path, or a symlink, are rejected, so that a page can't include
+    /// arbitrary files from the machine building the site.
+    fn find_include(&self, path: &str) -> Option<PathBuf> {
+        self.include_dirs.iter().find_map(|dir| {
+            let root = dir.canonicalize().ok()?;
+            let found = dir.join(path).canonicalize().ok()?;
+            (found.starts_with(&root) && found.is_file()).then_some(found)
+        })
+    }
+
+    /// Hash the file a shortcode includes, for the cache, or give `None` if it's missing.
+    pub fn hash_include(&self, path: &str) -> Option<u64> {
+        let data = fs::read(self.find_include(path)?).ok()?;
+        Some(hash_of(&data))
+    }
+}
+
+/// Write the output of a shortcode.
+///
+/// Shortcodes with a template in `shortcodes/` take precedence over the built-in ones.
+/// Unknown shortcodes are written out as text.
+fn write_shortcode(
+    writer: &mut impl io::Write,
+    options: &Options,
+    log: &mut Log,
+    node: &mdast::Node,
+    shortcode: &Shortcode<'_>,
+) -> anyhow::Result<()> {
+    let template_name = format!("{}.html", shortcode.name);
+    if let Some(template) = templates::optional_template(&options.shortcodes, &template_name)? {
+        let html = template
+            .render(shortcode.context())
+            .map_err(|e| anyhow!("failed to render shortcode `{}`: {e}", shortcode.name))?;
+        writer.write_all(html.as_bytes())?;
+        return Ok(());
+    }
+    match (shortcode.name, shortcode.args.as_slice()) {
+        ("csv", [path, ..]) => match options.find_include(log.include(path)) {
+            None => {
+                log.warn_at(format!("failed to find `{path}` for csv shortcode"), node);
+            }
+            Some(path) => {
+                let data = fs::read_to_string(path)?;
+                writer.write_all(csv::to_table(&data).as_bytes())?;
+            }
+        },
+        _ => {
+            writer.write_all(escape_html(shortcode.source).as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Collect the definitions used by reference links, like `[foo]: https://example.com`.
+fn collect_definitions(ast: &mdast::Node) -> HashMap<&str, &mdast::Definition> {
+    let mut out = HashMap::new();
+    let mut q = vec![ast];
+    while let Some(node) = q.pop() {
+        if let mdast::Node::Definition(def) = node {
+            // The first definition of an identifier takes precedence.
+            out.entry(def.identifier.as_str()).or_insert(def);
+        }
+        if let Some(children) = node.children() {
+            q.extend(children.iter().rev());
+        }
+    }
+    out
+}
+
+/// The URL of a link, replaced with `#` if it's unsafe, and the content is being sanitized.
+fn link_url<'a>(options: &Options, url: &'a str) -> &'a str {
+    if options.sanitize_html && !is_safe_url(url) {
+        return "#";
+    }
+    url
+}
+
+/// Format an optional `title` attribute, including a leading space.
+fn title_attr(title: Option<&str>) -> String {
+    title
+        .map(|x| format!(" title=\"{}\"", escape_attr(x)))
+        .unwrap_or_default()
+}
+
+/// The source, alt text, and title of an image node, if it is one.
+fn image_parts<'a>(
+    node: &'a mdast::Node,
+    definitions: &HashMap<&str, &'a mdast::Definition>,
+) -> Option<(&'a str, &'a str, Option<&'a str>)> {
+    match node {
+        mdast::Node::Image(n) => Some((&n.url, &n.alt, n.title.as_deref())),
+        mdast::Node::ImageReference(n) => {
+            let def = definitions.get(n.identifier.as_str())?;
+            Some((&def.url, &n.alt, def.title.as_deref()))
+        }
+        _ => None,
+    }
+}
+
+/// Write an image as a figure, captioned with its title, or its alt text.
+fn write_figure(
+    writer: &mut impl io::Write,
+    src: &str,
+    alt: &str,
+    title: Option<&str>,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "<figure>\n<img src=\"{}\" alt=\"{}\" />",
+        escape_attr(src),
+        escape_attr(alt)
+    )?;
+    match title.unwrap_or(alt) {
+        "" => {}
+        caption => write!(
+            writer,
+            "\n<figcaption>{}</figcaption>",
+            escape_html(caption)
+        )?,
+    }
+    write!(writer, "\n</figure>")
+}
+
+/// The id of the `n`th reference to a footnote, counting from 1, for it to link back to.
+fn footnote_ref_id(identifier: &str, n: usize) -> String {
+    match n {
+        1 => format!("fnref-{identifier}"),
+        _ => format!("fnref-{identifier}-{n}"),
+    }
+}
+
+/// Write an embedded image, like `![[image.png|300]]`.
+fn write_embed(writer: &mut impl io::Write, src: &str, link: &WikiLink<'_>) -> io::Result<()> {
+    let size = link.embed_size();
+    let alt = match (size, link.display) {
+        (None, Some(display)) => display,
+        _ => link.name,
+    };
+    write!(
+        writer,
+        "<img src=\"{}\" alt=\"{}\"",
+        escape_attr(src),
+        escape_attr(alt)
+    )?;
+    if let Some((width, height)) = size {
+        write!(writer, " width=\"{width}\"")?;
+        if let Some(height) = height {
+            write!(writer, " height=\"{height}\"")?;
+        }
+    }
+    write!(writer, " />")
+}
+
+/// Write a reference to an equation, like `\eqref{label}`.
+fn write_equation_ref(
+    writer: &mut impl io::Write,
+    equations: &Equations,
+    log: &mut Log,
+    node: &mdast::Node,
+    label: &str,
+) -> io::Result<()> {
+    match equations.number(label) {
+        None => {
+            log.warn_at(format!("reference to unknown equation `{label}`"), node);
+            write!(writer, "(??)")
+        }
+        Some(number) => write!(
+            writer,
+            "<a class=\"eqref\" href=\"#{}\">({number})</a>",
+            escape_attr(label)
+        ),
+    }
+}
+
+/// An embed of a note standing on its own, like `![[Note]]`, or `![[Note#Section]]`.
+fn transclusion(text: &str) -> Option<WikiLink<'_>> {
+    let mut segments = WikiLink::segment(text.trim());
+    match (segments.next(), segments.next()) {
+        (Some(Segment::Link(link)), None) if link.embed => Some(link),
+        _ => None,
+    }
+}
+
+/// The markdown of a page, or of a section of it, for including in another page.
+///
+/// A section runs from its heading to the next heading at the same level, or above it.
+/// Anchors like `^abc123` refer to the paragraph with that block id instead.
+/// The headings get adjusted like those of the page on its own.
+fn transcluded(
+    page: &Page,
+    anchor: Option<&str>,
+    options: &Options,
+) -> Result<mdast::Node, String> {
+    let source = fs::read_to_string(&page.in_path)
+        .map_err(|e| format!("failed to read `{}`: {e}", page.in_path.display()))?;
+    let mut ast = make_mdast(&source).map_err(|e| e.to_string())?;
+    adjust_headings(&mut ast, page, options);
+    let Some(anchor) = anchor else {
+        return Ok(ast);
+    };
+    let Some(children) = ast.children_mut() else {
+        return Ok(ast);
+    };
+    let id = anchor_id(anchor);
+    let heading = children
+        .iter()
+        .position(|x| matches!(x, mdast::Node::Heading(_)) && anchor_id(&plain_text(x)) == id);
+    let section = match heading {
+        Some(start) => {
+            let depth = |x: &mdast::Node| match x {
+                mdast::Node::Heading(h) => Some(h.depth),
+                _ => None,
+            };
+            let level = depth(&children[start]);
+            let end = children[start + 1..]
+                .iter()
+                .position(|x| depth(x).is_some_and(|x| Some(x) <= level))
+                .map_or(children.len(), |i| start + 1 + i);
+            children.drain(start..end).collect()
+        }
+        None => {
+            let block = anchor.strip_prefix('^');
+            // Blocks can be nested, like an item in a list.
+            let mut q: Vec<&mdast::Node> = children.iter().collect();
+            let mut paragraph = None;
+            while let Some(node) = q.pop() {
+                if let mdast::Node::Paragraph(p) = node
+                    && let Some(mdast::Node::Text(t)) = p.children.last()
+                    && split_block_id(&t.value).is_some_and(|(_, id)| Some(id) == block)
+                {
+                    paragraph = Some(node.clone());
+                    break;
+                }
+                if let Some(children) = node.children() {
+                    q.extend(children.iter().rev());
+                }
+            }
+            match paragraph {
+                Some(paragraph) => vec![paragraph],
+                None => return Err(format!("`{}` has no section `{anchor}`", page.name)),
+            }
+        }
+    };
+    Ok(mdast::Node::Root(mdast::Root {
+        children: section,
+        position: None,
+    }))
+}
+
+/// Write markdown as HTML, with wikilinks resolved from `page`, if it's part of one.
+pub fn write_md_ast(
     writer: &mut impl io::Write,
     site_map: &SiteMap,
-    katex_ctx: &katex::KatexContext,
+    page: Option<&Page>,
+    math_ctx: &MathContext,
+    options: &Options,
+    ast: &mdast::Node,
+) -> anyhow::Result<Log> {
+    let mut nesting = Nesting::default();
+    let mut log = write_within(writer, site_map, page, math_ctx, options, ast, &mut nesting)?;
+    log.headings = nesting.outline.issues();
+    log.toc = nesting.anchors.into_toc();
+    Ok(log)
+}
+
+/// What a page shares with the pages embedded in it.
+#[derive(Default)]
+struct Nesting<'a> {
+    /// The pages including the markdown being written, from the outermost.
+    embedding: Vec<&'a Path>,
+    /// The ids of headings, which can't clash with those of embedded headings.
+    anchors: Anchors,
+    /// The headings so far, including embedded ones.
+    outline: Outline,
+}
+
+/// Write markdown as HTML, possibly as part of another page, with `nesting`.
+fn write_within<'root, 'a>(
+    writer: &mut impl io::Write,
+    site_map: &'a SiteMap,
+    page: Option<&'a Page>,
+    math_ctx: &MathContext,
+    options: &Options,
     ast: &'root mdast::Node,
+    nesting: &mut Nesting<'a>,
 ) -> anyhow::Result<Log> {
     let mut log = Log::default();
+    let equations = Equations::collect(ast, options.equation_numbering);
+    let definitions = collect_definitions(ast);
     enum Work<'a> {
         Node(&'a mdast::Node),
         Lit(&'static str),
         Str(String),
+        /// The end of a link, written as `</a>`.
+        LinkEnd,
     }
 
+    let mut block_marker: Option<*const mdast::Text> = None;
     let mut footnote_ids = Sequential::<&'root str>::default();
     let mut footnote_defs =
         Vec::<Option<(&'root str, &'root [mdast::Node])>>::with_capacity(1 << 6);
+    // How many times each footnote has been referenced, to give each reference an id.
+    let mut footnote_refs = HashMap::<&'root str, usize>::new();
 
     // Work contains unprocessed nodes.
     //
@@ -71,7 +569,48 @@ pub fn write_md_ast<'root>(
         };
     }
     q.push(Work::Node(ast));
-    while let Some(work) = q.pop() {
+    // The last character of text written, for smart punctuation to decide which way quotes go.
+    let mut smart_prev: Option<char> = None;
+    // How many links the current node is in, since links can't contain the links for tags.
+    let mut link_depth = 0;
+    let mut footnotes_started = false;
+    let mut next_footnote_def = 0;
+    loop {
+        let Some(work) = q.pop() else {
+            // Once the main content is written, render the footnote definitions
+            // with the same state, so that they share the same footnote numbering.
+            if !footnotes_started {
+                footnotes_started = true;
+                log.has_footnotes = !footnote_defs.is_empty();
+                if log.has_footnotes {
+                    write!(writer, "<section class=\"footnotes\">\n<ol>\n")?;
+                }
+            }
+            let Some(&def) = footnote_defs.get(next_footnote_def) else {
+                break;
+            };
+            next_footnote_def += 1;
+            match def {
+                None => {
+                    lit!("<li>???</li>\n");
+                }
+                Some((identifier, children)) => {
+                    let count = footnote_refs.get(identifier).copied().unwrap_or(0);
+                    let backrefs = (1..=count)
+                        .map(|i| {
+                            format!(
+                                " <a href=\"#{}\" class=\"footnote-backref\">↩</a>",
+                                escape_attr(&footnote_ref_id(identifier, i))
+                            )
+                        })
+                        .collect::<String>();
+                    fmt!("{}</li>\n", backrefs);
+                    children!(children);
+                    fmt!("<li id=\"fn-{}\">", escape_attr(identifier));
+                }
+            }
+            continue;
+        };
         let node = match work {
             Work::Str(s) => {
                 writer.write_all(s.as_bytes())?;
@@ -81,17 +620,91 @@ pub fn write_md_ast<'root>(
                 writer.write_all(s.as_bytes())?;
                 continue;
             }
+            Work::LinkEnd => {
+                link_depth -= 1;
+                writer.write_all(b"</a>")?;
+                continue;
+            }
             Work::Node(node) => node,
         };
         use mdast::Node::*;
+        // Quotes at the start of a block always open a quotation.
+        if matches!(node, Paragraph(_) | Heading(_) | ListItem(_) | TableCell(_)) {
+            smart_prev = None;
+        }
         match node {
             Root(n) => {
                 children!(n.children);
             }
             Paragraph(n) => {
+                // A shortcode on its own replaces the whole paragraph.
+                if let [Text(t)] = n.children.as_slice()
+                    && let Some(shortcode) = Shortcode::parse_whole(&t.value)
+                {
+                    write_shortcode(writer, options, &mut log, node, &shortcode)?;
+                    continue;
+                }
+                // So does an embedded note, which falls back to a link when it can't be included.
+                if let [Text(t)] = n.children.as_slice()
+                    && let Some(link) = transclusion(&t.value)
+                    && site_map.static_by_name(link.page()).is_none()
+                    && let Some(target) = site_map.page_by_link(page, link.page())
+                {
+                    let depth = nesting.embedding.len();
+                    nesting.embedding.extend(page.map(|x| x.in_path.as_path()));
+                    let embedded = match nesting.embedding.contains(&target.in_path.as_path()) {
+                        true => Err(format!("`{}` ends up embedding itself", target.name)),
+                        false => transcluded(target, link.anchor(), options),
+                    };
+                    let nested = match embedded {
+                        Ok(ast) => {
+                            write!(writer, "\n<div class=\"transclusion\">")?;
+                            let nested = write_within(
+                                writer,
+                                site_map,
+                                Some(target),
+                                math_ctx,
+                                options,
+                                &ast,
+                                nesting,
+                            )?;
+                            write!(writer, "\n</div>")?;
+                            Ok(nested)
+                        }
+                        Err(message) => Err(message),
+                    };
+                    nesting.embedding.truncate(depth);
+                    match nested {
+                        // Other problems with the embedded page get reported with that page.
+                        Ok(nested) => {
+                            log.math |= nested.math;
+                            for name in &nested.includes {
+                                log.include(name);
+                            }
+                            continue;
+                        }
+                        Err(message) => {
+                            log.warn_at(format!("failed to embed `{}`: {message}", link.name), node)
+                        }
+                    }
+                }
+                if options.figures
+                    && let [image] = n.children.as_slice()
+                    && let Some((src, alt, title)) = image_parts(image, &definitions)
+                {
+                    write_figure(writer, link_url(options, src), alt, title)?;
+                    continue;
+                }
                 lit!("</p>");
                 children!(n.children);
-                lit!("\n<p>");
+                // A block id at the end of the paragraph, like `^abc123`, becomes its id.
+                match n.children.last() {
+                    Some(Text(t)) if let Some((_, block_id)) = split_block_id(&t.value) => {
+                        block_marker = Some(t as *const _);
+                        fmt!("\n<p id=\"{}\">", escape_attr(&block_anchor(block_id)));
+                    }
+                    _ => lit!("\n<p>"),
+                }
             }
             Blockquote(n) => {
                 lit!("</blockquote>");
@@ -117,24 +730,36 @@ pub fn write_md_ast<'root>(
             }
             ListItem(n) => {
                 lit!("</li>");
+                let mut block_id = None;
                 match (n.spread, n.children.as_slice()) {
                     (false, [Paragraph(inner)]) => {
                         children!(inner.children);
+                        // Without a paragraph of its own, the item carries the block id.
+                        if let Some(Text(t)) = inner.children.last()
+                            && let Some((_, id)) = split_block_id(&t.value)
+                        {
+                            block_marker = Some(t as *const _);
+                            block_id = Some(id);
+                        }
                     }
                     (_, children) => {
                         children!(children);
                     }
                 }
-                lit!("<li>");
+                match block_id {
+                    Some(id) => fmt!("<li id=\"{}\">", escape_attr(&block_anchor(id))),
+                    None => lit!("<li>"),
+                }
             }
-            Yaml(_) => {
+            Yaml(_) | Toml(_) => {
                 // Ignore front matter
             }
             Break(_) => {
                 lit!("\n<br/>");
             }
             InlineCode(n) => {
-                fmt!("<code>{}</code>", &n.value);
+                smart_prev = n.value.chars().last();
+                fmt!("<code>{}</code>", escape_html(&n.value));
             }
             Delete(n) => {
                 lit!("</del>");
@@ -148,95 +773,310 @@ pub fn write_md_ast<'root>(
             }
             FootnoteReference(n) => {
                 let id = footnote_ids.value(&n.identifier);
+                let count = footnote_refs.entry(&n.identifier).or_default();
+                *count += 1;
                 fmt!(
-                    "<sup><a href=\"#fn-{}\">{}</a></sup>",
-                    &n.identifier,
+                    "<sup id=\"{}\"><a href=\"#fn-{}\">{}</a></sup>",
+                    escape_attr(&footnote_ref_id(&n.identifier, *count)),
+                    escape_attr(&n.identifier),
                     id + 1
                 );
             }
+            Html(n) if options.sanitize_html => {
+                fmt!("{}", sanitize_html(&n.value));
+            }
             Html(n) => {
                 fmt!("{}", n.value);
             }
             Image(n) => {
-                let title = n
-                    .title
-                    .as_ref()
-                    .map(|x| format!("title={x}"))
-                    .unwrap_or_default();
-                fmt!("\n<img src={} alt={} {}/>", n.url, n.alt, title);
+                fmt!(
+                    "\n<img src=\"{}\" alt=\"{}\"{} />",
+                    escape_attr(link_url(options, &n.url)),
+                    escape_attr(&n.alt),
+                    title_attr(n.title.as_deref())
+                );
             }
+            ImageReference(n) => match definitions.get(n.identifier.as_str()) {
+                None => {
+                    fmt!("{}", escape_html(&n.alt));
+                }
+                Some(def) => {
+                    fmt!(
+                        "\n<img src=\"{}\" alt=\"{}\"{} />",
+                        escape_attr(link_url(options, &def.url)),
+                        escape_attr(&n.alt),
+                        title_attr(def.title.as_deref())
+                    );
+                }
+            },
             Strong(n) => {
                 lit!("</strong>");
                 children!(n.children);
                 lit!("<strong>");
             }
             Link(n) => {
-                lit!("</a>");
+                link_depth += 1;
+                q.push(Work::LinkEnd);
                 children!(n.children);
-                fmt!("<a href={}>", n.url);
+                fmt!(
+                    "<a href=\"{}\"{}>",
+                    escape_attr(link_url(options, &n.url)),
+                    title_attr(n.title.as_deref())
+                );
+            }
+            LinkReference(n) => match definitions.get(n.identifier.as_str()) {
+                None => {
+                    children!(n.children);
+                }
+                Some(def) => {
+                    link_depth += 1;
+                    q.push(Work::LinkEnd);
+                    children!(n.children);
+                    fmt!(
+                        "<a href=\"{}\"{}>",
+                        escape_attr(link_url(options, &def.url)),
+                        title_attr(def.title.as_deref())
+                    );
+                }
+            },
+            Definition(_) => {
+                // Definitions get resolved in the references using them.
             }
             Code(n) => {
-                fmt!("\n<pre><code>{}</code></pre>", n.value);
+                let lang = n.lang.as_deref().filter(|lang| !lang.is_empty());
+                let Some(lang) = lang else {
+                    fmt!("\n<pre><code>{}</code></pre>", escape_html(&n.value));
+                    continue;
+                };
+                if options.graphviz && lang == "dot" {
+                    match graphviz::render(&n.value) {
+                        Ok(svg) => {
+                            fmt!("\n<div class=\"graphviz\">{}</div>", svg);
+                            continue;
+                        }
+                        Err(e) => log.warn_at(e, node),
+                    }
+                }
+                let mut attrs = CodeAttrs::parse(n.meta.as_deref());
+                for attr in std::mem::take(&mut attrs.unknown) {
+                    log.warn_at(format!("unknown code block attribute `{attr}`"), node);
+                }
+                // A file name can also come after the language, like `rust:src/main.rs`.
+                let lang = match lang.split_once(':') {
+                    Some((lang, file)) if !lang.is_empty() && !file.is_empty() => {
+                        attrs.title.get_or_insert_with(|| file.to_string());
+                        lang
+                    }
+                    _ => lang,
+                };
+                let lines = match options.highlight_style {
+                    HighlightStyle::Inline => {
+                        highlight::highlight(&n.value, lang, &options.highlight_theme)
+                    }
+                    HighlightStyle::Classes => highlight::highlight_classes(&n.value, lang),
+                };
+                let lines = lines.unwrap_or_else(|e| {
+                    log.warn_at(e, node);
+                    None
+                });
+                let lines = lines.unwrap_or_else(|| {
+                    n.value
+                        .split_inclusive('\n')
+                        .map(|line| escape_html(line).into_owned())
+                        .collect()
+                });
+                let code = if attrs.has_lines() {
+                    attrs.write_lines(&lines)
+                } else {
+                    lines.concat()
+                };
+                fmt!(
+                    "\n<pre><code class=\"language-{}\">{}</code></pre>",
+                    escape_attr(lang),
+                    code
+                );
+                if let Some(title) = &attrs.title {
+                    fmt!("\n<div class=\"code-title\">{}</div>", escape_html(title));
+                }
             }
             InlineMath(n) => {
+                if let Some(label) = as_reference(&n.value) {
+                    write_equation_ref(writer, &equations, &mut log, node, label)?;
+                    continue;
+                }
                 log.math = true;
-                match katex::render_to_string(
-                    katex_ctx,
-                    &n.value,
-                    &katex::Settings {
-                        display_mode: false,
-                        ..Default::default()
-                    },
-                ) {
-                    Err(e) => {
-                        eprintln!("WARN: {e}");
-                        write!(writer, "<code>${}$</code>", n.value)?;
+                match math_ctx.render(&n.value, false) {
+                    None => {
+                        log.math_failed(math_ctx, node);
+                        write!(writer, "<code>${}$</code>", escape_html(&n.value))?;
                     }
-                    Ok(math) => {
+                    Some(math) => {
                         write!(writer, "<span class=\"katex-wrapper\">{}</span>", math)?;
                     }
                 }
             }
             Math(n) => {
                 log.math = true;
-                match katex::render_to_string(
-                    katex_ctx,
-                    &n.value,
-                    &katex::Settings {
-                        display_mode: true,
-                        ..Default::default()
-                    },
-                ) {
-                    Err(e) => {
-                        eprintln!("WARN: {e}");
-                        write!(writer, "<pre><code>$${}$$</code></pre>", n.value)?;
+                let (expr, _) = strip_label(&n.value);
+                let rendered = match math_ctx.render(&expr, true) {
+                    None => {
+                        log.math_failed(math_ctx, node);
+                        format!("<pre><code>$${}$$</code></pre>", escape_html(&n.value))
                     }
-                    Ok(math) => {
-                        write!(writer, "<span class=\"katex-wrapper\">{}</span>", math)?;
+                    Some(math) => format!("<span class=\"katex-wrapper\">{}</span>", math),
+                };
+                match equations.get(n) {
+                    Some(equation) if equation.number.is_some() || equation.label.is_some() => {
+                        let id = equation
+                            .label
+                            .as_ref()
+                            .map(|label| format!(" id=\"{}\"", escape_attr(label)))
+                            .unwrap_or_default();
+                        let number = equation
+                            .number
+                            .as_ref()
+                            .map(|number| {
+                                format!("<span class=\"equation-number\">({number})</span>")
+                            })
+                            .unwrap_or_default();
+                        write!(
+                            writer,
+                            "\n<div class=\"equation\"{id}>{rendered}{number}</div>"
+                        )?;
+                    }
+                    _ => {
+                        writer.write_all(rendered.as_bytes())?;
                     }
                 }
             }
             Text(n) => {
-                for segment in WikiLink::segment(&n.value) {
-                    use Segment::*;
-                    match segment {
-                        Normal(t) => {
-                            writer.write_all(t.as_bytes())?;
+                let mut value = n.value.as_str();
+                if block_marker == Some(n as *const _) {
+                    value = split_block_id(value).map_or(value, |(before, _)| before);
+                }
+                for piece in Shortcode::split(value) {
+                    let t = match piece {
+                        Piece::Shortcode(shortcode) => {
+                            write_shortcode(writer, options, &mut log, node, &shortcode)?;
+                            continue;
                         }
-                        Link(link) => match site_map.page_by_name(link.name) {
-                            None => {
-                                // If the reference doesn't exist, use emphasis nonetheless.
-                                write!(writer, "<em>{}</em>", link.display_or_name())?;
-                            }
-                            Some(page) => {
-                                write!(
-                                    writer,
-                                    "<a href=\"{}\">{}</a>",
-                                    page.link,
-                                    link.display_or_name()
-                                )?;
+                        Piece::Text(t) => t,
+                    };
+                    // Highlights, like `==this==`, can contain links, but not shortcodes.
+                    for (t, marked) in split_marks(t) {
+                        if marked {
+                            writer.write_all(b"<mark>")?;
+                        }
+                        for segment in WikiLink::segment(t) {
+                            use Segment::*;
+                            match segment {
+                                Normal(t) => {
+                                    for (text, label) in split_references(t, &equations) {
+                                        let pieces: Box<dyn Iterator<Item = _>> =
+                                            if options.inline_tags && link_depth == 0 {
+                                                Box::new(split_hashtags(text))
+                                            } else {
+                                                Box::new(std::iter::once((text, None)))
+                                            };
+                                        for (text, tag) in pieces {
+                                            let text = if options.smart_punctuation {
+                                                Cow::Owned(smart::smarten(text, &mut smart_prev))
+                                            } else {
+                                                Cow::Borrowed(text)
+                                            };
+                                            writer.write_all(escape_html(&text).as_bytes())?;
+                                            if let Some(tag) = tag {
+                                                write!(
+                                                    writer,
+                                                    "<a class=\"tag\" href=\"{}\">#{}</a>",
+                                                    escape_attr(&tag_url(tag)),
+                                                    escape_html(tag)
+                                                )?;
+                                                smart_prev = tag.chars().last();
+                                            }
+                                        }
+                                        if let Some(label) = label {
+                                            write_equation_ref(
+                                                writer, &equations, &mut log, node, label,
+                                            )?;
+                                        }
+                                    }
+                                }
+                                Link(link) => {
+                                    let file = site_map.static_by_name(link.page());
+                                    if let Some(file) = file.filter(|x| link.embed && x.is_image())
+                                    {
+                                        write_embed(writer, link_url(options, &file.link), &link)?;
+                                        continue;
+                                    }
+                                    let href =
+                                        match (link.page(), link.anchor()) {
+                                            // Equations in the page keep their label as their id.
+                                            ("", Some(anchor))
+                                                if equations.number(anchor).is_some() =>
+                                            {
+                                                Some(format!("#{anchor}"))
+                                            }
+                                            ("", Some(anchor)) => {
+                                                Some(format!("#{}", anchor_id(anchor)))
+                                            }
+                                            (name, anchor) => site_map
+                                                .page_by_link(page, name)
+                                                .map(|page| match anchor {
+                                                    None => page.link.clone(),
+                                                    Some(anchor) => {
+                                                        format!(
+                                                            "{}#{}",
+                                                            page.link,
+                                                            anchor_id(anchor)
+                                                        )
+                                                    }
+                                                }),
+                                        };
+                                    // Other files get linked to, keeping anchors like `#page=2` as is.
+                                    let href = href.or_else(|| {
+                                        let file = file?;
+                                        Some(match link.anchor() {
+                                            None => file.link.clone(),
+                                            Some(anchor) => format!("{}#{anchor}", file.link),
+                                        })
+                                    });
+                                    match href {
+                                        // Pages scheduled for later aren't broken, just not there yet.
+                                        None if site_map.is_scheduled(page, link.page()) => {
+                                            writer.write_all(
+                                                escape_html(&link.display_or_name()).as_bytes(),
+                                            )?;
+                                        }
+                                        None => {
+                                            // If the reference doesn't exist, use emphasis nonetheless.
+                                            log.broken_links.push(BrokenLink {
+                                                name: link.name.to_string(),
+                                                offset: n.position.as_ref().map(|x| x.start.offset),
+                                                ambiguous: site_map.is_ambiguous(page, link.page()),
+                                            });
+                                            write!(
+                                                writer,
+                                                "<em>{}</em>",
+                                                escape_html(&link.display_or_name())
+                                            )?;
+                                        }
+                                        Some(href) => {
+                                            write!(
+                                                writer,
+                                                "<a href=\"{}\">{}</a>",
+                                                escape_attr(&href),
+                                                escape_html(&link.display_or_name())
+                                            )?;
+                                        }
+                                    }
+                                    smart_prev = link.display_or_name().chars().last();
+                                }
                             }
-                        },
+                        }
+                        if marked {
+                            writer.write_all(b"</mark>")?;
+                        }
                     }
                 }
             }
@@ -259,55 +1099,242 @@ pub fn write_md_ast<'root>(
                 lit!("\n<th>");
             }
             Heading(n) => {
+                nesting.outline.push(n.depth);
+                let title = anchors::plain_text(node);
+                let id = escape_attr(nesting.anchors.push(n.depth, title)).into_owned();
                 fmt!("</h{}>", n.depth);
+                if options.heading_permalinks {
+                    fmt!(
+                        "<a class=\"permalink\" href=\"#{}\" aria-label=\"Permalink\">#</a>",
+                        id
+                    );
+                }
                 children!(n.children);
-                fmt!("\n<h{}>", n.depth);
-            }
-            MdxJsxFlowElement(_) => unimplemented!("MdxJsxFlowElement"),
-            MdxjsEsm(_) => unimplemented!("MdxjsEsm"),
-            Toml(_) => unimplemented!("Toml"),
-            MdxTextExpression(_) => unimplemented!("MdxTextExpression"),
-            ImageReference(_) => unimplemented!("ImageReference"),
-            MdxJsxTextElement(_) => unimplemented!("MdxJsxTextElement"),
-            LinkReference(_) => unimplemented!("LinkReference"),
-            MdxFlowExpression(_) => unimplemented!("MdxFlowExpression"),
-            Definition(_) => unimplemented!("Definition"),
-        }
-    }
-    write!(writer, "<section class=\"footnotes\">\n<ol>\n")?;
-    for def in footnote_defs.into_iter() {
-        match def {
-            None => {
-                write!(writer, "<li>???</li>\n")?;
+                fmt!("\n<h{} id=\"{}\">", n.depth, id);
             }
-            Some((identifier, children)) => {
-                write!(writer, "<li id=\"fn-{identifier}\">")?;
-                for n in children {
-                    let child_log = write_md_ast(writer, site_map, katex_ctx, n)?;
-                    log.merge(&child_log);
-                }
-                write!(writer, "</li>\n")?;
+            // These only get parsed with MDX, but plugins could still produce them.
+            MdxJsxFlowElement(_) | MdxjsEsm(_) | MdxTextExpression(_) | MdxJsxTextElement(_)
+            | MdxFlowExpression(_) => {
+                let mut diagnostic = Diagnostic::new("unsupported markdown: MDX");
+                diagnostic.location = node.position().map(|x| Location {
+                    line: x.start.line,
+                    column: x.start.column,
+                });
+                return Err(diagnostic.into());
             }
         }
     }
-    write!(writer, "</ol>\n</section>")?;
-    write!(writer, "\n")?;
+    if log.has_footnotes {
+        writeln!(writer, "</ol>\n</section>")?;
+    }
+    log.equations = equations.into_labels();
     Ok(log)
 }
 
-pub fn find_yaml_frontmatter<'root>(ast: &'root mdast::Node) -> Option<&'root str> {
+/// Check if a node is the `<!--more-->` marking the end of an excerpt.
+fn is_more_marker(node: &mdast::Node) -> bool {
+    matches!(node, mdast::Node::Html(h) if h.value.trim() == "<!--more-->")
+}
+
+/// The start of a document, for showing in lists of pages.
+///
+/// This is everything before a `<!--more-->`, if there is one, and the first
+/// paragraph otherwise.
+pub fn excerpt_ast(ast: &mdast::Node) -> Option<mdast::Node> {
+    let mdast::Node::Root(root) = ast else {
+        return None;
+    };
+    let children = match root.children.iter().position(is_more_marker) {
+        Some(i) => root.children[..i]
+            .iter()
+            .filter(|x| !matches!(x, mdast::Node::Yaml(_) | mdast::Node::Toml(_)))
+            .cloned()
+            .collect(),
+        None => {
+            let paragraph = root
+                .children
+                .iter()
+                .find(|x| matches!(x, mdast::Node::Paragraph(_)))?;
+            vec![paragraph.clone()]
+        }
+    };
+    let mut excerpt = mdast::Node::Root(mdast::Root {
+        children,
+        position: None,
+    });
+    // The footnotes themselves aren't part of the excerpt.
+    let mut q = vec![&mut excerpt];
+    while let Some(node) = q.pop() {
+        if let Some(children) = node.children_mut() {
+            children.retain(|x| !matches!(x, mdast::Node::FootnoteReference(_)));
+            q.extend(children.iter_mut());
+        }
+    }
+    Some(excerpt)
+}
+
+/// The position of the first `h1` in a document, outside of anything like a quote.
+fn first_h1_index(ast: &mdast::Node) -> Option<usize> {
+    let mdast::Node::Root(root) = ast else {
+        return None;
+    };
+    root.children
+        .iter()
+        .position(|node| matches!(node, mdast::Node::Heading(h) if h.depth == 1))
+}
+
+/// The text of the first `h1` in a document, for pages without a title.
+pub fn first_h1(ast: &mdast::Node) -> Option<String> {
+    let mdast::Node::Root(root) = ast else {
+        return None;
+    };
+    let heading = &root.children[first_h1_index(ast)?];
+    Some(anchors::plain_text(heading)).filter(|x| !x.trim().is_empty())
+}
+
+/// Remove the first `h1` in a document, when it's already the title of the page.
+pub fn remove_first_h1(ast: &mut mdast::Node) {
+    if let Some(i) = first_h1_index(ast)
+        && let mdast::Node::Root(root) = ast
+    {
+        root.children.remove(i);
+    }
+}
+
+/// The bytes of some text which were escaped with a `\` in its source, like the `[` of `\[`.
+///
+/// Text loses its escapes when parsed, so this walks through both of them together,
+/// stopping where they stop lining up, like where indentation got left out of the text.
+fn escaped_bytes(source: &str, text: &str) -> Vec<usize> {
+    let (source, text) = (source.as_bytes(), text.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < source.len() && j < text.len() {
+        if source[i] == b'\\' && source.get(i + 1) == Some(&text[j]) {
+            out.push(j);
+            i += 2;
+        } else if let Some(len) = entity_len(&source[i..])
+            && !text[j..].starts_with(&source[i..i + len])
+        {
+            // Entities, like `&#91;`, stand for a single character of the text,
+            // and escape it too.
+            out.push(j);
+            i += len;
+            j += text[j..]
+                .iter()
+                .skip(1)
+                .take_while(|&&x| x & 0xc0 == 0x80)
+                .count();
+        } else if source[i] == text[j] {
+            i += 1;
+        } else {
+            break;
+        }
+        j += 1;
+    }
+    out
+}
+
+/// The length of the entity at the start of some markdown, like `&amp;`, if there is one.
+fn entity_len(source: &[u8]) -> Option<usize> {
+    let end = source.iter().take(32).position(|&x| x == b';')?;
+    let name = source.get(1..end)?;
+    let is_entity = source[0] == b'&'
+        && !name.is_empty()
+        && name.iter().all(|&x| x.is_ascii_alphanumeric() || x == b'#');
+    is_entity.then_some(end + 1)
+}
+
+/// Split text between the brackets of escaped wikilinks, like `\[[Page]]`, so that
+/// they get written as they are, rather than as links.
+fn split_escaped_wikilinks(ast: &mut mdast::Node, source: &str) {
     let mut q = vec![ast];
-    while let Some(n) = q.pop() {
-        use mdast::Node::*;
-        match n {
-            Root(n) => {
-                q.extend(n.children.iter());
-            }
-            Yaml(n) => return Some(&n.value),
-            _ => {}
+    while let Some(node) = q.pop() {
+        let Some(children) = node.children_mut() else {
+            continue;
+        };
+        let mut i = 0;
+        while i < children.len() {
+            let mdast::Node::Text(text) = &children[i] else {
+                i += 1;
+                continue;
+            };
+            // A text starting with an escape starts after its `\`.
+            let span = text.position.as_ref().and_then(|x| {
+                let start = x.start.offset;
+                let start = match source.get(..start)?.ends_with('\\') {
+                    true => start - 1,
+                    false => start,
+                };
+                source.get(start..x.end.offset)
+            });
+            let Some(span) = span else {
+                i += 1;
+                continue;
+            };
+            let escaped = escaped_bytes(span, &text.value);
+            let splits = text
+                .value
+                .match_indices("[[")
+                .map(|(j, _)| j)
+                .filter(|j| escaped.contains(j) || escaped.contains(&(j + 1)))
+                .map(|j| j + 1)
+                .collect::<Vec<_>>();
+            let pieces = [0]
+                .into_iter()
+                .chain(splits.iter().copied())
+                .zip(splits.iter().copied().chain([text.value.len()]))
+                .map(|(start, end)| {
+                    mdast::Node::Text(mdast::Text {
+                        value: text.value[start..end].to_string(),
+                        position: text.position.clone(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let count = pieces.len();
+            children.splice(i..=i, pieces);
+            i += count;
+        }
+        q.extend(children.iter_mut());
+    }
+}
+
+/// Adjust the headings of a page as configured, leaving out its title heading,
+/// with `strip_title_heading`, and demoting the rest by its heading offset.
+pub fn adjust_headings(ast: &mut mdast::Node, page: &Page, options: &Options) {
+    if options.strip_title_heading && page.front_matter.heading_title {
+        remove_first_h1(ast);
+    }
+    let offset = page.front_matter.heading_offset;
+    shift_headings(ast, offset.unwrap_or(options.heading_offset));
+}
+
+/// Demote every heading in a document by some levels, stopping at `h6`.
+pub fn shift_headings(ast: &mut mdast::Node, offset: u8) {
+    if offset == 0 {
+        return;
+    }
+    let mut q = vec![ast];
+    while let Some(node) = q.pop() {
+        if let mdast::Node::Heading(h) = node {
+            h.depth = h.depth.saturating_add(offset).min(6);
+        }
+        if let Some(children) = node.children_mut() {
+            q.extend(children.iter_mut());
         }
     }
-    None
+}
+
+/// Find the front matter of a document, if it has any.
+pub fn find_frontmatter(ast: &mdast::Node) -> Option<Source<'_>> {
+    let mdast::Node::Root(root) = ast else {
+        return None;
+    };
+    root.children.iter().find_map(|node| match node {
+        mdast::Node::Yaml(n) => Some(Source::Yaml(&n.value)),
+        mdast::Node::Toml(n) => Some(Source::Toml(&n.value)),
+        _ => None,
+    })
 }
 
 pub fn extract_description(ast: &mdast::Node, max_len: usize) -> String {
@@ -327,7 +1354,8 @@ pub fn extract_description(ast: &mdast::Node, max_len: usize) -> String {
             List(n) => q.extend(n.children.iter().rev()),
             ListItem(n) => q.extend(n.children.iter().rev()),
             Text(n) => {
-                for c in n.value.chars() {
+                let chars = split_marks(&n.value).flat_map(|(text, _)| text.chars());
+                for c in chars {
                     if out.len() >= max_len {
                         break 'outer;
                     }
@@ -348,3 +1376,411 @@ pub fn extract_description(ast: &mdast::Node, max_len: usize) -> String {
     out.truncate(out.trim_end().len());
     out
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+    use std::hash::{DefaultHasher, Hasher};
+
+    fn render(data: &str) -> String {
+        render_with(&Options::default(), data)
+    }
+
+    fn render_with(options: &Options, data: &str) -> String {
+        render_ast(options, &make_mdast(data).unwrap())
+    }
+
+    fn render_ast(options: &Options, ast: &mdast::Node) -> String {
+        let mut buf = Vec::new();
+        write_md_ast(
+            &mut buf,
+            &SiteMap::default(),
+            None,
+            &MathContext::default(),
+            options,
+            ast,
+        )
+        .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn footnotes_share_numbering() {
+        let html = render("A[^a] B[^b]\n\n[^a]: See[^b].\n[^b]: Done.\n");
+        assert_eq!(html.matches("<section class=\"footnotes\">").count(), 1);
+        assert!(!render("No notes.\n").contains("footnotes"));
+        assert!(html.contains("A<sup id=\"fnref-a\"><a href=\"#fn-a\">1</a></sup>"));
+        assert!(html.contains(
+            "<li id=\"fn-a\">\n<p>See<sup id=\"fnref-b-2\"><a href=\"#fn-b\">2</a></sup>.</p> <a href=\"#fnref-a\" class=\"footnote-backref\">↩</a></li>"
+        ));
+        assert!(html.contains("<li id=\"fn-b\">\n<p>Done.</p> <a href=\"#fnref-b\" class=\"footnote-backref\">↩</a> <a href=\"#fnref-b-2\" class=\"footnote-backref\">↩</a></li>"));
+    }
+
+    #[test]
+    fn descriptions() {
+        let ast = make_mdast("Some ==marked== *text*, and `a == b`.\n").unwrap();
+        assert_eq!(
+            extract_description(&ast, 160),
+            "Some marked text, and a == b."
+        );
+        assert_eq!(extract_description(&ast, 9), "Some mark");
+    }
+
+    #[test]
+    fn equation_references() {
+        let html = render("See \\eqref{eq:a}.\n\n$$\nx = 1 \\label{eq:a}\n$$\n");
+        assert!(html.contains("See <a class=\"eqref\" href=\"#eq:a\">(1)</a>."));
+        assert!(html.contains("<div class=\"equation\" id=\"eq:a\">"));
+        assert!(html.contains("<span class=\"equation-number\">(1)</span></div>"));
+        let html = render("$$\nx \\label{eq:a}\n$$\n\nSee [[#eq:a]] and [[#Step:Setup]].\n");
+        assert!(html.contains("<a href=\"#eq:a\">eq:a</a>"));
+        assert!(html.contains("<a href=\"#stepsetup\">Step:Setup</a>"));
+    }
+
+    #[test]
+    fn shortcode_templates() {
+        let mut options = Options::default();
+        options
+            .shortcodes
+            .add_template(
+                "youtube.html",
+                "<iframe src=\"/embed/{{ args[0] }}\"></iframe>",
+            )
+            .unwrap();
+        let html = render_with(&options, "{{< youtube a&b >}}\n\nSee {{< nope >}}.\n");
+        assert!(html.contains("<iframe src=\"/embed/a&amp;b\"></iframe>"));
+        assert!(html.contains("See {{&lt; nope &gt;}}."));
+    }
+
+    #[test]
+    fn figures() {
+        let options = Options {
+            figures: true,
+            ..Default::default()
+        };
+        let html = render_with(
+            &options,
+            "![A cat](cat.png \"My cat\")\n\n![A dog][dog]\n\nSee ![x](x.png).\n\n[dog]: dog.png\n",
+        );
+        assert!(html.contains("<figure>\n<img src=\"cat.png\" alt=\"A cat\" />\n<figcaption>My cat</figcaption>\n</figure>"));
+        assert!(
+            html.contains("<img src=\"dog.png\" alt=\"A dog\" />\n<figcaption>A dog</figcaption>")
+        );
+        assert!(html.contains("<p>See \n<img src=\"x.png\" alt=\"x\" />.</p>"));
+        assert!(!render("![A cat](cat.png)\n").contains("<figure>"));
+    }
+
+    #[test]
+    fn smart_punctuation() {
+        let options = Options {
+            smart_punctuation: true,
+            ..Default::default()
+        };
+        let html = render_with(
+            &options,
+            "\"*Hi*\" -- `\"x\"...`\n\n'a'\n\n[[Hello World]]'s\n",
+        );
+        assert!(html.contains("<p>“<em>Hi</em>” – <code>\"x\"...</code></p>"));
+        assert!(html.contains("<p>‘a’</p>"));
+        // Quotes after a link close, like they would after its text.
+        assert!(html.contains("<em>Hello World</em>’s</p>"));
+        assert!(render("\"a\"\n").contains("<p>\"a\"</p>"));
+    }
+
+    #[test]
+    fn excerpts() {
+        let excerpt = |data: &str| {
+            let excerpt = excerpt_ast(&make_mdast(data).unwrap()).unwrap();
+            render_ast(&Options::default(), &excerpt).trim().to_string()
+        };
+        assert_eq!(
+            excerpt("# A\n\nFirst *one*\n\nSecond\n"),
+            "<p>First <em>one</em></p>"
+        );
+        assert_eq!(
+            excerpt("---\ntitle: A\n---\nFirst\n\n- Second\n\n<!--more-->\n\nThird\n"),
+            "<p>First</p>\n<ul><li>Second</li></ul>"
+        );
+        assert!(excerpt_ast(&make_mdast("# Only a heading\n").unwrap()).is_none());
+    }
+
+    #[test]
+    fn heading_titles() {
+        let mut ast =
+            make_mdast("---\ndate: 2024-01-01\n---\n> # Quoted\n\n# The *Title*\n\nText\n")
+                .unwrap();
+        assert_eq!(first_h1(&ast).as_deref(), Some("The Title"));
+        remove_first_h1(&mut ast);
+        assert_eq!(first_h1(&ast), None);
+        assert!(render("# A\n\n# B\n").contains("<h1 id=\"b\">"));
+    }
+
+    #[test]
+    fn shifted_headings() {
+        let mut ast = make_mdast("# A\n\n> ## B\n\n##### C\n").unwrap();
+        shift_headings(&mut ast, 2);
+        let html = render_ast(&Options::default(), &ast);
+        assert!(html.contains("<h3 id=\"a\">A</h3>"));
+        assert!(html.contains("<h4 id=\"b\">B</h4>"));
+        assert!(html.contains("<h6 id=\"c\">C</h6>"));
+    }
+
+    #[test]
+    fn inline_tags() {
+        let options = Options {
+            inline_tags: true,
+            ..Default::default()
+        };
+        let html = render_with(&options, "See #Rust/Async, [#not](x) #1\n");
+        assert!(html.contains(
+            "<p>See <a class=\"tag\" href=\"/tag/rust/async/\">#Rust/Async</a>, <a href=\"x\">#not</a> #1</p>"
+        ));
+        assert!(render("#Rust\n").contains("<p>#Rust</p>"));
+    }
+
+    #[test]
+    fn includes_stay_inside() {
+        let dir = TempDir::new("include");
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data/t.csv"), "a,b\n").unwrap();
+        fs::write(dir.join("secret.csv"), "a,b\n").unwrap();
+        let options = Options {
+            include_dirs: vec![dir.join("data")],
+            ..Options::default()
+        };
+        let found = options.find_include("t.csv");
+        let escaped = options.find_include("../secret.csv");
+        let absolute = options.find_include(dir.join("secret.csv").to_str().unwrap());
+        assert!(found.is_some());
+        assert_eq!(escaped, None);
+        assert_eq!(absolute, None);
+    }
+
+    #[test]
+    fn attachments() {
+        let dir = TempDir::new("attachments");
+        fs::create_dir_all(dir.join("files")).unwrap();
+        fs::write(dir.join("files/report.pdf"), "").unwrap();
+        fs::write(dir.join("diagram.svg"), "").unwrap();
+        let config = crate::config::Config::default();
+        let site_map = SiteMap::build(&config, &dir, Path::new("")).unwrap();
+        let source = "[[report.pdf]], [[report.pdf#page=2|page 2]], ![[report.pdf]], ![[diagram.svg]], [[diagram.svg]]\n";
+        let ast = make_mdast(source).unwrap();
+        let mut buf = Vec::new();
+        let options = Options::default();
+        let log = write_md_ast(
+            &mut buf,
+            &site_map,
+            None,
+            &MathContext::default(),
+            &options,
+            &ast,
+        )
+        .unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(log.broken_links.is_empty());
+        assert!(html.contains(concat!(
+            "<a href=\"/files/report.pdf\">report.pdf</a>, ",
+            "<a href=\"/files/report.pdf#page=2\">page 2</a>, ",
+            "<a href=\"/files/report.pdf\">report.pdf</a>, ",
+            "<img src=\"/diagram.svg\" alt=\"diagram.svg\" />, ",
+            "<a href=\"/diagram.svg\">diagram.svg</a>"
+        )));
+    }
+
+    #[test]
+    fn transclusions() {
+        let dir = TempDir::new("transclusion");
+        let files = [
+            (
+                "Host",
+                "## Group\n\n![[Defs#Group]]\n\n![[Defs#^def]]\n\n![[Defs#^item]]\n\n![[Loop]]\n\n![[Titled]]\n\nSee ![[Defs]].\n\n![[Defs#Nope]]\n",
+            ),
+            (
+                "Defs",
+                "---\ntitle: Defs\n---\n# Group\n\nA *group*. ^def\n\n## Ring\n\nSub\n\n# Field\n\n- Not included\n- An item ^item\n",
+            ),
+            ("Loop", "Loop\n\n![[Host]]\n"),
+            ("Titled", "# Titled\n\nBody\n"),
+        ];
+        for (name, source) in files {
+            fs::write(dir.join(format!("{name}.md")), source).unwrap();
+        }
+        let config = crate::config::Config::default();
+        let site_map = SiteMap::build(&config, &dir, Path::new("")).unwrap();
+        let host = site_map.page_by_name("Host").unwrap();
+        let ast = make_mdast(&fs::read_to_string(&host.in_path).unwrap()).unwrap();
+        let mut buf = Vec::new();
+        let options = Options {
+            strip_title_heading: true,
+            heading_offset: 1,
+            ..Default::default()
+        };
+        let log = write_md_ast(
+            &mut buf,
+            &site_map,
+            Some(host),
+            &MathContext::default(),
+            &options,
+            &ast,
+        )
+        .unwrap();
+        // Pages change along with the pages they embed.
+        let hash_embeds = || {
+            let mut hasher = DefaultHasher::new();
+            site_map.hash_embeds(host, &mut hasher).unwrap();
+            hasher.finish()
+        };
+        let before = hash_embeds();
+        fs::write(dir.join("Defs.md"), "Changed").unwrap();
+        let after = hash_embeds();
+        assert_ne!(before, after);
+        let html = String::from_utf8(buf).unwrap();
+        let sections = html
+            .split("<div class=\"transclusion\">")
+            .collect::<Vec<_>>();
+        assert_eq!(sections.len(), 6);
+        // Embedded headings get demoted like the page on its own, without clashing ids.
+        assert!(sections[1].contains("<h2 id=\"group-1\">Group</h2>"));
+        assert!(sections[1].contains("<h3 id=\"ring\">Ring</h3>"));
+        assert!(!sections[1].contains("Field"));
+        assert!(sections[2].starts_with("\n<p id=\"block-def\">A <em>group</em>.</p>\n</div>"));
+        // Blocks inside of lists get embedded on their own.
+        assert!(sections[3].starts_with("\n<p id=\"block-item\">An item</p>\n</div>"));
+        // The loop embeds the host again, which ends up as a link.
+        assert!(
+            sections[4]
+                .starts_with("\n<p>Loop</p>\n<p><a href=\"/host.html\">Host</a></p>\n</div>")
+        );
+        assert!(sections[5].starts_with("\n<p>Body</p>\n</div>"));
+        let toc = log.toc.iter().map(|x| x.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(toc, vec!["group", "group-1", "ring"]);
+        assert!(html.contains("<p>See <a href=\"/defs.html\">Defs</a>.</p>"));
+        assert!(html.ends_with("<p><a href=\"/defs.html#nope\">Defs &gt; Nope</a></p>"));
+        let warnings = log
+            .warnings
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec!["failed to embed `Defs#Nope`: `Defs` has no section `Nope`, at line 15, column 1"]
+        );
+    }
+
+    #[test]
+    fn sanitizing() {
+        let options = Options {
+            sanitize_html: true,
+            ..Default::default()
+        };
+        let html = render_with(
+            &options,
+            "<div onmouseover=\"x()\">\n\nHi <b onclick=\"x()\">a</b> [b](javascript:x())\n\n</div>\n\n<script>x()</script>\n",
+        );
+        assert_eq!(html, "<div>\n<p>Hi <b>a</b> <a href=\"#\">b</a></p></div>");
+        let html = render_with(
+            &options,
+            "![a](javascript:x()) ![b](data:text/html,hi) ![c][r] ![d](d.png)\n\n[r]: javascript:y()\n",
+        );
+        assert_eq!(html.matches("<img src=\"#\"").count(), 3);
+        assert!(html.contains("<img src=\"d.png\" alt=\"d\" />"));
+    }
+
+    #[test]
+    fn code_blocks() {
+        let html = render("```rust\nlet x = a < b;\n```\n\n```\n<b>\n```\n");
+        assert!(html.contains("<pre><code class=\"language-rust\">"));
+        assert!(!html.contains("a < b"));
+        assert!(html.contains("<pre><code>&lt;b&gt;</code></pre>"));
+        let html = render("```rust {linenos, hl_lines=[2]}\na\nb\n```\n");
+        assert!(
+            html.contains("<span class=\"line highlighted\"><span class=\"line-number\">2</span>")
+        );
+        let html = render("```rust:src/main.rs\nx\n```\n\n```rust {title=\"<a>\"}\nx\n```\n");
+        assert!(html.contains(
+            "<div class=\"code-title\">src/main.rs</div>\n<pre><code class=\"language-rust\">"
+        ));
+        assert!(html.contains("<div class=\"code-title\">&lt;a&gt;</div>"));
+        // Without highlighting, the language is still there, for client-side highlighters.
+        let html = render("```not-a-language extra\nx\n```\n");
+        assert!(html.contains("<pre><code class=\"language-not-a-language\">x</code></pre>"));
+    }
+
+    #[test]
+    fn graphs() {
+        let options = Options {
+            graphviz: true,
+            ..Default::default()
+        };
+        let html = render_with(&options, "```dot\ndigraph { a -> b }\n```\n");
+        // Without Graphviz installed, the graph should still show up, as code.
+        match graphviz::render("digraph { a -> b }") {
+            Ok(_) => assert!(html.starts_with("\n<div class=\"graphviz\"><svg")),
+            Err(_) => assert!(html.contains("<pre><code class=\"language-dot\">")),
+        }
+        assert!(!render("```dot\ndigraph { a -> b }\n```\n").contains("<svg"));
+    }
+
+    #[test]
+    fn heading_ids() {
+        let html = render("# Hello *World*\n\n## Hello World\n");
+        assert!(html.contains("<h1 id=\"hello-world\">Hello <em>World</em></h1>"));
+        assert!(html.contains("<h2 id=\"hello-world-1\">"));
+        let html = render(
+            "See [[#Hello World]], and [[Missing#Hello]].\n\nA block ^abc\n\nSee [[#^abc]].\n",
+        );
+        assert!(html.contains("<p id=\"block-abc\">A block</p>"));
+        assert!(html.contains("<a href=\"#block-abc\">^abc</a>"));
+        assert!(html.contains("<a href=\"#hello-world\">Hello World</a>"));
+        assert!(html.contains("<em>Missing &gt; Hello</em>"));
+        let html = render("- First ^one\n- Second\n");
+        assert!(html.contains("<li id=\"block-one\">First</li><li>Second</li>"));
+    }
+
+    #[test]
+    fn escaping() {
+        let html =
+            render("a <b & `<script>` [x](/a?b=1&c=\"2\" \"t\") ![\"q\"](/i.png)\n\n<i>raw</i>\n");
+        assert!(html.contains("a &lt;b &amp; <code>&lt;script&gt;</code>"));
+        assert!(html.contains("<a href=\"/a?b=1&amp;c=&quot;2&quot;\" title=\"t\">x</a>"));
+        assert!(html.contains("<img src=\"/i.png\" alt=\"&quot;q&quot;\" />"));
+        assert!(html.contains("<i>raw</i>"));
+    }
+
+    #[test]
+    fn escaped_wikilinks() {
+        let html = render(
+            "\\[[Not a link]], [\\[Nor this]], \\\\[[Link]], `[[Code]]` &amp; \\[[Last|one]] &#91;[Entity]]\n",
+        );
+        assert!(html.contains(
+            "<p>[[Not a link]], [[Nor this]], \\<em>Link</em>, <code>[[Code]]</code> &amp; [[Last|one]] [[Entity]]</p>"
+        ));
+        assert_eq!(
+            WikiLink::extract("\\[[No]] \\\\[[Yes]] ![[Also]]")
+                .map(|x| x.name)
+                .collect::<Vec<_>>(),
+            vec!["Yes", "Also"]
+        );
+    }
+
+    #[test]
+    fn marks() {
+        let html = render("A ==key point== about [[X]], ==see [[Y]]==, and `==code==`, x == y.\n");
+        assert!(html.contains(
+            "<p>A <mark>key point</mark> about <em>X</em>, <mark>see <em>Y</em></mark>, and <code>==code==</code>, x == y.</p>"
+        ));
+    }
+
+    #[test]
+    fn reference_links() {
+        let html = render(
+            "[One][a], [a], and ![img][B].\n\n[a]: /one \"First\"\n[b]: /b.png\n[a]: /ignored\n",
+        );
+        assert!(html.contains("<a href=\"/one\" title=\"First\">One</a>"));
+        assert!(html.contains("<a href=\"/one\" title=\"First\">a</a>"));
+        assert!(html.contains("<img src=\"/b.png\" alt=\"img\" />"));
+        assert!(!html.contains("ignored"));
+    }
+}
@@ -0,0 +1,139 @@
+use markdown::mdast;
+use regex::Regex;
+use serde::Serialize;
+use std::{collections::HashMap, sync::LazyLock};
+
+use crate::slug::slugify;
+
+/// Extract the plain text of a node, e.g. the text of a heading.
+pub fn plain_text(node: &mdast::Node) -> String {
+    let mut out = String::new();
+    let mut q = vec![node];
+    while let Some(node) = q.pop() {
+        use mdast::Node::*;
+        match node {
+            Text(n) => out.push_str(&n.value),
+            InlineCode(n) => out.push_str(&n.value),
+            InlineMath(n) => out.push_str(&n.value),
+            _ => {
+                if let Some(children) = node.children() {
+                    q.extend(children.iter().rev());
+                }
+            }
+        }
+    }
+    out
+}
+
+/// The slug used for a heading with some text, if it's the first with that text.
+fn heading_slug(text: &str) -> String {
+    let slug = slugify(text);
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug
+    }
+}
+
+static BLOCK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s\^([A-Za-z0-9-]+)\s*$").unwrap());
+
+/// Split off a block id marker, like ` ^abc123`, from the end of some text.
+///
+/// This returns the text before the marker, and the block id.
+pub fn split_block_id(text: &str) -> Option<(&str, &str)> {
+    let capture = BLOCK_RE.captures(text)?;
+    let start = capture.get(0)?.start();
+    Some((&text[..start], capture.get(1)?.as_str()))
+}
+
+/// The id of the element for a block with some block id.
+///
+/// This avoids the `^`, which would need to be escaped in URLs and CSS selectors.
+pub fn block_anchor(block_id: &str) -> String {
+    format!("block-{block_id}")
+}
+
+/// The id which a link to `#anchor` should point to.
+///
+/// Anchors like `^abc123` refer to blocks. Other anchors refer to headings,
+/// and get slugified the same way.
+pub fn anchor_id(anchor: &str) -> String {
+    if let Some(block_id) = anchor.strip_prefix('^') {
+        return block_anchor(block_id);
+    }
+    heading_slug(anchor)
+}
+
+/// A heading in the table of contents of a page.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TocEntry {
+    pub depth: u8,
+    pub title: String,
+    pub id: String,
+}
+
+/// Assigns unique ids to the headings of a page.
+///
+/// Repeated headings get a numbered suffix, like `notes`, `notes-1`, `notes-2`.
+#[derive(Default)]
+pub struct Anchors {
+    seen: HashMap<String, usize>,
+    toc: Vec<TocEntry>,
+}
+
+impl Anchors {
+    /// Assign an id to the next heading in the document.
+    pub fn push(&mut self, depth: u8, title: String) -> &str {
+        let slug = heading_slug(&title);
+        let id = match self.seen.get_mut(&slug) {
+            None => slug.clone(),
+            Some(count) => {
+                *count += 1;
+                format!("{slug}-{count}")
+            }
+        };
+        self.seen.entry(slug).or_insert(0);
+        self.seen.entry(id.clone()).or_insert(0);
+        self.toc.push(TocEntry { depth, title, id });
+        &self.toc[self.toc.len() - 1].id
+    }
+
+    /// Consume this, returning the headings, in order.
+    pub fn into_toc(self) -> Vec<TocEntry> {
+        self.toc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unique_ids() {
+        let mut anchors = Anchors::default();
+        assert_eq!(anchors.push(2, "Notes".into()), "notes");
+        assert_eq!(anchors.push(2, "Notes".into()), "notes-1");
+        assert_eq!(anchors.push(2, "Notes 1".into()), "notes-1-1");
+        assert_eq!(anchors.push(3, "???".into()), "section");
+        assert_eq!(anchors.into_toc()[1].id, "notes-1");
+    }
+
+    #[test]
+    fn link_anchors() {
+        assert_eq!(anchor_id("Some Section"), "some-section");
+        assert_eq!(anchor_id("Step 1: Setup"), "step-1-setup");
+        assert_eq!(anchor_id("Step:Setup"), "stepsetup");
+        assert_eq!(anchor_id("^abc-1"), "block-abc-1");
+    }
+
+    #[test]
+    fn block_ids() {
+        assert_eq!(
+            split_block_id("Some text ^abc-1\n"),
+            Some(("Some text", "abc-1"))
+        );
+        assert_eq!(split_block_id("Some text^abc"), None);
+        assert_eq!(split_block_id("x ^abc y"), None);
+    }
+}
@@ -0,0 +1,153 @@
+use std::ops::RangeInclusive;
+
+/// The attributes of a code block, after its language, like ```` ```rust {linenos, hl_lines=[3,7-9]} ````.
+#[derive(Debug, Default, PartialEq)]
+pub struct CodeAttrs {
+    /// Number each line.
+    pub linenos: bool,
+    /// The lines to highlight, counting from 1.
+    pub hl_lines: Vec<RangeInclusive<usize>>,
+    /// A title for the code block, usually the name of a file, like `title="src/main.rs"`.
+    pub title: Option<String>,
+    /// The attributes which weren't understood, to warn about.
+    pub unknown: Vec<String>,
+}
+
+/// Split a list by commas, ignoring those inside of brackets or quotes.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' if !quoted => depth += 1,
+            ']' if !quoted => depth = depth.saturating_sub(1),
+            ',' if !quoted && depth == 0 => {
+                out.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&s[start..]);
+    out
+}
+
+/// Parse lines like `[3, 7-9]`, returning `None` if they're invalid.
+fn parse_lines(s: &str) -> Option<Vec<RangeInclusive<usize>>> {
+    let s = s.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let mut out = Vec::new();
+    for item in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+        let range = match item.split_once('-') {
+            Some((a, b)) => a.trim().parse().ok()?..=b.trim().parse().ok()?,
+            None => {
+                let line = item.parse().ok()?;
+                line..=line
+            }
+        };
+        out.push(range);
+    }
+    Some(out)
+}
+
+impl CodeAttrs {
+    /// Parse the attributes from the rest of the info string of a code block.
+    ///
+    /// Unknown attributes get ignored, and collected in `unknown`.
+    pub fn parse(meta: Option<&str>) -> Self {
+        let mut out = Self::default();
+        let Some(inner) = meta
+            .map(str::trim)
+            .and_then(|x| x.strip_prefix('{'))
+            .and_then(|x| x.strip_suffix('}'))
+        else {
+            return out;
+        };
+        for attr in split_top_level(inner).into_iter().map(str::trim) {
+            let (key, value) = match attr.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (attr, None),
+            };
+            match (key, value) {
+                ("", None) => {}
+                ("linenos", None) => out.linenos = true,
+                ("hl_lines", Some(value)) if let Some(lines) = parse_lines(value) => {
+                    out.hl_lines = lines;
+                }
+                ("title", Some(value)) => {
+                    let unquoted = value.strip_prefix('"').and_then(|x| x.strip_suffix('"'));
+                    out.title = Some(unquoted.unwrap_or(value).to_string());
+                }
+                _ => out.unknown.push(attr.to_string()),
+            }
+        }
+        out
+    }
+
+    /// Whether the lines of the code block need to be marked up individually.
+    pub fn has_lines(&self) -> bool {
+        self.linenos || !self.hl_lines.is_empty()
+    }
+
+    /// Wrap each line of a code block in a `<span class="line">`, adding line numbers,
+    /// and a `highlighted` class on the selected lines.
+    ///
+    /// Each line is HTML, possibly ending with a newline.
+    pub fn write_lines<S: AsRef<str>>(&self, lines: &[S]) -> String {
+        let mut out = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            let number = i + 1;
+            let line = line.as_ref();
+            let (content, ending) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            out.push_str("<span class=\"line");
+            if self.hl_lines.iter().any(|range| range.contains(&number)) {
+                out.push_str(" highlighted");
+            }
+            out.push_str("\">");
+            if self.linenos {
+                out.push_str(&format!("<span class=\"line-number\">{number}</span>"));
+            }
+            out.push_str(content);
+            out.push_str("</span>");
+            out.push_str(ending);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn attributes() {
+        assert_eq!(
+            CodeAttrs::parse(Some("{linenos, hl_lines=[3,7-9]}")),
+            CodeAttrs {
+                linenos: true,
+                hl_lines: vec![3..=3, 7..=9],
+                title: None,
+                unknown: Vec::new(),
+            }
+        );
+        assert_eq!(
+            CodeAttrs::parse(Some("{title=\"a, b.rs\"}"))
+                .title
+                .as_deref(),
+            Some("a, b.rs")
+        );
+        assert_eq!(CodeAttrs::parse(Some("{wrap}")).unknown, vec!["wrap"]);
+        assert_eq!(CodeAttrs::parse(Some("ignore")), CodeAttrs::default());
+        assert_eq!(CodeAttrs::parse(None), CodeAttrs::default());
+        let attrs = CodeAttrs::parse(Some("{hl_lines=[2]}"));
+        assert_eq!(
+            attrs.write_lines(&["a\n", "b\n"]),
+            "<span class=\"line\">a</span>\n<span class=\"line highlighted\">b</span>\n"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use markdown::mdast;
+use regex::Regex;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    sync::LazyLock,
+};
+
+use crate::config::EquationNumbering;
+
+static LABEL_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\label\{([^{}]+)\}").unwrap());
+static REF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\(?:eq)?ref\{([^{}]+)\}").unwrap());
+
+/// Remove a `\label{...}` from an expression, returning the label, if any.
+pub fn strip_label(expr: &str) -> (Cow<'_, str>, Option<&str>) {
+    match LABEL_RE.captures(expr) {
+        None => (Cow::Borrowed(expr), None),
+        Some(capture) => {
+            let label = capture.get(1).map(|x| x.as_str().trim());
+            (LABEL_RE.replace(expr, ""), label)
+        }
+    }
+}
+
+/// If an expression is just a reference, like `\eqref{foo}`, return the label.
+pub fn as_reference(expr: &str) -> Option<&str> {
+    let capture = REF_RE.captures(expr.trim())?;
+    let whole = capture.get(0)?;
+    if whole.len() != expr.trim().len() {
+        return None;
+    }
+    Some(capture.get(1)?.as_str().trim())
+}
+
+/// Split text around each `\eqref{...}` pointing to one of the equations.
+///
+/// This yields the text before each reference along with its label,
+/// and then whatever text remains after the last reference. References to
+/// labels which aren't in the page are left as they are, since prose can
+/// well mention `\ref{...}` without meaning an equation.
+pub fn split_references<'a>(
+    text: &'a str,
+    equations: &'a Equations,
+) -> impl Iterator<Item = (&'a str, Option<&'a str>)> {
+    let mut pos = 0;
+    let mut captures = REF_RE
+        .captures_iter(text)
+        .filter(|capture| equations.number(capture[1].trim()).is_some());
+    std::iter::from_fn(move || match captures.next() {
+        Some(capture) => {
+            let whole = capture.get(0).unwrap();
+            let before = &text[pos..whole.start()];
+            pos = whole.end();
+            Some((before, Some(capture.get(1).unwrap().as_str().trim())))
+        }
+        None if pos < text.len() => {
+            let rest = &text[pos..];
+            pos = text.len();
+            Some((rest, None))
+        }
+        None => None,
+    })
+}
+
+/// The number and label assigned to a display equation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Equation {
+    pub number: Option<String>,
+    pub label: Option<String>,
+}
+
+/// The numbering of all the display equations in a page.
+///
+/// This is computed ahead of time, so that references can point forward.
+#[derive(Default)]
+pub struct Equations {
+    by_node: HashMap<*const mdast::Math, Equation>,
+    numbers: BTreeMap<String, String>,
+}
+
+impl Equations {
+    /// Number the equations in a document.
+    ///
+    /// Equations with a label always get a number, but unlabelled ones only do
+    /// with numbering enabled. Sections are the top-level headings of the document,
+    /// counting from 1, with any equations before the first heading in a section of their own.
+    pub fn collect(ast: &mdast::Node, numbering: EquationNumbering) -> Self {
+        let mut nodes = Vec::new();
+        let mut q = vec![ast];
+        while let Some(node) = q.pop() {
+            if let Some(children) = node.children() {
+                q.extend(children.iter().rev());
+            }
+            if matches!(node, mdast::Node::Math(_) | mdast::Node::Heading(_)) {
+                nodes.push(node);
+            }
+        }
+        let top_depth = nodes
+            .iter()
+            .filter_map(|node| match node {
+                mdast::Node::Heading(h) => Some(h.depth),
+                _ => None,
+            })
+            .min();
+        let mut out = Self::default();
+        let mut section = 0;
+        let mut count = 0;
+        for node in nodes {
+            let math = match node {
+                mdast::Node::Heading(h) => {
+                    if numbering == EquationNumbering::Section && Some(h.depth) == top_depth {
+                        section += 1;
+                        count = 0;
+                    }
+                    continue;
+                }
+                mdast::Node::Math(math) => math,
+                _ => continue,
+            };
+            let (_, label) = strip_label(&math.value);
+            let number = match (numbering, label) {
+                (EquationNumbering::None, None) => None,
+                (EquationNumbering::Section, _) => {
+                    section = section.max(1);
+                    count += 1;
+                    Some(format!("{section}.{count}"))
+                }
+                _ => {
+                    count += 1;
+                    Some(count.to_string())
+                }
+            };
+            if let (Some(label), Some(number)) = (label, &number) {
+                out.numbers.insert(label.to_string(), number.clone());
+            }
+            out.by_node.insert(
+                math,
+                Equation {
+                    number,
+                    label: label.map(|x| x.to_string()),
+                },
+            );
+        }
+        out
+    }
+
+    /// Get the numbering for a specific equation.
+    pub fn get(&self, math: &mdast::Math) -> Option<&Equation> {
+        self.by_node.get(&(math as *const _))
+    }
+
+    /// Look up the number of the equation with a given label.
+    pub fn number(&self, label: &str) -> Option<&str> {
+        self.numbers.get(label).map(|x| x.as_str())
+    }
+
+    /// Consume this numbering, returning the number for each label.
+    pub fn into_labels(self) -> BTreeMap<String, String> {
+        self.numbers
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::markdown::make_mdast;
+
+    fn numbers(data: &str, numbering: EquationNumbering) -> BTreeMap<String, String> {
+        let ast = make_mdast(data).unwrap();
+        Equations::collect(&ast, numbering).into_labels()
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            strip_label(r"x = 1 \label{eq:one}"),
+            (Cow::Borrowed("x = 1 "), Some("eq:one"))
+        );
+        assert_eq!(as_reference(r" \eqref{eq:one} "), Some("eq:one"));
+        assert_eq!(as_reference(r"\eqref{eq:one} + 1"), None);
+    }
+
+    #[test]
+    fn references() {
+        let ast = make_mdast("$$\nx \\label{a}\n$$\n\n$$\ny \\label{b}\n$$\n").unwrap();
+        let equations = Equations::collect(&ast, EquationNumbering::None);
+        assert_eq!(
+            split_references(r"see \eqref{a} and \ref{b}.", &equations).collect::<Vec<_>>(),
+            vec![("see ", Some("a")), (" and ", Some("b")), (".", None)]
+        );
+        assert_eq!(
+            split_references(r"write \ref{fig} in LaTeX", &equations).collect::<Vec<_>>(),
+            vec![(r"write \ref{fig} in LaTeX", None)]
+        );
+    }
+
+    #[test]
+    fn numbering() {
+        let data = "# A\n\n$$\nx \\label{a}\n$$\n\n$$\ny\n$$\n\n# B\n\n$$\nz \\label{c}\n$$\n";
+        let none = numbers(data, EquationNumbering::None);
+        assert_eq!(none["a"], "1");
+        assert_eq!(none["c"], "2");
+        let all = numbers(data, EquationNumbering::All);
+        assert_eq!(all["c"], "3");
+        let section = numbers(data, EquationNumbering::Section);
+        assert_eq!(section["a"], "1.1");
+        assert_eq!(section["c"], "2.1");
+        let preamble = format!("$$\nw \\label{{p}}\n$$\n\n{data}");
+        let section = numbers(&preamble, EquationNumbering::Section);
+        assert_eq!(section["p"], "1.1");
+        assert_eq!(section["a"], "2.1");
+    }
+}
@@ -0,0 +1,42 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Render a graph in the DOT language to SVG, using the `dot` command from Graphviz.
+///
+/// This fails if Graphviz isn't installed, or the graph is invalid,
+/// so that the graph can be shown as code instead.
+pub fn render(source: &str) -> Result<String, String> {
+    let child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            return Err(format!(
+                "failed to run Graphviz `dot`, showing the graph as code: {e}"
+            ));
+        }
+    };
+    let failed = |e: &dyn std::fmt::Display| format!("failed to render graph: {e}");
+    // Writing everything before reading is fine, since `dot` needs the whole graph anyways.
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(source.as_bytes()),
+        None => Ok(()),
+    };
+    let output = child.wait_with_output().map_err(|e| failed(&e))?;
+    if written.is_err() || !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(failed(&error.trim()));
+    }
+    let svg = String::from_utf8(output.stdout).map_err(|e| failed(&e))?;
+    // Skip the XML declaration and doctype, which don't belong inside of HTML.
+    let start = svg
+        .find("<svg")
+        .ok_or_else(|| failed(&"no `<svg>` in the output"))?;
+    Ok(svg[start..].trim_end().to_string())
+}
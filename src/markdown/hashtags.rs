@@ -0,0 +1,90 @@
+use markdown::mdast;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// A tag inside of text, like `#rust`, or `#programming/rust`, after whitespace.
+static HASHTAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|\s)#([\p{L}\p{N}_\-/]+)").unwrap());
+
+/// Check that a tag has something other than numbers, so that `#1` isn't one.
+fn is_tag(tag: &str) -> bool {
+    tag.chars()
+        .any(|c| c.is_alphabetic() || c == '_' || c == '-')
+}
+
+/// Split text around each inline tag, like `#rust`.
+///
+/// This yields the text before each tag along with the tag, without the `#`,
+/// and then whatever text remains after the last tag.
+pub fn split_hashtags(text: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    let mut pos = 0;
+    let mut captures = HASHTAG_RE.captures_iter(text);
+    std::iter::from_fn(move || {
+        for capture in captures.by_ref() {
+            let group = capture.get(1).unwrap();
+            let tag = group.as_str().trim_end_matches('/');
+            if !is_tag(tag) {
+                continue;
+            }
+            // The tag starts at the `#`, right before the group.
+            let before = &text[pos..group.start() - 1];
+            pos = group.start() + tag.len();
+            return Some((before, Some(tag)));
+        }
+        if pos < text.len() {
+            let rest = &text[pos..];
+            pos = text.len();
+            return Some((rest, None));
+        }
+        None
+    })
+}
+
+/// Find the inline tags in the text of a document, outside of links and code.
+pub fn collect_hashtags(ast: &mdast::Node) -> Vec<String> {
+    use mdast::Node::*;
+
+    let mut out = Vec::<String>::new();
+    let mut q = vec![ast];
+    while let Some(node) = q.pop() {
+        match node {
+            Link(_) | LinkReference(_) => {}
+            Text(n) => {
+                for (_, tag) in split_hashtags(&n.value) {
+                    if let Some(tag) = tag
+                        && !out.iter().any(|x| x == tag)
+                    {
+                        out.push(tag.to_string());
+                    }
+                }
+            }
+            _ => {
+                if let Some(children) = node.children() {
+                    q.extend(children.iter().rev());
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::markdown::make_mdast;
+
+    #[test]
+    fn hashtags() {
+        let split = split_hashtags("#a b#c #1 #d/e/, #f").collect::<Vec<_>>();
+        assert_eq!(
+            split,
+            vec![
+                ("", Some("a")),
+                (" b#c #1 ", Some("d/e")),
+                ("/, ", Some("f"))
+            ]
+        );
+        let ast = make_mdast("#rust and #go\n\n[#not](x) `#code` *#rust*\n").unwrap();
+        assert_eq!(collect_hashtags(&ast), vec!["rust", "go"]);
+    }
+}
@@ -0,0 +1,193 @@
+/// The theme used for highlighting, unless the config picks another one.
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Where the stylesheet for highlighting with classes gets written.
+pub const HIGHLIGHT_CSS: &str = "/highlight.css";
+
+#[cfg(feature = "highlight")]
+mod imp {
+    use std::sync::LazyLock;
+    use syntect::{
+        easy::HighlightLines,
+        highlighting::ThemeSet,
+        html::{
+            ClassStyle, IncludeBackground, css_for_theme_with_class_style,
+            line_tokens_to_classed_spans, styled_line_to_highlighted_html,
+        },
+        parsing::{ParseState, ScopeStack, SyntaxSet},
+        util::LinesWithEndings,
+    };
+
+    /// A prefix for the classes of highlighted code, so they don't clash with others, like `string`.
+    const CLASS_PREFIX: &str = "hl-";
+    const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+        prefix: CLASS_PREFIX,
+    };
+
+    static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+    static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+    /// Check if a theme with this name exists.
+    pub fn has_theme(name: &str) -> bool {
+        THEMES.themes.contains_key(name)
+    }
+
+    /// Highlight some code, returning `None` if the language isn't known,
+    /// and an error if highlighting failed.
+    ///
+    /// The output is the HTML for each line, with its line ending, as a series of
+    /// `<span>` elements with inline styles. The elements don't span multiple lines.
+    pub fn highlight(code: &str, lang: &str, theme: &str) -> Result<Option<Vec<String>>, String> {
+        let (Some(syntax), Some(theme)) = (
+            SYNTAXES.find_syntax_by_token(lang),
+            THEMES.themes.get(theme),
+        ) else {
+            return Ok(None);
+        };
+        let failed = |e: syntect::Error| format!("failed to highlight `{lang}` code: {e}");
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let mut out = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            let regions = highlighter
+                .highlight_line(line, &SYNTAXES)
+                .map_err(failed)?;
+            out.push(
+                styled_line_to_highlighted_html(&regions, IncludeBackground::No).map_err(failed)?,
+            );
+        }
+        Ok(Some(out))
+    }
+
+    /// The HTML for a line of code, with the elements for each scope.
+    fn classed_line(
+        parse_state: &mut ParseState,
+        stack: &mut ScopeStack,
+        line: &str,
+    ) -> Result<String, syntect::Error> {
+        let ops = parse_state.parse_line(line, &SYNTAXES)?;
+        let (html, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, stack)?;
+        Ok(html)
+    }
+
+    /// Highlight some code with classes, returning `None` if the language isn't known.
+    ///
+    /// Like `highlight`, this returns the HTML for each line, with the elements
+    /// for the scopes still open at the end of a line getting reopened on the next one.
+    pub fn highlight_classes(code: &str, lang: &str) -> Result<Option<Vec<String>>, String> {
+        let Some(syntax) = SYNTAXES.find_syntax_by_token(lang) else {
+            return Ok(None);
+        };
+        let mut parse_state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        let mut out = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            let mut html = String::new();
+            for scope in stack.as_slice() {
+                let classes = scope
+                    .build_string()
+                    .split('.')
+                    .map(|atom| format!("{CLASS_PREFIX}{atom}"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                html.push_str(&format!("<span class=\"{classes}\">"));
+            }
+            let spans = classed_line(&mut parse_state, &mut stack, line)
+                .map_err(|e| format!("failed to highlight `{lang}` code: {e}"))?;
+            html.push_str(&spans);
+            html.push_str(&"</span>".repeat(stack.len()));
+            out.push(html);
+        }
+        Ok(Some(out))
+    }
+
+    fn theme_css(theme: &str) -> Option<String> {
+        css_for_theme_with_class_style(THEMES.themes.get(theme)?, CLASS_STYLE).ok()
+    }
+
+    /// The stylesheet for code highlighted with classes, using the colors of a theme.
+    ///
+    /// A dark theme overrides these colors for readers preferring a dark color scheme.
+    pub fn stylesheet(theme: &str, dark_theme: Option<&str>) -> Option<String> {
+        let mut css = theme_css(theme)?;
+        if let Some(dark_theme) = dark_theme {
+            let dark = theme_css(dark_theme)?;
+            css.push_str(&format!(
+                "\n@media (prefers-color-scheme: dark) {{\n{dark}}}\n"
+            ));
+        }
+        Some(css)
+    }
+}
+
+#[cfg(not(feature = "highlight"))]
+mod imp {
+    /// Check if a theme with this name exists.
+    pub fn has_theme(_name: &str) -> bool {
+        true
+    }
+
+    /// Highlight some code, returning `None` if this isn't possible.
+    pub fn highlight(
+        _code: &str,
+        _lang: &str,
+        _theme: &str,
+    ) -> Result<Option<Vec<String>>, String> {
+        Ok(None)
+    }
+
+    /// Highlight some code with classes, returning `None` if this isn't possible.
+    pub fn highlight_classes(_code: &str, _lang: &str) -> Result<Option<Vec<String>>, String> {
+        Ok(None)
+    }
+
+    /// The stylesheet for code highlighted with classes, if that's possible.
+    pub fn stylesheet(_theme: &str, _dark_theme: Option<&str>) -> Option<String> {
+        None
+    }
+}
+
+pub use imp::{has_theme, highlight, highlight_classes, stylesheet};
+
+#[cfg(all(test, feature = "highlight"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn highlighting() {
+        let out = highlight("fn main() {}\n// hi\n", "rust", DEFAULT_THEME)
+            .unwrap()
+            .unwrap();
+        assert_eq!(out.len(), 2);
+        assert!(out[0].contains("<span style="));
+        assert!(out[0].contains("main"));
+        assert_eq!(
+            highlight("x < y", "not-a-language", DEFAULT_THEME),
+            Ok(None)
+        );
+        assert!(!has_theme("not-a-theme"));
+    }
+
+    #[test]
+    fn highlighting_classes() {
+        let out = highlight_classes("/* a\nb */ fn main() {}\n", "rust")
+            .unwrap()
+            .unwrap();
+        assert_eq!(out.len(), 2);
+        for line in &out {
+            assert_eq!(
+                line.matches("<span").count(),
+                line.matches("</span>").count()
+            );
+        }
+        assert!(out[1].starts_with("<span class=\"hl-source hl-rust\"><span class=\"hl-comment"));
+        assert!(!out.concat().contains("style="));
+        let css = stylesheet(DEFAULT_THEME, None).unwrap();
+        assert!(css.contains(".hl-comment"));
+        let dual = stylesheet(DEFAULT_THEME, Some("base16-ocean.dark")).unwrap();
+        let (light, dark) = dual
+            .split_once("@media (prefers-color-scheme: dark) {")
+            .unwrap();
+        assert_eq!(light.trim(), css.trim());
+        assert!(dark.contains("Base16 Ocean Dark"));
+    }
+}
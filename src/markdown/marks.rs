@@ -0,0 +1,45 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Highlighted text, like `==important==`, with no spaces just inside of the `==`.
+static MARK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"==([^=\s](?:[^=]*[^=\s])?)==").unwrap());
+
+/// Split text around its highlights, like `==important==`, the way Obsidian writes them.
+///
+/// This yields each piece of the text, without the `==`, along with whether it's highlighted.
+/// Highlights only work inside of a single piece of text, so `==some *text*==` stays as is.
+pub fn split_marks(text: &str) -> impl Iterator<Item = (&str, bool)> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    for capture in MARK_RE.captures_iter(text) {
+        let (whole, inner) = (capture.get(0).unwrap(), capture.get(1).unwrap());
+        if whole.start() > pos {
+            out.push((&text[pos..whole.start()], false));
+        }
+        out.push((inner.as_str(), true));
+        pos = whole.end();
+    }
+    if pos < text.len() {
+        out.push((&text[pos..], false));
+    }
+    out.into_iter()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn marks() {
+        let split = split_marks("a ==b c== d ==e==").collect::<Vec<_>>();
+        assert_eq!(
+            split,
+            vec![("a ", false), ("b c", true), (" d ", false), ("e", true)]
+        );
+        let plain = ["x == y == z", "a === b", "====", "== a=="];
+        for text in plain {
+            assert_eq!(split_marks(text).collect::<Vec<_>>(), vec![(text, false)]);
+        }
+    }
+}
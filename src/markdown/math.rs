@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{diagnostic::Warnings, fs_utils::write_if_changed};
+
+/// The KaTeX stylesheet, for sites which don't provide their own copy in `katex/`.
+pub const KATEX_CDN_CSS: &str = "https://cdn.jsdelivr.net/npm/katex@0.16.22/dist/katex.min.css";
+
+/// The name of the file keeping rendered math between builds, inside of the output directory.
+pub const MATH_CACHE_FILE: &str = ".clog-math-cache";
+
+/// An expression, and whether it's in display mode.
+type Key = (String, bool);
+
+/// How rendered math gets stored on disk.
+#[derive(Serialize, Deserialize)]
+struct StoredMath {
+    /// The version of clog, and thus KaTeX, which rendered the math.
+    version: String,
+    entries: Vec<(String, bool, String)>,
+}
+
+/// The HTML for math expressions which were already rendered.
+///
+/// This is shared between the threads of a build, so that each expression only gets
+/// rendered once, and can be saved, to skip rendering them in later builds.
+///
+/// Only the expressions used by a build get saved, so that old ones don't pile up.
+/// Pages reused from an earlier build don't need theirs, since their HTML is kept.
+#[derive(Debug, Default)]
+pub struct MathCache {
+    /// The expressions rendered, or looked up, in this build.
+    entries: Mutex<HashMap<Key, String>>,
+    /// The expressions from an earlier build, which haven't been looked up yet.
+    loaded: Mutex<HashMap<Key, String>>,
+}
+
+impl MathCache {
+    /// Load the cache from a file, returning an empty cache if it's missing, invalid,
+    /// or from another version.
+    pub fn load(path: &Path, warnings: &mut Warnings) -> Self {
+        let Ok(data) = fs::read(path) else {
+            return Self::default();
+        };
+        let stored = match serde_json::from_slice::<StoredMath>(&data) {
+            Ok(stored) => stored,
+            Err(e) => {
+                warnings.push(path, format!("ignoring invalid math cache: {e}"));
+                return Self::default();
+            }
+        };
+        if stored.version != env!("CARGO_PKG_VERSION") {
+            return Self::default();
+        }
+        let loaded = stored
+            .entries
+            .into_iter()
+            .map(|(expr, display_mode, html)| ((expr, display_mode), html))
+            .collect();
+        Self {
+            entries: Mutex::default(),
+            loaded: Mutex::new(loaded),
+        }
+    }
+
+    /// Save the expressions used in this build, leaving out the others.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((expr, display_mode), html)| (expr.clone(), *display_mode, html.clone()))
+            .collect::<Vec<_>>();
+        // Sorting avoids needlessly changing the file, which only gets written if it changed.
+        entries.sort_unstable();
+        let stored = StoredMath {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            entries,
+        };
+        write_if_changed(path, serde_json::to_vec(&stored)?)
+    }
+
+    #[cfg_attr(not(feature = "math"), allow(dead_code))]
+    fn get(&self, expr: &str, display_mode: bool) -> Option<String> {
+        let key = (expr.to_string(), display_mode);
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(html) = entries.get(&key) {
+            return Some(html.clone());
+        }
+        let html = self.loaded.lock().unwrap().remove(&key)?;
+        entries.insert(key, html.clone());
+        Some(html)
+    }
+
+    #[cfg_attr(not(feature = "math"), allow(dead_code))]
+    fn insert(&self, expr: &str, display_mode: bool, html: String) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert((expr.to_string(), display_mode), html);
+    }
+}
+
+/// Renders math expressions to HTML.
+///
+/// With the `math` feature disabled, nothing gets rendered, and the writer
+/// falls back to showing the source of each expression as code.
+#[derive(Default)]
+pub struct MathContext {
+    #[cfg(feature = "math")]
+    katex: katex::KatexContext,
+    /// Expressions which were already rendered, possibly by another context.
+    #[cfg_attr(not(feature = "math"), allow(dead_code))]
+    cache: Arc<MathCache>,
+    /// How long rendering took, since this was last taken.
+    elapsed: Cell<Duration>,
+    /// Why the last expression failed to render, if it did.
+    #[cfg_attr(not(feature = "math"), allow(dead_code))]
+    error: Cell<Option<String>>,
+}
+
+/// Sort the declarations inside of each `style` attribute.
+///
+/// KaTeX writes these in an arbitrary order, which would otherwise change the output
+/// of a page between builds. Each property only appears once, so the order doesn't matter.
+#[cfg(feature = "math")]
+fn sort_styles(html: &str) -> String {
+    use regex::{Captures, Regex};
+    use std::sync::LazyLock;
+
+    static STYLE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"style="([^"]*)""#).unwrap());
+
+    STYLE_RE
+        .replace_all(html, |caps: &Captures<'_>| {
+            let mut declarations = caps[1]
+                .split(';')
+                .filter(|x| !x.trim().is_empty())
+                .collect::<Vec<_>>();
+            declarations.sort_unstable();
+            let sorted = declarations
+                .iter()
+                .map(|x| format!("{x};"))
+                .collect::<String>();
+            format!("style=\"{sorted}\"")
+        })
+        .into_owned()
+}
+
+impl MathContext {
+    /// Create a context, sharing rendered expressions through a cache.
+    pub fn with_cache(cache: Arc<MathCache>) -> Self {
+        Self {
+            cache,
+            ..Default::default()
+        }
+    }
+
+    /// How long rendering math has taken, since the last call.
+    pub fn take_elapsed(&self) -> Duration {
+        self.elapsed.take()
+    }
+
+    /// Why the last expression failed to render, if it did, and this wasn't already taken.
+    pub fn take_error(&self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Render an expression, returning `None` if this wasn't possible.
+    #[cfg(feature = "math")]
+    pub fn render(&self, expr: &str, display_mode: bool) -> Option<String> {
+        if let Some(html) = self.cache.get(expr, display_mode) {
+            return Some(html);
+        }
+        let settings = katex::Settings {
+            display_mode,
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        let rendered = katex::render_to_string(&self.katex, expr, &settings);
+        self.elapsed.set(self.elapsed.get() + start.elapsed());
+        match rendered {
+            Err(e) => {
+                self.error.set(Some(e.to_string()));
+                None
+            }
+            Ok(math) => {
+                let html = sort_styles(&math);
+                self.cache.insert(expr, display_mode, html.clone());
+                Some(html)
+            }
+        }
+    }
+
+    /// Render an expression, returning `None` if this wasn't possible.
+    #[cfg(not(feature = "math"))]
+    pub fn render(&self, _expr: &str, _display_mode: bool) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(all(test, feature = "math"))]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn sorted_styles() {
+        assert_eq!(
+            sort_styles(r#"<span style="top:-3em;margin-right:0.05em;">"#),
+            r#"<span style="margin-right:0.05em;top:-3em;">"#
+        );
+    }
+
+    #[test]
+    fn caching() {
+        let dir = TempDir::new("math");
+        let path = dir.join("cache.json");
+        let cache = Arc::new(MathCache::default());
+        let math_ctx = MathContext::with_cache(Arc::clone(&cache));
+        let html = math_ctx.render("x^2", false).unwrap();
+        cache.insert("y", true, "cached".to_string());
+        cache.insert("unused", true, "old".to_string());
+        cache.save(&path).unwrap();
+        let cache = Arc::new(MathCache::load(&path, &mut Warnings::default()));
+        let math_ctx = MathContext::with_cache(Arc::clone(&cache));
+        assert_eq!(math_ctx.render("x^2", false), Some(html));
+        assert_eq!(math_ctx.render("y", true).as_deref(), Some("cached"));
+        assert_ne!(math_ctx.render("y", false).as_deref(), Some("cached"));
+        // Expressions which weren't used in the last build get left out.
+        cache.save(&path).unwrap();
+        let cache = MathCache::load(&path, &mut Warnings::default());
+        assert_eq!(cache.get("y", true).as_deref(), Some("cached"));
+        assert_eq!(cache.get("unused", true), None);
+    }
+}
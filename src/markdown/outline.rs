@@ -0,0 +1,95 @@
+use std::fmt;
+
+/// A problem with the heading structure of a page.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeadingIssue {
+    /// A heading jumped more than one level deeper than the previous one, e.g. `h2` to `h4`.
+    Skipped { from: u8, to: u8 },
+    /// The page contains more than one `h1`.
+    MultipleH1 { count: usize },
+}
+
+impl fmt::Display for HeadingIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Skipped { from, to } => write!(f, "heading level skipped (h{from} -> h{to})"),
+            Self::MultipleH1 { count } => write!(f, "page has {count} h1 headings"),
+        }
+    }
+}
+
+/// Tracks the sequence of headings in a page, in document order.
+#[derive(Default)]
+pub struct Outline {
+    prev: Option<u8>,
+    h1_count: usize,
+    skipped: Vec<HeadingIssue>,
+}
+
+impl Outline {
+    /// Record the next heading in the document.
+    pub fn push(&mut self, depth: u8) {
+        if depth == 1 {
+            self.h1_count += 1;
+        }
+        if let Some(prev) = self.prev
+            && depth > prev + 1
+        {
+            self.skipped.push(HeadingIssue::Skipped {
+                from: prev,
+                to: depth,
+            });
+        }
+        self.prev = Some(depth);
+    }
+
+    /// Consume the outline, returning all of the issues found.
+    pub fn issues(self) -> Vec<HeadingIssue> {
+        let mut out = self.skipped;
+        if self.h1_count > 1 {
+            out.push(HeadingIssue::MultipleH1 {
+                count: self.h1_count,
+            });
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn issues(depths: &[u8]) -> Vec<HeadingIssue> {
+        let mut outline = Outline::default();
+        for &d in depths {
+            outline.push(d);
+        }
+        outline.issues()
+    }
+
+    #[test]
+    fn well_formed() {
+        assert_eq!(issues(&[]), vec![]);
+        assert_eq!(issues(&[1, 2, 3, 2, 3, 3]), vec![]);
+        assert_eq!(issues(&[2, 3, 4, 2, 2]), vec![]);
+    }
+
+    #[test]
+    fn skipped() {
+        assert_eq!(
+            issues(&[1, 3, 4, 6]),
+            vec![
+                HeadingIssue::Skipped { from: 1, to: 3 },
+                HeadingIssue::Skipped { from: 4, to: 6 }
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_h1() {
+        assert_eq!(
+            issues(&[1, 2, 1, 1]),
+            vec![HeadingIssue::MultipleH1 { count: 3 }]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+/// Whether a quote after this character opens a quotation, rather than closing one.
+///
+/// `None` is the start of a block, like a paragraph.
+fn opens_quote(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{-–—“‘".contains(c),
+    }
+}
+
+/// Replace straight quotes with curly ones, `--` and `---` with dashes, and `...` with `…`.
+///
+/// The previous character written in the same block gets passed along, and updated,
+/// since text can be split into multiple nodes, e.g. around `*emphasis*`.
+pub fn smarten(text: &str, prev: &mut Option<char>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let replaced = match c {
+            '"' if opens_quote(*prev) => '“',
+            '"' => '”',
+            '\'' if opens_quote(*prev) => '‘',
+            '\'' => '’',
+            '-' if chars.next_if_eq(&'-').is_some() => match chars.next_if_eq(&'-') {
+                Some(_) => '—',
+                None => '–',
+            },
+            '.' if chars.clone().take(2).eq(['.', '.']) => {
+                chars.nth(1);
+                '…'
+            }
+            c => c,
+        };
+        out.push(replaced);
+        *prev = Some(replaced);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn smart(text: &str) -> String {
+        smarten(text, &mut None)
+    }
+
+    #[test]
+    fn punctuation() {
+        assert_eq!(
+            smart("\"Hi,\" she said. 'It's fine...'"),
+            "“Hi,” she said. ‘It’s fine…’"
+        );
+        assert_eq!(smart("1--2 --- or -x"), "1–2 — or -x");
+        assert_eq!(smart("(\"a\")"), "(“a”)");
+        let mut prev = None;
+        assert_eq!(smarten("\"", &mut prev), "“");
+        prev = Some('a');
+        assert_eq!(smarten("\"", &mut prev), "”");
+    }
+}
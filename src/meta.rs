@@ -0,0 +1,116 @@
+use serde::Serialize;
+
+use crate::{config::Config, sitemap::Page};
+
+/// A `<meta>` tag describing a page, for when it gets shared.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MetaTag {
+    /// OpenGraph tags use `property`, while Twitter uses `name`.
+    pub attr: &'static str,
+    pub key: &'static str,
+    pub content: String,
+}
+
+impl MetaTag {
+    fn property(key: &'static str, content: impl Into<String>) -> Self {
+        Self {
+            attr: "property",
+            key,
+            content: content.into(),
+        }
+    }
+
+    fn name(key: &'static str, content: impl Into<String>) -> Self {
+        Self {
+            attr: "name",
+            key,
+            content: content.into(),
+        }
+    }
+}
+
+/// Make the URL of an image absolute, when the `base_url` is known, since sites showing cards need that.
+pub fn image_url(config: &Config, image: &str) -> String {
+    if image.starts_with("https://") || image.starts_with("http://") {
+        return image.to_string();
+    }
+    config.url(&format!("/{}", image.trim_start_matches('/')))
+}
+
+/// The OpenGraph and Twitter card tags for a page.
+///
+/// The cover of a page is used first, then its `image`, and then the site's `image`.
+pub fn page_meta(config: &Config, page: &Page, description: &str) -> Vec<MetaTag> {
+    let front_matter = &page.front_matter;
+    let image = page
+        .cover
+        .as_deref()
+        .or(front_matter.image.as_deref())
+        .or(config.image.as_deref())
+        .map(|image| image_url(config, image));
+    let mut out = vec![
+        MetaTag::property("og:type", "article"),
+        MetaTag::property("og:title", &front_matter.title),
+    ];
+    if let Some(url) = config.absolute_url(&page.link) {
+        out.push(MetaTag::property("og:url", url));
+    }
+    if let Some(title) = &config.title {
+        out.push(MetaTag::property("og:site_name", title));
+    }
+    if !description.is_empty() {
+        out.push(MetaTag::property("og:description", description));
+    }
+    if let Some(image) = &image {
+        out.push(MetaTag::property("og:image", image));
+    }
+    let date = front_matter
+        .published
+        .as_ref()
+        .unwrap_or(&front_matter.date);
+    out.push(MetaTag::property("article:published_time", date));
+    for tag in &front_matter.tags {
+        out.push(MetaTag::property("article:tag", tag));
+    }
+    let card = match image {
+        Some(_) => "summary_large_image",
+        None => "summary",
+    };
+    out.push(MetaTag::name("twitter:card", card));
+    if let Some(twitter) = &config.twitter {
+        out.push(MetaTag::name("twitter:site", twitter));
+    }
+    out.push(MetaTag::name("twitter:title", &front_matter.title));
+    if !description.is_empty() {
+        out.push(MetaTag::name("twitter:description", description));
+    }
+    if let Some(image) = image {
+        out.push(MetaTag::name("twitter:image", image));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn images() {
+        let config = Config {
+            base_url: Some("https://example.com/".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            image_url(&config, "static/cover.png"),
+            "https://example.com/static/cover.png"
+        );
+        assert_eq!(
+            image_url(&config, "/static/cover.png"),
+            "https://example.com/static/cover.png"
+        );
+        assert_eq!(
+            image_url(&config, "https://cdn.example.com/a.png"),
+            "https://cdn.example.com/a.png"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    config::Author,
+    escape::{escape_attr, escape_html},
+};
+
+/// The data exposed about a page through an `h-entry`.
+pub struct EntryData<'a> {
+    pub title: &'a str,
+    pub url: &'a str,
+    pub published: &'a str,
+    pub authors: &'a [String],
+    /// The author to use if the page has none of its own.
+    pub site_author: Option<&'a Author>,
+}
+
+/// Wrap the body of a page in `h-entry` markup.
+///
+/// The properties other than `e-content` are hidden, so that this doesn't affect the
+/// look of the page, while still being picked up by microformats parsers.
+pub fn h_entry(data: &EntryData<'_>, body: &str) -> String {
+    let fallback = data
+        .site_author
+        .filter(|_| data.authors.is_empty())
+        .map(|author| &author.name);
+    let authors = data
+        .authors
+        .iter()
+        .chain(fallback)
+        .map(|author| {
+            format!(
+                "<span class=\"p-author h-card\" hidden>{}</span>\n",
+                escape_html(author)
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<article class=\"h-entry\">\n\
+         <a class=\"u-url\" href=\"{}\" hidden></a>\n\
+         <data class=\"p-name\" value=\"{}\" hidden></data>\n\
+         <time class=\"dt-published\" datetime=\"{}\" hidden></time>\n\
+         {authors}\
+         <div class=\"e-content\">{body}</div>\n\
+         </article>\n",
+        escape_attr(data.url),
+        escape_attr(data.title),
+        escape_attr(data.published),
+    )
+}
+
+/// Create an `h-card` for the author of the site, linking to the home page.
+pub fn h_card(author: &Author, home: &str) -> String {
+    let email = author
+        .email
+        .as_ref()
+        .map(|email| {
+            format!(
+                " <a class=\"u-email\" href=\"mailto:{}\">{}</a>",
+                escape_attr(email),
+                escape_html(email)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<span class=\"h-card\"><a class=\"p-name u-url\" href=\"{}\">{}</a>{email}</span>",
+        escape_attr(home),
+        escape_html(&author.name)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entry_properties() {
+        let site_author = Author {
+            name: "Site".into(),
+            email: None,
+        };
+        let data = EntryData {
+            title: "A \"quoted\" title",
+            url: "/posts/a.html",
+            published: "2024-01-02",
+            authors: &[],
+            site_author: Some(&site_author),
+        };
+        let html = h_entry(&data, "<p>Body</p>");
+        assert!(html.starts_with("<article class=\"h-entry\">"));
+        assert!(html.contains("<a class=\"u-url\" href=\"/posts/a.html\" hidden></a>"));
+        assert!(html.contains("value=\"A &quot;quoted&quot; title\""));
+        assert!(html.contains("<time class=\"dt-published\" datetime=\"2024-01-02\" hidden>"));
+        assert!(html.contains("<span class=\"p-author h-card\" hidden>Site</span>"));
+        assert!(html.contains("<div class=\"e-content\"><p>Body</p></div>"));
+    }
+
+    #[test]
+    fn card() {
+        let author = Author {
+            name: "Alice".into(),
+            email: Some("alice@example.com".into()),
+        };
+        assert_eq!(
+            h_card(&author, "https://example.com/"),
+            "<span class=\"h-card\"><a class=\"p-name u-url\" href=\"https://example.com/\">Alice</a> <a class=\"u-email\" href=\"mailto:alice@example.com\">alice@example.com</a></span>"
+        );
+    }
+}
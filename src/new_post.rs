@@ -0,0 +1,78 @@
+use anyhow::anyhow;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use time::UtcOffset;
+
+use crate::{frontmatter::today, slug::slugify};
+
+/// The markdown for a new post, with its front matter filled in.
+fn post_template(title: &str, date: &str) -> anyhow::Result<String> {
+    // Serializing the title quotes it, if it contains anything special to YAML.
+    let title = serde_yaml::to_string(title)?;
+    Ok(format!(
+        "---\ntitle: {}\ndate: {date}\ntags: []\ndraft: true\n---\n\n",
+        title.trim_end()
+    ))
+}
+
+/// Create a new draft post in the content directory, returning its path.
+///
+/// The file is named after the slugified title, inside of `folder`, if given.
+/// It's dated today, in the timezone with the given offset, or UTC.
+pub fn new_post(
+    content_dir: &Path,
+    title: &str,
+    folder: Option<&Path>,
+    offset: Option<UtcOffset>,
+) -> anyhow::Result<PathBuf> {
+    let slug = slugify(title);
+    if slug.is_empty() {
+        return Err(anyhow!("can't make a file name from the title `{title}`"));
+    }
+    let mut dir = content_dir.to_path_buf();
+    if let Some(folder) = folder {
+        if folder
+            .components()
+            .any(|x| !matches!(x, Component::Normal(_)))
+        {
+            return Err(anyhow!(
+                "the folder should be relative to `content`, without `..`"
+            ));
+        }
+        dir.push(folder);
+    }
+    let path = dir.join(format!("{slug}.md"));
+    if path.exists() {
+        return Err(anyhow!("{} already exists", path.display()));
+    }
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, post_template(title, &today(offset)?)?)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn template() {
+        assert_eq!(
+            post_template("Hello: World", "2024-01-02").unwrap(),
+            "---\ntitle: 'Hello: World'\ndate: 2024-01-02\ntags: []\ndraft: true\n---\n\n"
+        );
+    }
+
+    #[test]
+    fn creates_post() {
+        let dir = TempDir::new("new");
+        let path = new_post(&dir, "My Post Title", Some(Path::new("Posts")), None).unwrap();
+        let again = new_post(&dir, "My Post Title", Some(Path::new("Posts")), None);
+        let escape = new_post(&dir, "x", Some(Path::new("../x")), None);
+        assert_eq!(path, dir.join("Posts/my-post-title.md"));
+        assert!(again.is_err());
+        assert!(escape.is_err());
+    }
+}
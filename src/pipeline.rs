@@ -0,0 +1,133 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{Receiver, sync_channel},
+    },
+    thread,
+};
+
+/// How many threads to use for reading, which is mostly waiting on IO.
+const IO_THREADS: usize = 8;
+
+/// Receive the next item from a receiver shared between threads.
+fn recv_shared<T>(rx: &Mutex<Receiver<T>>) -> Option<T> {
+    rx.lock().ok()?.recv().ok()
+}
+
+/// Run a pipeline over some items, in three stages.
+///
+/// - `read` runs on a pool of IO threads, and should do the file reading.
+/// - `process` runs on a pool of worker threads, one per CPU, with each worker
+///   having its own state, created with `init`.
+/// - `write` runs on the calling thread, receiving the output of processing.
+///
+/// The stages are connected with bounded channels, so reading can only get slightly
+/// ahead of processing. Items reach `write` in no particular order.
+///
+/// The first error in any stage stops the pipeline, and gets returned.
+pub fn run<T, R, O, S>(
+    items: &[T],
+    read: impl Fn(&T) -> anyhow::Result<R> + Sync,
+    init: impl Fn() -> S + Sync,
+    process: impl Fn(&mut S, &T, R) -> anyhow::Result<O> + Sync,
+    mut write: impl FnMut(&T, O) -> anyhow::Result<()>,
+) -> anyhow::Result<()>
+where
+    T: Sync,
+    R: Send,
+    O: Send,
+{
+    let workers = thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1);
+    let next = AtomicUsize::new(0);
+    let (read_tx, read_rx) = sync_channel::<(usize, anyhow::Result<R>)>(2 * workers);
+    let (process_tx, process_rx) = sync_channel::<(usize, anyhow::Result<O>)>(2 * workers);
+    // Shared between the workers, so that the channel closes once they've all stopped.
+    let read_rx = Arc::new(Mutex::new(read_rx));
+    thread::scope(|s| {
+        for _ in 0..IO_THREADS.min(items.len()) {
+            let read_tx = read_tx.clone();
+            let (next, read) = (&next, &read);
+            s.spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    if read_tx.send((i, read(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(read_tx);
+        for _ in 0..workers {
+            let read_rx = Arc::clone(&read_rx);
+            let process_tx = process_tx.clone();
+            let (init, process) = (&init, &process);
+            s.spawn(move || {
+                let mut state = init();
+                while let Some((i, data)) = recv_shared(&read_rx) {
+                    let out = data.and_then(|data| process(&mut state, &items[i], data));
+                    if process_tx.send((i, out)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(read_rx);
+        drop(process_tx);
+        // Dropping the receiver on error makes the other stages stop early.
+        for (i, out) in process_rx {
+            write(&items[i], out?)?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn processes_every_item() {
+        let items = (0..100).collect::<Vec<u64>>();
+        let mut out = Vec::new();
+        run(
+            &items,
+            |&x| Ok(x * 2),
+            || 1,
+            |state, _, x| Ok(x + *state),
+            |&item, x| {
+                out.push((item, x));
+                Ok(())
+            },
+        )
+        .unwrap();
+        out.sort();
+        assert_eq!(
+            out,
+            items.iter().map(|&x| (x, 2 * x + 1)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn stops_on_error() {
+        let items = (0..1000).collect::<Vec<u64>>();
+        let result = run(
+            &items,
+            |&x| Ok(x),
+            || (),
+            |_, _, x| match x {
+                10 => Err(anyhow!("bad item")),
+                x => Ok(x),
+            },
+            |_, _| Ok(()),
+        );
+        assert_eq!(result.unwrap_err().to_string(), "bad item");
+    }
+}
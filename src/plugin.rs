@@ -0,0 +1,127 @@
+use anyhow::anyhow;
+use markdown::mdast;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use crate::sitemap::Page;
+
+/// What a plugin gets sent for each page, as JSON on its standard input.
+#[derive(Serialize)]
+struct Input<'a> {
+    path: &'a Path,
+    link: &'a str,
+    title: &'a str,
+    date: &'a str,
+    tags: &'a [String],
+    /// The markdown of the page, in the same format as `mdast` in JavaScript.
+    ast: &'a mdast::Node,
+}
+
+/// What a plugin answers with, as JSON on its standard output.
+///
+/// Every field is optional, and an empty answer leaves the page as is.
+#[derive(Deserialize, Default)]
+struct Output {
+    /// A replacement for the markdown of the page.
+    ast: Option<mdast::Node>,
+    /// Values for the template of the page, available as `plugin`.
+    #[serde(default)]
+    context: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Run a single plugin over a page, returning its answer.
+fn run_one(command: &str, dir: &Path, input: &Input<'_>) -> anyhow::Result<Output> {
+    let mut args = command.split_whitespace();
+    let program = args.next().ok_or_else(|| anyhow!("empty plugin command"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("failed to run plugin `{command}`: {e}"))?;
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("missing stdin"))?;
+    // Writing on another thread avoids a deadlock when a plugin answers before reading everything.
+    // Failing to write means that the plugin stopped early, which its status tells us about.
+    let output = thread::scope(|s| {
+        s.spawn(move || serde_json::to_writer(&mut stdin, input));
+        child.wait_with_output()
+    })?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("plugin `{command}` failed: {}", error.trim()));
+    }
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Output::default());
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| anyhow!("plugin `{command}` gave invalid output: {e}"))
+}
+
+/// Run the markdown of a page through each plugin, in order, before it gets written out.
+///
+/// Each plugin is a command, run from `dir`, which receives the page as JSON, and can
+/// answer with a new AST for it, and values for its template. The values from every
+/// plugin are returned, with later plugins overriding earlier ones.
+pub fn run(
+    plugins: &[String],
+    dir: &Path,
+    page: &Page,
+    ast: &mut mdast::Node,
+) -> anyhow::Result<serde_json::Map<String, serde_json::Value>> {
+    let mut context = serde_json::Map::new();
+    for command in plugins {
+        let input = Input {
+            path: &page.in_path,
+            link: &page.link,
+            title: &page.front_matter.title,
+            date: &page.front_matter.date,
+            tags: &page.front_matter.tags,
+            ast,
+        };
+        let output = run_one(command, dir, &input)
+            .map_err(|e| anyhow!("{}: {e}", page.in_path.display()))?;
+        if let Some(new_ast) = output.ast {
+            *ast = new_ast;
+        }
+        context.extend(output.context);
+    }
+    Ok(context)
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+    use crate::{config::Config, markdown::make_mdast, sitemap::SiteMap};
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    #[test]
+    fn plugins() {
+        let dir = TempDir::new("plugin");
+        let script = dir.join("plugin.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\ncat > /dev/null\necho '{\"context\": {\"answer\": 42}}'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("Post.md"), "Hello *world*").unwrap();
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let page = site_map.pages().next().unwrap();
+        let mut ast = make_mdast("Hello *world*").unwrap();
+        let before = ast.clone();
+        // `cat` answers with its input, which has the same AST.
+        let plugins = ["cat".to_string(), "./plugin.sh".to_string()];
+        let context = run(&plugins, &dir, page, &mut ast);
+        let failed = run(&["false".to_string()], &dir, page, &mut ast);
+        assert_eq!(context.unwrap()["answer"], 42);
+        assert_eq!(ast, before);
+        assert!(failed.is_err());
+    }
+}
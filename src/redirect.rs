@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+
+use crate::{config::RedirectsFile, escape::escape_attr};
+
+/// Create a small page redirecting to another URL.
+///
+/// This uses a meta refresh, since static hosting doesn't let us send real redirects,
+/// along with a canonical link, so that search engines index the target instead.
+pub fn redirect_page(target: &str) -> String {
+    let target = escape_attr(target);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Redirecting…</title>\n<link rel=\"canonical\" href=\"{target}\">\n<meta http-equiv=\"refresh\" content=\"0; url={target}\">\n</head>\n<body>\n<p>This page has moved to <a href=\"{target}\">{target}</a>.</p>\n</body>\n</html>\n"
+    )
+}
+
+/// Create a file listing redirects, from old links to new ones, returning its name and contents.
+///
+/// Hosts reading these can send real redirects, instead of needing a page for each.
+pub fn redirects_file(
+    kind: RedirectsFile,
+    redirects: &[(&str, &str)],
+) -> anyhow::Result<(&'static str, String)> {
+    match kind {
+        RedirectsFile::Netlify => {
+            let lines = redirects
+                .iter()
+                .map(|(from, to)| format!("{from} {to} 301\n"))
+                .collect();
+            Ok(("_redirects", lines))
+        }
+        RedirectsFile::Json => {
+            let map = redirects.iter().copied().collect::<BTreeMap<_, _>>();
+            Ok(("redirects.json", serde_json::to_string_pretty(&map)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redirect() {
+        let html = redirect_page("/a?b&c");
+        assert!(html.contains("<meta http-equiv=\"refresh\" content=\"0; url=/a?b&amp;c\">"));
+        assert!(html.contains("<link rel=\"canonical\" href=\"/a?b&amp;c\">"));
+    }
+
+    #[test]
+    fn redirects() {
+        let redirects = [("/old.html", "/new.html"), ("/a/", "/b.html")];
+        let (name, netlify) = redirects_file(RedirectsFile::Netlify, &redirects).unwrap();
+        assert_eq!(name, "_redirects");
+        assert_eq!(netlify, "/old.html /new.html 301\n/a/ /b.html 301\n");
+        let (name, json) = redirects_file(RedirectsFile::Json, &redirects).unwrap();
+        assert_eq!(name, "redirects.json");
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["/old.html"], "/new.html");
+    }
+}
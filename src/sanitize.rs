@@ -0,0 +1,259 @@
+use crate::{
+    escape::{decode_entities, escape_attr},
+    html_check::{tag_end, tag_name},
+};
+
+/// Elements which are kept, with only their allowed attributes.
+const ALLOWED_ELEMENTS: [&str; 52] = [
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Elements which get removed along with everything inside of them.
+const REMOVED_ELEMENTS: [&str; 9] = [
+    "script", "style", "iframe", "object", "embed", "noscript", "template", "textarea", "svg",
+];
+
+/// Attributes allowed on any element.
+const GLOBAL_ATTRIBUTES: [&str; 5] = ["class", "id", "title", "lang", "dir"];
+
+/// Attributes allowed on specific elements.
+const ELEMENT_ATTRIBUTES: [(&str, &str); 13] = [
+    ("a", "href"),
+    ("img", "src"),
+    ("img", "alt"),
+    ("img", "width"),
+    ("img", "height"),
+    ("td", "colspan"),
+    ("td", "rowspan"),
+    ("th", "colspan"),
+    ("th", "rowspan"),
+    ("ol", "start"),
+    ("details", "open"),
+    ("blockquote", "cite"),
+    ("q", "cite"),
+];
+
+/// Attributes containing a URL, which needs to be checked with `is_safe_url`.
+const URL_ATTRIBUTES: [&str; 3] = ["href", "src", "cite"];
+
+/// Check that a URL can't run scripts, like `javascript:alert(1)` would.
+///
+/// Relative URLs are fine, along with `http`, `https`, and `mailto` ones.
+pub fn is_safe_url(url: &str) -> bool {
+    let url = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match url.find(':') {
+        None => true,
+        // A colon after a path, query, or fragment starts, e.g. `a/b:c`, isn't a scheme.
+        Some(i) if url[..i].contains(['/', '?', '#']) => true,
+        Some(i) => matches!(&url[..i], "http" | "https" | "mailto"),
+    }
+}
+
+/// Parse the attributes inside of a tag, like `href="a" hidden`, as names and values.
+fn attributes(s: &str) -> Vec<(String, Option<String>)> {
+    let mut out = Vec::new();
+    let mut rest = s.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    while !rest.is_empty() {
+        let name_len = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_len].to_ascii_lowercase();
+        rest = rest[name_len..].trim_start();
+        let mut value = None;
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (v, remaining) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(q).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = Some(v.to_string());
+            rest = remaining;
+        }
+        if !name.is_empty() {
+            out.push((name, value));
+        }
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    }
+    out
+}
+
+fn is_allowed_attribute(element: &str, name: &str) -> bool {
+    GLOBAL_ATTRIBUTES.contains(&name) || ELEMENT_ATTRIBUTES.contains(&(element, name))
+}
+
+/// Remove anything which could run scripts, or otherwise mess with a page, from some HTML.
+///
+/// This uses an allowlist of elements and attributes. Other elements get removed, but
+/// their content is kept, unless they're something like a `<script>`. Since raw HTML
+/// in markdown is often just part of an element, like `<b>` on its own, this works on
+/// each tag individually, without making sure that they're balanced.
+pub fn sanitize_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        out.push_str(&html[pos..start]);
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            pos = rest
+                .find("-->")
+                .map(|i| start + i + 3)
+                .unwrap_or(html.len());
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos = tag_end(html, start).map(|i| i + 1).unwrap_or(html.len());
+            continue;
+        }
+        let closing = rest.starts_with("</");
+        let name_start = start + if closing { 2 } else { 1 };
+        let name = tag_name(html, name_start);
+        let end = tag_end(html, name_start).filter(|_| !name.is_empty());
+        let Some(end) = end else {
+            // A stray `<`, like in `a < b`.
+            out.push_str("&lt;");
+            pos = start + 1;
+            continue;
+        };
+        pos = end + 1;
+        if REMOVED_ELEMENTS.contains(&name.as_str()) {
+            if !closing {
+                let close = format!("</{name}");
+                pos = match html[pos..].to_ascii_lowercase().find(&close) {
+                    Some(i) => tag_end(html, pos + i).map_or(html.len(), |i| i + 1),
+                    None => html.len(),
+                };
+            }
+            continue;
+        }
+        if !ALLOWED_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+        if closing {
+            out.push_str(&format!("</{name}>"));
+            continue;
+        }
+        out.push('<');
+        out.push_str(&name);
+        for (attr, value) in attributes(&html[name_start + name.len()..end]) {
+            if !is_allowed_attribute(&name, &attr) {
+                continue;
+            }
+            // Values get written out escaped again, so references like `&amp;` stay the same.
+            match value.as_deref().map(decode_entities) {
+                Some(value) if URL_ATTRIBUTES.contains(&attr.as_str()) && !is_safe_url(&value) => {}
+                Some(value) => out.push_str(&format!(" {attr}=\"{}\"", escape_attr(&value))),
+                None => out.push_str(&format!(" {attr}")),
+            }
+        }
+        if html[..end].ends_with('/') {
+            out.push_str(" /");
+        }
+        out.push('>');
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sanitizing() {
+        assert_eq!(
+            sanitize_html("<p class=\"x\" onclick=\"evil()\">Hi <b>there</b></p>"),
+            "<p class=\"x\">Hi <b>there</b></p>"
+        );
+        assert_eq!(
+            sanitize_html("a<script>alert('<p>')</script>b<style>p {}</style>"),
+            "ab"
+        );
+        assert_eq!(
+            sanitize_html("<a href=\"javascript:alert(1)\">x</a><img src=a.png alt='A'/>"),
+            "<a>x</a><img src=\"a.png\" alt=\"A\" />"
+        );
+        assert_eq!(sanitize_html("<blink>a</blink> < b<!-- c -->"), "a &lt; b");
+        assert_eq!(sanitize_html("</b>"), "</b>");
+        assert_eq!(
+            sanitize_html("<a title=\"a &amp; b\" href=\"/x?a=1&amp;b=2\">x</a>"),
+            "<a title=\"a &amp; b\" href=\"/x?a=1&amp;b=2\">x</a>"
+        );
+        assert_eq!(
+            sanitize_html("<a href=\"javascript&#58;x()\">x</a>"),
+            "<a>x</a>"
+        );
+    }
+
+    #[test]
+    fn urls() {
+        assert!(is_safe_url("https://example.com"));
+        assert!(is_safe_url("/a/b:c"));
+        assert!(is_safe_url("#top"));
+        assert!(!is_safe_url("javascript:alert(1)"));
+        assert!(!is_safe_url(" JaVa\tScRiPt:alert(1)"));
+        assert!(!is_safe_url("data:text/html,hi"));
+    }
+}
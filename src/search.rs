@@ -0,0 +1,155 @@
+use serde::Serialize;
+
+/// A page, as it appears in the search index.
+#[derive(Debug, Serialize)]
+pub struct SearchEntry<'a> {
+    pub title: &'a str,
+    pub link: &'a str,
+    pub tags: &'a [String],
+    pub date: &'a str,
+    /// The text of the page, without any markup.
+    pub body: String,
+}
+
+/// Elements whose content isn't text a reader would search for.
+const SKIPPED: [&str; 4] = ["script", "style", "math", "svg"];
+
+/// Elements which separate the text before and after them.
+const BLOCKS: [&str; 22] = [
+    "p",
+    "div",
+    "br",
+    "hr",
+    "li",
+    "ul",
+    "ol",
+    "dt",
+    "dd",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+    "blockquote",
+    "section",
+    "table",
+    "tr",
+    "td",
+    "th",
+];
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Extract the text from some HTML, with the whitespace collapsed.
+///
+/// Elements which aren't meant to be read, like scripts, or the duplicate markup
+/// KaTeX hides with `aria-hidden`, get skipped.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    // The element being skipped, and how many of the same element are open inside of it.
+    let mut skipping: Option<(String, usize)> = None;
+    let mut rest = html;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('<') {
+            let end = after.find('>').map_or(after.len(), |i| i + 1);
+            let tag = &after[..end.saturating_sub(1)];
+            rest = &after[end..];
+            let closing = tag.starts_with('/');
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            let self_closing = tag.ends_with('/');
+            // Blocks separate words, like `<p>a</p><p>b</p>`, unlike `<em>a</em>b`.
+            if BLOCKS.contains(&name.as_str()) {
+                text.push(' ');
+            }
+            match &mut skipping {
+                Some((skipped, depth)) if *skipped == name && !self_closing => {
+                    if !closing {
+                        *depth += 1;
+                    } else if *depth == 0 {
+                        skipping = None;
+                    } else {
+                        *depth -= 1;
+                    }
+                }
+                Some(_) => {}
+                None if closing || self_closing => {}
+                None => {
+                    if SKIPPED.contains(&name.as_str()) || tag.contains("aria-hidden=\"true\"") {
+                        skipping = Some((name, 0));
+                    }
+                }
+            }
+            continue;
+        }
+        let end = rest.find('<').unwrap_or(rest.len());
+        let chunk = &rest[..end];
+        rest = &rest[end..];
+        if skipping.is_some() {
+            continue;
+        }
+        let mut chunk = chunk;
+        while let Some(i) = chunk.find('&') {
+            text.push_str(&chunk[..i]);
+            let after = &chunk[i + 1..];
+            match after
+                .find(';')
+                .and_then(|j| Some((j, decode_entity(&after[..j])?)))
+            {
+                Some((j, c)) => {
+                    text.push(c);
+                    chunk = &after[j + 1..];
+                }
+                None => {
+                    text.push('&');
+                    chunk = after;
+                }
+            }
+        }
+        text.push_str(chunk);
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            html_to_text("<p>Some <em>text</em> &amp; more</p><p>Next&#x21;</p>"),
+            "Some text & more Next!"
+        );
+        assert_eq!(
+            html_to_text(
+                "<p>a <span aria-hidden=\"true\"><span>x</span>y</span> <code>b</code>,</p><script>1 < 2</script>"
+            ),
+            "a b,"
+        );
+        assert_eq!(html_to_text("AT&T <br/> ok"), "AT&T ok");
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// The path pages listen on for reloads, as server-sent events.
+const RELOAD_PATH: &str = "/__clog/reload";
+
+/// Added to the HTML pages being served, to reload them after a rebuild.
+const RELOAD_SCRIPT: &str =
+    "<script>new EventSource(\"/__clog/reload\").onmessage = () => location.reload();</script>";
+
+/// How often to check that pages listening for reloads are still there.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Tells pages being served to reload, whenever the site gets rebuilt.
+#[derive(Debug, Default)]
+pub struct Reload {
+    /// How many times the site has been rebuilt.
+    builds: Mutex<u64>,
+    rebuilt: Condvar,
+}
+
+impl Reload {
+    /// Reload every page listening for it.
+    pub fn notify(&self) {
+        *self.builds.lock().unwrap() += 1;
+        self.rebuilt.notify_all();
+    }
+
+    /// Wait for a build after `seen`, returning the latest one, or `None` after a while.
+    fn wait(&self, seen: u64) -> Option<u64> {
+        let builds = self.builds.lock().unwrap();
+        let (builds, _) = self
+            .rebuilt
+            .wait_timeout_while(builds, KEEP_ALIVE, |x| *x == seen)
+            .unwrap();
+        (*builds != seen).then_some(*builds)
+    }
+}
+
+/// Guess the content type of a file from its extension.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decode the `%XX` escapes in a URL path.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Figure out which file under `root` a request path refers to.
+///
+/// Directories are served through their `index.html`, and a missing extension
+/// falls back to `.html`, so that links without one work like they would when deployed.
+/// Paths trying to escape the root are rejected.
+fn resolve(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let url_path = url_path.split(['?', '#']).next().unwrap_or_default();
+    let decoded = percent_decode(url_path);
+    let mut path = root.to_path_buf();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(x) => path.push(x),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    if path.is_file() {
+        return Some(path);
+    }
+    let html = path.with_extension("html");
+    html.is_file().then_some(html)
+}
+
+/// Add the live reload script to a page, at the end of its body.
+fn inject_reload(html: &[u8]) -> Vec<u8> {
+    let end = html
+        .windows(7)
+        .rposition(|x| x.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+    [&html[..end], RELOAD_SCRIPT.as_bytes(), &html[end..]].concat()
+}
+
+/// Keep a connection open, sending an event each time the site gets rebuilt.
+///
+/// This only stops once writing fails, when the page has gone away.
+fn stream_reloads(reload: &Reload, stream: &mut TcpStream) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+    )?;
+    let mut seen = *reload.builds.lock().unwrap();
+    loop {
+        match reload.wait(seen) {
+            Some(builds) => {
+                seen = builds;
+                write!(stream, "data: reload\n\n")?;
+            }
+            None => write!(stream, ": keep-alive\n\n")?,
+        }
+        stream.flush()?;
+    }
+}
+
+/// Write a response, leaving out the body for `HEAD` requests, while keeping its length.
+fn respond(
+    stream: &mut impl Write,
+    method: &str,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(body)?;
+    }
+    Ok(())
+}
+
+fn handle(root: &Path, reload: &Reload, mut stream: TcpStream) -> anyhow::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(url_path)) = (parts.next(), parts.next()) else {
+        return respond(
+            &mut stream,
+            "GET",
+            "400 Bad Request",
+            "text/plain",
+            b"bad request",
+        );
+    };
+    if method != "GET" && method != "HEAD" {
+        return respond(
+            &mut stream,
+            "GET",
+            "405 Method Not Allowed",
+            "text/plain",
+            b"method not allowed",
+        );
+    }
+    if url_path == RELOAD_PATH {
+        return stream_reloads(reload, &mut stream);
+    }
+    let Some(path) = resolve(root, url_path) else {
+        eprintln!("404 {url_path}");
+        let not_found = root.join("404.html");
+        let body = match fs::read(&not_found) {
+            Ok(html) => inject_reload(&html),
+            Err(_) => b"not found".to_vec(),
+        };
+        return respond(
+            &mut stream,
+            method,
+            "404 Not Found",
+            content_type(&not_found),
+            &body,
+        );
+    };
+    let mut body = fs::read(&path)?;
+    if path.extension().is_some_and(|x| x == "html") {
+        body = inject_reload(&body);
+    }
+    respond(&mut stream, method, "200 OK", content_type(&path), &body)
+}
+
+/// Serve the files in a directory over HTTP, on localhost.
+///
+/// This is meant for previewing a site, and not for production use. HTML pages
+/// get a script added to them, reloading them whenever `reload` gets notified.
+pub fn serve(root: &Path, port: u16, reload: Arc<Reload>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("serving {} at http://localhost:{port}/", root.display());
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("WARN: {e}");
+                continue;
+            }
+        };
+        let root = root.to_path_buf();
+        let reload = Arc::clone(&reload);
+        thread::spawn(move || {
+            if let Err(e) = handle(&root, &reload, stream) {
+                eprintln!("WARN: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn decode() {
+        assert_eq!(percent_decode("/a%20b/%C3%A9%zz"), "/a b/é%zz");
+    }
+
+    #[test]
+    fn resolving() {
+        let root = TempDir::new("serve");
+        fs::create_dir_all(root.join("posts")).unwrap();
+        fs::write(root.join("index.html"), "").unwrap();
+        fs::write(root.join("posts/a b.html"), "").unwrap();
+        assert_eq!(resolve(&root, "/"), Some(root.join("index.html")));
+        assert_eq!(
+            resolve(&root, "/posts/a%20b?x=1"),
+            Some(root.join("posts/a b.html"))
+        );
+        assert_eq!(resolve(&root, "/posts/"), None);
+        assert_eq!(resolve(&root, "/../etc/passwd"), None);
+    }
+
+    #[test]
+    fn head_requests() {
+        let mut get = Vec::new();
+        respond(&mut get, "GET", "200 OK", "text/plain", b"hello").unwrap();
+        let mut head = Vec::new();
+        respond(&mut head, "HEAD", "200 OK", "text/plain", b"hello").unwrap();
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.contains("Content-Length: 5\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+        assert_eq!(get.strip_prefix(head.as_bytes()), Some(&b"hello"[..]));
+    }
+
+    #[test]
+    fn injecting() {
+        assert_eq!(
+            inject_reload(b"<body>a</BODY></html>"),
+            format!("<body>a{RELOAD_SCRIPT}</BODY></html>").as_bytes()
+        );
+        assert_eq!(inject_reload(b"a"), format!("a{RELOAD_SCRIPT}").as_bytes());
+    }
+
+    #[test]
+    fn reloading() {
+        let reload = Arc::new(Reload::default());
+        let waiting = {
+            let reload = Arc::clone(&reload);
+            thread::spawn(move || reload.wait(0))
+        };
+        reload.notify();
+        assert_eq!(waiting.join().unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,147 @@
+use minijinja::{Value, context};
+use regex::Regex;
+use std::{collections::BTreeMap, sync::LazyLock};
+
+static RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\{\{<\s*([A-Za-z0-9_-]+)((?:\s+(?:"[^"]*"|[^\s"<>{}]+))*)\s*>\}\}"#).unwrap()
+});
+static ARG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""([^"]*)"|([^\s"<>{}]+)"#).unwrap());
+
+/// A shortcode like `{{< csv "data/results.csv" >}}` inside of a post.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shortcode<'a> {
+    /// The full text of the shortcode, including the delimiters.
+    pub source: &'a str,
+    pub name: &'a str,
+    /// The arguments, with any quotes removed.
+    pub args: Vec<&'a str>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Piece<'a> {
+    Text(&'a str),
+    Shortcode(Shortcode<'a>),
+}
+
+impl<'a> Shortcode<'a> {
+    fn from_captures(captures: &regex::Captures<'a>) -> Self {
+        let source = captures.get(0).unwrap().as_str();
+        let name = captures.get(1).unwrap().as_str();
+        let args = captures
+            .get(2)
+            .map(|args| {
+                ARG_RE
+                    .captures_iter(args.as_str())
+                    .filter_map(|arg| arg.get(1).or(arg.get(2)).map(|x| x.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { source, name, args }
+    }
+
+    /// The values available to the template for this shortcode.
+    ///
+    /// All of the arguments are in `args`, and arguments like `key=value` are also
+    /// available as `key`.
+    pub fn context(&self) -> Value {
+        let named = self
+            .args
+            .iter()
+            .filter_map(|arg| arg.split_once('='))
+            .filter(|(key, _)| !key.is_empty() && key != &"args")
+            .collect::<BTreeMap<_, _>>();
+        context! { args => self.args, ..Value::from_serialize(&named) }
+    }
+
+    /// Parse a string which consists of exactly one shortcode, ignoring surrounding whitespace.
+    pub fn parse_whole(data: &'a str) -> Option<Self> {
+        let data = data.trim();
+        let captures = RE.captures(data)?;
+        if captures.get(0)?.len() != data.len() {
+            return None;
+        }
+        Some(Self::from_captures(&captures))
+    }
+
+    /// Split data into normal text, and shortcodes.
+    pub fn split(data: &'a str) -> impl Iterator<Item = Piece<'a>> {
+        let mut pos = 0;
+        let mut captures = RE.captures_iter(data);
+        let mut pending = None;
+        std::iter::from_fn(move || {
+            if let Some(shortcode) = pending.take() {
+                return Some(Piece::Shortcode(shortcode));
+            }
+            match captures.next() {
+                Some(capture) => {
+                    let whole = capture.get(0).unwrap();
+                    let before = &data[pos..whole.start()];
+                    pos = whole.end();
+                    let shortcode = Self::from_captures(&capture);
+                    if before.is_empty() {
+                        return Some(Piece::Shortcode(shortcode));
+                    }
+                    pending = Some(shortcode);
+                    Some(Piece::Text(before))
+                }
+                None if pos < data.len() => {
+                    let rest = &data[pos..];
+                    pos = data.len();
+                    Some(Piece::Text(rest))
+                }
+                None => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Shortcode::parse_whole(r#" {{< csv "data/my results.csv" header >}} "#),
+            Some(Shortcode {
+                source: r#"{{< csv "data/my results.csv" header >}}"#,
+                name: "csv",
+                args: vec!["data/my results.csv", "header"]
+            })
+        );
+        assert_eq!(Shortcode::parse_whole("{{< csv >}} and more"), None);
+        assert_eq!(Shortcode::parse_whole("no shortcode"), None);
+    }
+
+    #[test]
+    fn context() {
+        let shortcode = Shortcode::parse_whole("{{< youtube abc123 start=10 >}}").unwrap();
+        let env = minijinja::Environment::new();
+        let out = env
+            .render_str("{{ args[0] }} {{ start }}", shortcode.context())
+            .unwrap();
+        assert_eq!(out, "abc123 10");
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            Shortcode::split("a {{< x 1 >}} b {{<y>}}").collect::<Vec<_>>(),
+            vec![
+                Piece::Text("a "),
+                Piece::Shortcode(Shortcode {
+                    source: "{{< x 1 >}}",
+                    name: "x",
+                    args: vec!["1"]
+                }),
+                Piece::Text(" b "),
+                Piece::Shortcode(Shortcode {
+                    source: "{{<y>}}",
+                    name: "y",
+                    args: vec![]
+                }),
+            ]
+        );
+    }
+}
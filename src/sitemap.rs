@@ -1,24 +1,39 @@
 use crate::{
-    config::Config,
-    frontmatter::FrontMatter,
-    markdown::{find_yaml_frontmatter, make_mdast},
-    slug::slugify_path,
+    config::{Config, DateSource, DuplicateNames},
+    diagnostic::Diagnostic,
+    escape::escape_path_segment,
+    frontmatter::{DateSettings, FrontMatter, GitHistory, today},
+    fs_utils::entry_type,
+    ignore::{IGNORE_FILE, Ignore},
+    markdown::{collect_hashtags, find_frontmatter, first_h1, make_mdast},
+    slug::{slugify, slugify_path},
+    tags::tag_ancestors,
     wikilink::WikiLink,
 };
 use anyhow::anyhow;
 use std::{
     borrow::Cow,
     cmp::Reverse,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     ffi::OsStr,
     fs,
-    path::{Path, PathBuf},
+    hash::{Hash, Hasher},
+    path::{Component, Path, PathBuf},
 };
+use unidecode::unidecode_char;
 
-const STATIC_EXTENSIONS: [&str; 3] = ["png", "jpg", "svg"];
+/// Images, which embeds like `![[a.png]]` show inline.
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "svg"];
+
+/// Other attachments, which get copied along with images, for wikilinks to link to.
+const ATTACHMENT_EXTENSIONS: [&str; 8] = ["pdf", "mp3", "mp4", "webm", "ogg", "wav", "zip", "csv"];
+
+fn is_image_extension(e: &OsStr) -> bool {
+    IMAGE_EXTENSIONS.iter().any(|&x| x == e)
+}
 
 fn is_static_extension(e: &OsStr) -> bool {
-    STATIC_EXTENSIONS.iter().any(|&x| x == e)
+    is_image_extension(e) || ATTACHMENT_EXTENSIONS.iter().any(|&x| x == e)
 }
 
 /// Translate a path, moving it from having in_path as parent, to out_path.
@@ -26,14 +41,263 @@ fn translate(in_path: &Path, out_path: &Path, path: &Path) -> anyhow::Result<Pat
     Ok(out_path.join(path.strip_prefix(in_path)?))
 }
 
-fn read_front_matter(path: &Path) -> anyhow::Result<FrontMatter> {
+/// Read the front matter of a page, adding the tags in its text, with `inline_tags`.
+///
+/// Pages without a title in their front matter get the one of their first `# Heading`.
+pub fn read_front_matter(
+    path: &Path,
+    dates: DateSettings<'_>,
+    inline_tags: bool,
+) -> anyhow::Result<FrontMatter> {
     let contents = fs::read_to_string(path)?;
     let ast = make_mdast(&contents)?;
-    let yaml = find_yaml_frontmatter(&ast);
-    let fm = FrontMatter::try_from_yaml(&path, yaml)?;
+    let source = find_frontmatter(&ast);
+    let mut fm = FrontMatter::try_from_source(path, source, dates)?;
+    fm.use_heading_title(first_h1(&ast));
+    if inline_tags {
+        for tag in collect_hashtags(&ast) {
+            if !fm.tags.contains(&tag) {
+                fm.tags.push(tag);
+            }
+        }
+        fm.tags.sort();
+    }
     Ok(fm)
 }
 
+/// Check that a link from the front matter of a page stays inside of the site,
+/// adding a warning if it doesn't.
+fn stays_inside(field: &str, link: &str, warnings: &mut Vec<String>) -> bool {
+    let escapes = Path::new(link)
+        .components()
+        .any(|x| x == Component::ParentDir);
+    if escapes {
+        warnings.push(format!("ignoring {field} `{link}`, which contains `..`"));
+    }
+    !escapes
+}
+
+/// The location of an explicit link, like a permalink.
+///
+/// A link ending with `/` is written to an `index.html` in that folder, and one
+/// without an extension gets `.html` added.
+fn explicit_location(link: &str) -> (String, PathBuf) {
+    let mut link = format!("/{}", link.trim_start_matches('/'));
+    if !link.ends_with('/') && Path::new(&link).extension().is_none() {
+        link.push_str(".html");
+    }
+    let mut out = PathBuf::from(link.trim_start_matches('/'));
+    if link.ends_with('/') {
+        out.push("index.html");
+    }
+    (link, out)
+}
+
+/// The link to a file in the output, from its path, e.g. `/Posts/a%20b.png` for `Posts/a b.png`.
+pub fn path_link(rel_path: &Path) -> String {
+    rel_path
+        .iter()
+        .map(|x| x.to_string_lossy())
+        .fold(String::new(), |acc, x| acc + "/" + &escape_path_segment(&x))
+}
+
+/// Where the page for missing URLs goes, from `content/404.md`, or `templates/404.html`.
+pub const NOT_FOUND_LINK: &str = "/404.html";
+
+/// Fill in a permalink pattern from the config, like `/:folder/:slug/`, for a page.
+///
+/// `:folder` is the slugified folder of the page, `:slug` its slug, or slugified name,
+/// and `:year`, `:month`, and `:day` come from when it was published.
+fn expand_permalink(pattern: &str, rel_path: &Path, front_matter: &FrontMatter) -> String {
+    let stem = rel_path.file_stem().unwrap_or_default().to_string_lossy();
+    let slug = slugify(front_matter.slug.as_deref().unwrap_or(&stem));
+    let folder = rel_path
+        .parent()
+        .map(|x| {
+            slugify_path(x)
+                .iter()
+                .map(|x| x.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .unwrap_or_default();
+    let date = front_matter
+        .published
+        .as_ref()
+        .unwrap_or(&front_matter.date);
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+    let expanded = pattern
+        .replace(":folder", &folder)
+        .replace(":slug", &slug)
+        .replace(":year", year)
+        .replace(":month", month)
+        .replace(":day", day);
+    // An empty folder, for pages at the top of the site, leaves behind a `//`.
+    let mut out = String::with_capacity(expanded.len());
+    for c in expanded.chars() {
+        if !(c == '/' && out.ends_with('/')) {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Figure out the link to a page, and where it goes, relative to the output directory.
+///
+/// By default, this follows the path of the page, slugified, unless `keep_paths` is set.
+/// The front matter can replace the file name with `slug`, or the whole link with
+/// `permalink`. A `pattern` from the config replaces the default, except for `index` pages.
+fn page_location(
+    rel_path: &Path,
+    front_matter: &FrontMatter,
+    pattern: Option<&str>,
+    keep_paths: bool,
+    warnings: &mut Vec<String>,
+) -> (String, PathBuf) {
+    let permalink = front_matter
+        .permalink
+        .as_ref()
+        .filter(|permalink| stays_inside("permalink", permalink, warnings));
+    if let Some(permalink) = permalink {
+        return explicit_location(permalink);
+    }
+    let is_index = rel_path.file_stem() == Some(OsStr::new("index"));
+    if let Some(pattern) = pattern.filter(|_| !is_index) {
+        return explicit_location(&expand_permalink(pattern, rel_path, front_matter));
+    }
+    let path = match &front_matter.slug {
+        Some(slug) => rel_path.with_file_name(slugify(slug)),
+        None => rel_path.to_path_buf(),
+    };
+    let out = output_path(&path.with_extension("html"), keep_paths);
+    (path_link(&out), out)
+}
+
+/// A path in the output, for one in `content`, slugified unless `keep_paths` is set.
+pub fn output_path(rel_path: &Path, keep_paths: bool) -> PathBuf {
+    if keep_paths {
+        rel_path.to_path_buf()
+    } else {
+        slugify_path(rel_path)
+    }
+}
+
+/// The language folder a page is in, like `fr` for `fr/Post.md`, if it's one of the site's languages.
+fn language_folder<'a>(config: &'a Config, rel_path: &Path) -> Option<&'a str> {
+    let first = rel_path
+        .parent()?
+        .components()
+        .next()?
+        .as_os_str()
+        .to_str()?;
+    config
+        .languages
+        .iter()
+        .map(String::as_str)
+        .find(|&lang| lang == first)
+}
+
+/// The language of a page, from its front matter, its folder, or the site, in that order.
+fn page_language(config: &Config, rel_path: &Path, front_matter: &FrontMatter) -> Option<String> {
+    front_matter
+        .lang
+        .as_deref()
+        .or_else(|| language_folder(config, rel_path))
+        .or(config.language.as_deref())
+        .map(str::to_string)
+}
+
+/// The language to add to the start of the link to a page, if any.
+///
+/// This is only needed for pages with a `lang` in their front matter, which isn't the
+/// main language of the site, and which aren't already in the folder of that language.
+fn language_prefix<'a>(
+    config: &Config,
+    rel_path: &Path,
+    front_matter: &'a FrontMatter,
+) -> Option<&'a str> {
+    let lang = front_matter.lang.as_deref()?;
+    let redundant = front_matter.permalink.is_some()
+        || config.language.as_deref() == Some(lang)
+        || language_folder(config, rel_path) == Some(lang);
+    (!redundant).then_some(lang)
+}
+
+/// What identifies the translations of a page, which all share this key.
+///
+/// By default, this is the path of the page, without its language folder.
+fn translation_key(config: &Config, rel_path: &Path, front_matter: &FrontMatter) -> String {
+    if let Some(key) = &front_matter.translation_key {
+        return key.clone();
+    }
+    let path = language_folder(config, rel_path)
+        .and_then(|lang| rel_path.strip_prefix(lang).ok())
+        .unwrap_or(rel_path);
+    path.with_extension("").to_string_lossy().into_owned()
+}
+
+/// Figure out where the redirects for the aliases of a page go, relative to the output directory.
+///
+/// Aliases are old paths to the page, like `Posts/Old Name`, and get slugified like
+/// paths are, so that they match the old URL of a page after renaming it.
+fn alias_locations(
+    front_matter: &FrontMatter,
+    warnings: &mut Vec<String>,
+) -> Vec<(String, PathBuf)> {
+    front_matter
+        .aliases
+        .iter()
+        .filter(|alias| stays_inside("alias", alias, warnings))
+        .map(|alias| {
+            let slugified = slugify_path(Path::new(alias.trim_matches('/')));
+            let trailing = if alias.ends_with('/') { "/" } else { "" };
+            explicit_location(&format!("{}{trailing}", slugified.display()))
+        })
+        .collect()
+}
+
+/// The link to the cover of a page, resolved relative to the page.
+///
+/// Covers starting with `/` are relative to the content folder instead, and URLs
+/// are kept as is. Covers which aren't static files of the site are an error.
+fn cover_link(
+    in_path: &Path,
+    page: &Page,
+    statics: &HashMap<&Path, &Static>,
+) -> Result<Option<String>, String> {
+    let Some(cover) = page.front_matter.cover.as_deref() else {
+        return Ok(None);
+    };
+    if cover.starts_with("https://") || cover.starts_with("http://") {
+        return Ok(Some(cover.to_string()));
+    }
+    let mut path = match cover.strip_prefix('/') {
+        Some(_) => in_path.to_path_buf(),
+        None => page.in_path.parent().unwrap_or(in_path).to_path_buf(),
+    };
+    for component in Path::new(cover.trim_start_matches('/')).components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::Normal(x) => path.push(x),
+            _ => {}
+        }
+    }
+    match statics.get(path.as_path()) {
+        Some(file) if file.is_image() => Ok(Some(file.link.clone())),
+        Some(_) => Err(format!("skipping cover `{cover}`, which isn't an image")),
+        None => Err(format!(
+            "skipping cover `{cover}`, which isn't a file of the site"
+        )),
+    }
+}
+
 /// A Static file, like an image.
 ///
 /// This is still contained inside of the content folder.
@@ -41,6 +305,24 @@ fn read_front_matter(path: &Path) -> anyhow::Result<FrontMatter> {
 pub struct Static {
     pub in_path: PathBuf,
     pub out_path: PathBuf,
+    /// The link to this file, relative to the root of the site.
+    pub link: String,
+}
+
+impl Static {
+    /// Whether this file is an image, which can be shown inline.
+    pub fn is_image(&self) -> bool {
+        self.in_path.extension().is_some_and(is_image_extension)
+    }
+}
+
+/// A redirect from an old link to a page.
+#[derive(Clone, Debug)]
+pub struct Redirect {
+    /// The old link, like `/posts/old-name.html`.
+    pub link: String,
+    /// Where to write a page redirecting to the new link.
+    pub out_path: PathBuf,
 }
 
 /// A page with actual markdown content.
@@ -51,6 +333,14 @@ pub struct Page {
     pub front_matter: FrontMatter,
     pub in_path: PathBuf,
     pub out_path: PathBuf,
+    /// The redirects to this page, from its aliases.
+    pub redirects: Vec<Redirect>,
+    /// The language of this page, if the site, or the page, has one.
+    pub lang: Option<String>,
+    /// The link to the cover of this page, if it has one which exists.
+    pub cover: Option<String>,
+    /// The path of this page inside of `content`, without its extension, like `notes/Idea`.
+    path_name: String,
     index: usize,
 }
 
@@ -65,121 +355,588 @@ impl Page {
 
 type PageIndex = usize;
 
+/// The date a page was published, used to order series and archives.
+fn published_date(page: &Page) -> &str {
+    let front_matter = &page.front_matter;
+    front_matter
+        .published
+        .as_ref()
+        .unwrap_or(&front_matter.date)
+}
+
+/// Order pages sharing a name, so that the one wikilinks to the name go to comes first.
+fn sort_duplicates(config: &Config, pages: &[Page], indices: &mut [PageIndex]) {
+    sort_page_indices(pages, indices);
+    // Sorting is stable, so the newest page still wins among equally good ones.
+    match config.duplicate_names {
+        DuplicateNames::Shortest => indices.sort_by_key(|&i| {
+            let path = &pages[i].path_name;
+            (path.matches('/').count(), path.len())
+        }),
+        DuplicateNames::Folders => indices.sort_by_key(|&i| {
+            let path = Path::new(&pages[i].path_name);
+            config
+                .preferred_folders
+                .iter()
+                .position(|folder| path.starts_with(folder))
+                .unwrap_or(usize::MAX)
+        }),
+        _ => {}
+    }
+}
+
+/// A name without its case, accents, or repeated whitespace, so that `[[my  cafe]]`
+/// finds `My Café`, the way Obsidian does.
+///
+/// Names which are already folded, like most links, get borrowed.
+fn fold_name(name: &str) -> Cow<'_, str> {
+    if is_folded(name) {
+        return Cow::Borrowed(name);
+    }
+    let mut out = String::with_capacity(name.len());
+    for word in name.split_whitespace() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        for c in word.chars() {
+            match c {
+                // Accents written separately from their letter.
+                '\u{300}'..='\u{36f}' => {}
+                // Only Latin letters lose their accents, since transliterating
+                // other scripts, or symbols like `×`, would make unrelated names match.
+                '\u{c0}'..='\u{24f}' | '\u{1e00}'..='\u{1eff}' if c.is_alphabetic() => {
+                    out.push_str(&unidecode_char(c).to_lowercase());
+                }
+                _ => out.extend(c.to_lowercase()),
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Whether `fold_name` would leave a name the same.
+fn is_folded(name: &str) -> bool {
+    let spaced = !name.starts_with(' ') && !name.ends_with(' ') && !name.contains("  ");
+    spaced
+        && name.chars().all(|c| match c {
+            ' ' => true,
+            '\u{300}'..='\u{36f}' => false,
+            '\u{c0}'..='\u{24f}' | '\u{1e00}'..='\u{1eff}' if c.is_alphabetic() => false,
+            c => !c.is_whitespace() && c.to_lowercase().eq([c]),
+        })
+}
+
+/// The pages among some sharing a name which also share a language with one of the others.
+///
+/// Translations of a page usually keep its name, without making links ambiguous.
+fn clashing(pages: &[Page], indices: &[PageIndex]) -> Vec<PageIndex> {
+    let clashes = |i: PageIndex| {
+        let lang = &pages[i].lang;
+        indices.iter().any(|&j| j != i && pages[j].lang == *lang)
+    };
+    indices.iter().copied().filter(|&i| clashes(i)).collect()
+}
+
+/// The candidates for a link in some language, or all of them if none are in it.
+fn in_language(pages: &[Page], candidates: &[PageIndex], lang: Option<&str>) -> Vec<PageIndex> {
+    let same = candidates
+        .iter()
+        .copied()
+        .filter(|&i| pages[i].lang.as_deref() == lang)
+        .collect::<Vec<_>>();
+    if same.is_empty() {
+        candidates.to_vec()
+    } else {
+        same
+    }
+}
+
+/// Find the page a wikilink goes to, by its name, or by the end of its path, like `notes/Idea`.
+///
+/// A path starting with `/` has to be the whole path of the page, inside of `content`.
+/// Names match exactly, or failing that, ignoring case, accents, and whitespace.
+/// Pages in `lang`, the language of the linking page, win over the others.
+fn resolve_name(
+    pages: &[Page],
+    pages_by_name: &HashMap<String, Vec<PageIndex>>,
+    pages_by_folded_name: &HashMap<String, Vec<PageIndex>>,
+    policy: DuplicateNames,
+    lang: Option<&str>,
+    name: &str,
+) -> Option<PageIndex> {
+    resolve_in(pages, pages_by_name, policy, lang, name, |x| {
+        Cow::Borrowed(x)
+    })
+    .or_else(|| resolve_in(pages, pages_by_folded_name, policy, lang, name, fold_name))
+}
+
+/// Turn a link relative to the folder of a page, like `../Other/Bar`, into a whole path,
+/// like `/Other/Bar`, leaving other links alone.
+///
+/// This fails for links going above the root of `content`.
+fn absolute_name<'a>(from: &str, name: &'a str) -> Option<Cow<'a, str>> {
+    if !name.starts_with("./") && !name.starts_with("../") {
+        return Some(Cow::Borrowed(name));
+    }
+    let mut parts = from.split('/').collect::<Vec<_>>();
+    // The page itself isn't a folder.
+    parts.pop();
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(Cow::Owned(format!("/{}", parts.join("/"))))
+}
+
+/// Resolve a name among pages indexed by `key` of their name.
+fn resolve_in(
+    pages: &[Page],
+    names: &HashMap<String, Vec<PageIndex>>,
+    policy: DuplicateNames,
+    lang: Option<&str>,
+    name: &str,
+    key: fn(&str) -> Cow<'_, str>,
+) -> Option<PageIndex> {
+    let file = name.rsplit('/').next().unwrap_or(name);
+    let candidates = names.get(key(file).as_ref())?;
+    if file == name {
+        let candidates = in_language(pages, candidates, lang);
+        if policy == DuplicateNames::Qualified && candidates.len() > 1 {
+            return None;
+        }
+        return candidates.first().copied();
+    }
+    let name = key(name);
+    let is_match = |path: &str| match name.strip_prefix('/') {
+        Some(whole) => path == whole,
+        None => path == name || path.ends_with(&format!("/{name}")),
+    };
+    in_language(pages, candidates, lang)
+        .into_iter()
+        .chain(candidates.iter().copied())
+        .find(|&i| is_match(&key(&pages[i].path_name)))
+}
+
 fn sort_page_indices(pages: &[Page], indices: &mut [PageIndex]) {
     indices.sort_by_key(|&i| {
         (
             Reverse(&pages[i].front_matter.date),
             Reverse(&pages[i].front_matter.title),
+            &pages[i].in_path,
         )
     });
 }
 
-#[derive(Debug)]
+/// Find the files in the content of a site, skipping ignored ones.
+///
+/// Folders are walked in a stable order, with the files in each sorted by name.
+/// Problems finding them, like symlinks which aren't followed, get added to `warnings`.
+pub fn content_files(
+    config: &Config,
+    in_path: &Path,
+    warnings: &mut Vec<(PathBuf, Diagnostic)>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    let mut q = vec![in_path.to_path_buf()];
+    let ignore = Ignore::load(in_path)?;
+    let ignore_path = in_path.join(IGNORE_FILE);
+    for warning in &ignore.warnings {
+        warnings.push((ignore_path.clone(), warning.clone()));
+    }
+    let mut visited = HashSet::from([fs::canonicalize(in_path)?]);
+    while let Some(dir) = q.pop() {
+        // The order of entries depends on the filesystem, so sort them for stable output.
+        let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            // Ignored entries get skipped first, so ignoring a symlink silences its warning.
+            let path = entry.path();
+            let rel_path = path.strip_prefix(in_path)?;
+            if config.ignored_folders.contains(rel_path)
+                || ignore.is_ignored(rel_path, path.is_dir())
+            {
+                continue;
+            }
+            let file_type = entry_type(&entry, config.follow_symlinks, &mut visited, warnings)?;
+            let Some(file_type) = file_type else {
+                continue;
+            };
+            if file_type.is_dir() {
+                q.push(path);
+            } else if file_type.is_file() && path.to_str().is_some() {
+                out.push(path);
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Default)]
 pub struct SiteMap {
     statics: Vec<Static>,
+    statics_by_name: HashMap<String, usize>,
     pages: Vec<Page>,
     pages_by_name: HashMap<String, Vec<usize>>,
-    pages_by_tag: HashMap<String, Vec<usize>>,
-    folders: HashMap<PathBuf, Vec<usize>>,
+    /// The pages with each name, ignoring case, accents, and whitespace.
+    pages_by_folded_name: HashMap<String, Vec<usize>>,
+    /// How wikilinks to names shared by several pages get resolved.
+    duplicate_names: DuplicateNames,
+    /// The pages with each tag, by the slug of the tag.
+    pages_by_tag: BTreeMap<String, Vec<usize>>,
+    /// The name shown for each tag, by its slug.
+    tag_names: HashMap<String, String>,
+    pages_by_author: BTreeMap<String, Vec<usize>>,
+    pages_by_series: BTreeMap<String, Vec<usize>>,
+    pages_by_language: BTreeMap<String, Vec<usize>>,
+    folders: BTreeMap<PathBuf, Vec<usize>>,
+    translations: Vec<Vec<usize>>,
     backlinks: Vec<Vec<usize>>,
+    /// The pages embedded by each page, like `![[Note]]`.
+    embeds: Vec<Vec<usize>>,
+    related: Vec<Vec<usize>>,
+    /// The paths of the pages scheduled for later, like `notes/Idea`, which links can go to.
+    scheduled: Vec<String>,
+    /// Where the pages scheduled for later go, in case an earlier build already wrote them.
+    scheduled_outputs: Vec<PathBuf>,
+    /// Problems with pages found while building the site map, like covers which don't exist.
+    warnings: Vec<(PathBuf, Diagnostic)>,
 }
 
 impl SiteMap {
     pub fn build(config: &Config, in_path: &Path, out_path: &Path) -> anyhow::Result<Self> {
         let mut statics: Vec<Static> = Vec::with_capacity(128);
         let mut pages: Vec<Page> = Vec::with_capacity(1024);
-        let mut q = vec![Cow::Borrowed(in_path)];
         let mut index = 0;
         let mut backlinks: Vec<Vec<usize>> = Vec::with_capacity(1024);
-        while let Some(dir) = q.pop() {
-            let rel_path = dir.strip_prefix(in_path)?;
-            if config.ignored_folders.contains(rel_path) {
+        let mut translation_keys = Vec::with_capacity(1024);
+        let mut scheduled = Vec::new();
+        let mut scheduled_outputs = Vec::new();
+        let mut dates = config.date_settings()?;
+        let today = today(dates.offset)?;
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        let history = match dates.source {
+            DateSource::Git => Some(GitHistory::load(in_path, dates.offset)),
+            DateSource::Mtime => None,
+        };
+        if let Some(history) = &history {
+            for warning in &history.warnings {
+                warnings.push((in_path.to_path_buf(), Diagnostic::new(warning.clone())));
+            }
+        }
+        dates.git = history.as_ref();
+        for path in content_files(config, in_path, &mut warnings)? {
+            let Some(extension) = path.extension() else {
+                continue;
+            };
+            if is_static_extension(extension) {
+                let rel_path = path.strip_prefix(in_path)?;
+                let link = path_link(rel_path);
+                statics.push(Static {
+                    out_path: translate(in_path, out_path, &path)?,
+                    in_path: path,
+                    link,
+                });
                 continue;
             }
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let file_type = entry.file_type()?;
-                if file_type.is_dir() {
-                    q.push(Cow::Owned(entry.path()));
-                    continue;
-                }
-                if !file_type.is_file() {
-                    continue;
-                }
-                let path = entry.path();
-                if path.to_str().is_none() {
-                    continue;
-                }
-                let Some(extension) = path.extension() else {
-                    continue;
-                };
-                if is_static_extension(extension) {
-                    statics.push(Static {
-                        out_path: translate(in_path, out_path, &path)?,
-                        in_path: path,
-                    });
+            if extension != "md" {
+                continue;
+            }
+            // Every page gets read before failing, to report all of the broken ones at once.
+            let mut front_matter = match read_front_matter(&path, dates, config.inline_tags) {
+                Ok(front_matter) => front_matter,
+                Err(e) => {
+                    errors.push(Diagnostic::from(e).show_in(&path));
                     continue;
                 }
-                if extension != "md" {
-                    continue;
+            };
+            let published = front_matter
+                .published
+                .as_ref()
+                .unwrap_or(&front_matter.date)
+                .clone();
+            let name = path
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .ok_or_else(|| anyhow!("failed to get file stem"))?
+                .to_string();
+            let rel_path = path.strip_prefix(in_path)?;
+            let mut page_warnings = Vec::new();
+            let (mut link, mut rel_out_path) = page_location(
+                rel_path,
+                &front_matter,
+                config.permalink.as_deref(),
+                config.keep_paths,
+                &mut page_warnings,
+            );
+            if let Some(lang) = language_prefix(config, rel_path, &front_matter) {
+                let lang = slugify(lang);
+                link = format!("/{lang}{link}");
+                rel_out_path = Path::new(&lang).join(rel_out_path);
+            }
+            // Hosts look for the page shown for missing URLs at a fixed path.
+            if rel_path == Path::new("404.md") {
+                link = NOT_FOUND_LINK.to_string();
+                rel_out_path = PathBuf::from(link.trim_start_matches('/'));
+                front_matter.unlisted = true;
+            }
+            let path_name = rel_path
+                .with_extension("")
+                .iter()
+                .map(|x| x.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            // Scheduled pages get skipped, until a build on or after their date.
+            if !config.future && published > today {
+                scheduled.push(path_name);
+                scheduled_outputs.push(out_path.join(rel_out_path));
+                for (_, alias) in alias_locations(&front_matter, &mut Vec::new()) {
+                    scheduled_outputs.push(out_path.join(alias));
                 }
-                let front_matter = read_front_matter(&path)?;
-                let name = path
-                    .file_stem()
-                    .and_then(|x| x.to_str())
-                    .ok_or_else(|| anyhow!("failed to get file stem"))?
-                    .to_string();
-                let link = {
-                    let rel_path = path.strip_prefix(in_path)?.with_extension("html");
-                    let slugified = slugify_path(&rel_path);
-                    let out_segment = slugified.to_str().unwrap();
-                    let mut out = String::with_capacity(1 + out_segment.len());
-                    out.push('/');
-                    out.push_str(out_segment);
-                    out
-                };
-                pages.push(Page {
-                    name,
+                continue;
+            }
+            let lang = page_language(config, rel_path, &front_matter);
+            translation_keys.push(translation_key(config, rel_path, &front_matter));
+            let redirects = alias_locations(&front_matter, &mut page_warnings)
+                .into_iter()
+                .map(|(link, out)| Redirect {
                     link,
-                    front_matter,
-                    out_path: out_path.join(slugify_path(
-                        &path.strip_prefix(in_path)?.with_extension("html"),
-                    )),
-                    in_path: path,
-                    index,
-                });
-                // We expect most of these to be empty.
-                backlinks.push(Vec::new());
-                index += 1;
+                    out_path: out_path.join(out),
+                })
+                .collect();
+            for warning in page_warnings {
+                warnings.push((path.clone(), Diagnostic::new(warning)));
             }
+            pages.push(Page {
+                name,
+                link,
+                front_matter,
+                out_path: out_path.join(rel_out_path),
+                redirects,
+                lang,
+                cover: None,
+                path_name,
+                in_path: path,
+                index,
+            });
+            // We expect most of these to be empty.
+            backlinks.push(Vec::new());
+            index += 1;
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "failed to read {} pages:\n{}",
+                errors.len(),
+                errors.join("\n")
+            ));
         }
-        let mut pages_by_name = {
+        let pages_by_name = {
             let mut out = HashMap::<_, Vec<_>>::new();
             for (i, page) in pages.iter().enumerate() {
                 out.entry(page.name.clone()).or_default().push(i);
             }
+            for list in out.values_mut() {
+                sort_duplicates(config, &pages, list);
+            }
             out
         };
-        let mut pages_by_tag = {
+        let pages_by_folded_name = {
             let mut out = HashMap::<_, Vec<_>>::new();
+            for (i, page) in pages.iter().enumerate() {
+                out.entry(fold_name(&page.name).into_owned())
+                    .or_default()
+                    .push(i);
+            }
+            for list in out.values_mut() {
+                sort_duplicates(config, &pages, list);
+            }
+            out
+        };
+        if config.duplicate_names == DuplicateNames::Error {
+            let mut shared = pages_by_name
+                .iter()
+                .map(|(name, indices)| (name, clashing(&pages, indices)))
+                .filter(|(_, indices)| !indices.is_empty())
+                .map(|(name, indices)| {
+                    let paths = indices
+                        .iter()
+                        .map(|&i| pages[i].in_path.display().to_string())
+                        .collect::<Vec<_>>();
+                    format!("`{name}`: {}", paths.join(", "))
+                })
+                .collect::<Vec<_>>();
+            if !shared.is_empty() {
+                shared.sort();
+                return Err(anyhow!(
+                    "pages share names, which `duplicate_names: error` doesn't allow:\n{}",
+                    shared.join("\n")
+                ));
+            }
+        }
+        // Covers can only be checked once every static file is known.
+        let statics_by_path = statics
+            .iter()
+            .map(|x| (x.in_path.as_path(), x))
+            .collect::<HashMap<_, _>>();
+        for page in &mut pages {
+            match cover_link(in_path, page, &statics_by_path) {
+                Ok(cover) => page.cover = cover,
+                Err(warning) => warnings.push((page.in_path.clone(), Diagnostic::new(warning))),
+            }
+        }
+        let statics_by_name = {
+            let mut out = HashMap::new();
+            for (i, file) in statics.iter().enumerate() {
+                if let Some(name) = file.in_path.file_name().and_then(|x| x.to_str()) {
+                    out.entry(name.to_string()).or_insert(i);
+                }
+            }
+            out
+        };
+        // Tags are grouped by their slug, since that's their URL, named after their first use.
+        let mut tag_names = HashMap::new();
+        let mut pages_by_tag = {
+            let mut out = BTreeMap::<_, Vec<_>>::new();
             for (i, page) in pages.iter().enumerate() {
                 for tag in &page.front_matter.tags {
-                    out.entry(tag.clone()).or_default().push(i);
+                    let ancestors = tag_ancestors(tag);
+                    if ancestors.is_empty() {
+                        let warning =
+                            format!("skipping tag `{tag}`, which has nothing to put in a URL");
+                        warnings.push((page.in_path.clone(), Diagnostic::new(warning)));
+                    }
+                    // Pages with a nested tag, like `programming/rust`, are also in `programming`.
+                    for (name, slug) in ancestors {
+                        tag_names.entry(slug.clone()).or_insert(name);
+                        let list = out.entry(slug).or_default();
+                        if !list.contains(&i) {
+                            list.push(i);
+                        }
+                    }
+                }
+            }
+            out
+        };
+        let mut pages_by_author = {
+            let mut out = BTreeMap::<_, Vec<_>>::new();
+            for (i, page) in pages.iter().enumerate() {
+                for author in &page.front_matter.authors {
+                    out.entry(author.clone()).or_default().push(i);
+                }
+            }
+            out
+        };
+        let mut pages_by_series = {
+            let mut out = BTreeMap::<_, Vec<_>>::new();
+            for (i, page) in pages.iter().enumerate() {
+                if let Some(series) = &page.front_matter.series
+                    && page.front_matter.listed()
+                {
+                    out.entry(series.clone()).or_default().push(i);
+                }
+            }
+            out
+        };
+        let mut pages_by_language = {
+            let mut out = BTreeMap::<_, Vec<_>>::new();
+            for (i, page) in pages.iter().enumerate() {
+                if let Some(lang) = &page.lang {
+                    out.entry(lang.clone()).or_default().push(i);
                 }
             }
             out
         };
-        // Generate warnings for duplicate names
-        for (name, indices) in &pages_by_name {
-            if indices.len() > 1 {
-                eprintln!("WARNING: `{name}` has conflicts");
-                for &i in indices {
-                    eprintln!("\t{}", pages[i].in_path.to_string_lossy());
+        // Translations are the pages sharing a key, in another language.
+        let mut translations = {
+            let mut by_key = HashMap::<_, Vec<_>>::new();
+            for (i, key) in translation_keys.iter().enumerate() {
+                by_key.entry(key).or_default().push(i);
+            }
+            (0..pages.len())
+                .map(|i| {
+                    by_key[&translation_keys[i]]
+                        .iter()
+                        .copied()
+                        .filter(|&j| {
+                            pages[j].lang != pages[i].lang && pages[j].front_matter.listed()
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        // Generate warnings for pages overwriting each other.
+        {
+            let mut by_out_path = HashMap::<_, Vec<_>>::new();
+            for page in &pages {
+                by_out_path
+                    .entry(page.out_path.as_path())
+                    .or_default()
+                    .push(page);
+            }
+            for (out_path, conflicting) in by_out_path {
+                if conflicting.len() < 2 {
+                    continue;
+                }
+                for page in &conflicting {
+                    let others = conflicting
+                        .iter()
+                        .filter(|x| x.in_path != page.in_path)
+                        .map(|x| format!("`{}`", x.in_path.display()))
+                        .collect::<Vec<_>>();
+                    let warning = format!(
+                        "`{}` is also written by {}",
+                        out_path.display(),
+                        others.join(", ")
+                    );
+                    warnings.push((page.in_path.clone(), Diagnostic::new(warning)));
+                }
+            }
+        }
+        // And for aliases overwriting each other, or static files.
+        {
+            let mut by_out_path = HashMap::<_, Vec<_>>::new();
+            for page in &pages {
+                for redirect in &page.redirects {
+                    by_out_path
+                        .entry(redirect.out_path.as_path())
+                        .or_default()
+                        .push(page);
+                }
+            }
+            let statics_by_out_path = statics
+                .iter()
+                .map(|x| (x.out_path.as_path(), x))
+                .collect::<HashMap<_, _>>();
+            for (out_path, mut aliased) in by_out_path {
+                aliased.dedup_by(|a, b| a.in_path == b.in_path);
+                for page in &aliased {
+                    let mut others = aliased
+                        .iter()
+                        .filter(|x| x.in_path != page.in_path)
+                        .map(|x| format!("an alias of `{}`", x.in_path.display()))
+                        .collect::<Vec<_>>();
+                    if let Some(file) = statics_by_out_path.get(out_path) {
+                        others.push(format!("`{}`", file.in_path.display()));
+                    }
+                    if others.is_empty() {
+                        continue;
+                    }
+                    let warning = format!(
+                        "alias at `{}` is also written by {}",
+                        out_path.display(),
+                        others.join(", ")
+                    );
+                    warnings.push((page.in_path.clone(), Diagnostic::new(warning)));
                 }
             }
         }
         let mut folders = {
-            let mut out = HashMap::<_, Vec<_>>::new();
+            let mut out = BTreeMap::<_, Vec<_>>::new();
             for (i, page) in pages.iter().enumerate() {
                 if let Some(folder) = page.folder(in_path)? {
                     out.entry(folder).or_default().push(i);
@@ -187,76 +944,984 @@ impl SiteMap {
             }
             out
         };
-        // Create backlinks
+        // Find the pages embedded by each page, and create backlinks, once per linking page,
+        // and without revealing drafts or unlisted pages.
+        let mut embeds = vec![Vec::new(); pages.len()];
         for (i, page) in pages.iter().enumerate() {
             let content = fs::read_to_string(&page.in_path)?;
             for link in WikiLink::extract(&content) {
-                let Some(&linked_page_i) = pages_by_name.get(link.name).and_then(|x| x.first())
+                let Some(linked_page_i) =
+                    absolute_name(&page.path_name, link.page()).and_then(|name| {
+                        resolve_name(
+                            &pages,
+                            &pages_by_name,
+                            &pages_by_folded_name,
+                            config.duplicate_names,
+                            page.lang.as_deref(),
+                            &name,
+                        )
+                    })
                 else {
                     continue;
                 };
-                backlinks[linked_page_i].push(i);
+                if link.embed && !embeds[i].contains(&linked_page_i) {
+                    embeds[i].push(linked_page_i);
+                }
+                if !page.front_matter.listed() {
+                    continue;
+                }
+                // Pages are visited in order, so a repeated link is always the last one.
+                if linked_page_i != i && backlinks[linked_page_i].last() != Some(&i) {
+                    backlinks[linked_page_i].push(i);
+                }
             }
         }
+        let related = related_pages(&pages, &pages_by_tag, &backlinks);
         // Sort grouped pages.
-        for list in pages_by_name.values_mut() {
+        for list in pages_by_tag.values_mut() {
             sort_page_indices(&pages, list);
         }
-        for list in pages_by_tag.values_mut() {
+        for list in pages_by_author.values_mut() {
             sort_page_indices(&pages, list);
         }
         for list in folders.values_mut() {
             sort_page_indices(&pages, list);
         }
+        for list in pages_by_language.values_mut() {
+            sort_page_indices(&pages, list);
+        }
+        for list in &mut translations {
+            list.sort_by_key(|&i| &pages[i].lang);
+        }
+        // Unlike other lists, a series is read from the first part to the last.
+        for list in pages_by_series.values_mut() {
+            list.sort_by_key(|&i| (published_date(&pages[i]), &pages[i].front_matter.title));
+        }
         for list in &mut backlinks {
             sort_page_indices(&pages, list);
         }
+        // Only what no published page, or file, writes over is left over from earlier builds.
+        let written = pages
+            .iter()
+            .flat_map(|x| {
+                std::iter::once(&x.out_path).chain(x.redirects.iter().map(|x| &x.out_path))
+            })
+            .chain(statics.iter().map(|x| &x.out_path))
+            .collect::<HashSet<_>>();
+        scheduled_outputs.retain(|x| !written.contains(x));
         Ok(Self {
             statics,
+            statics_by_name,
             pages,
             pages_by_name,
+            pages_by_folded_name,
+            duplicate_names: config.duplicate_names,
             pages_by_tag,
+            tag_names,
+            pages_by_author,
+            pages_by_series,
+            pages_by_language,
             folders,
+            translations,
             backlinks,
+            embeds,
+            related,
+            scheduled,
+            scheduled_outputs,
+            warnings,
+        })
+    }
+
+    /// Check if a wikilink goes to a page scheduled for later, which isn't part of the site yet.
+    pub fn is_scheduled(&self, from: Option<&Page>, name: &str) -> bool {
+        let name = match from {
+            Some(from) => absolute_name(&from.path_name, name),
+            None => Some(Cow::Borrowed(name)),
+        };
+        let Some(name) = name.map(|x| fold_name(&x).into_owned()) else {
+            return false;
+        };
+        self.scheduled.iter().any(|path| {
+            let path = fold_name(path);
+            match name.strip_prefix('/') {
+                Some(whole) => path == whole,
+                None => path == name || path.ends_with(&format!("/{name}")),
+            }
         })
     }
 
+    /// Where the pages scheduled for later would be written, which shouldn't exist yet.
+    pub fn scheduled_outputs(&self) -> impl Iterator<Item = &Path> {
+        self.scheduled_outputs.iter().map(|x| x.as_path())
+    }
+
+    /// The problems with pages found while building the site map, with the page each is in.
+    pub fn warnings(&self) -> impl Iterator<Item = (&Path, &Diagnostic)> {
+        self.warnings.iter().map(|(path, x)| (path.as_path(), x))
+    }
+
     /// Iterate over all the static files in the content directory.
     pub fn statics(&self) -> impl Iterator<Item = &Static> {
         self.statics.iter()
     }
 
+    /// Attempt to fetch a static file by its file name, like `image.png`.
+    ///
+    /// A path like `attachments/image.png` also works, with only the file name mattering,
+    /// the same way Obsidian resolves embeds.
+    pub fn static_by_name(&self, name: &str) -> Option<&Static> {
+        let name = Path::new(name).file_name()?.to_str()?;
+        let i = *self.statics_by_name.get(name)?;
+        Some(&self.statics[i])
+    }
+
     /// Iterate over all of the pages.
     pub fn pages(&self) -> impl Iterator<Item = &Page> {
         self.pages.iter()
     }
 
-    /// Attempt to fetch a specific page by name.
+    /// Attempt to fetch a specific page by name, or by a path, like `notes/Idea`.
+    ///
+    /// Pages sharing a name are resolved following `duplicate_names`.
     pub fn page_by_name(&self, name: &str) -> Option<&Page> {
-        let i = *self.pages_by_name.get(name)?.first()?;
+        self.resolve(None, name)
+    }
+
+    /// Attempt to fetch the page a wikilink goes to, from a page, if it's in one.
+    ///
+    /// Links starting with `./` or `../` are relative to the folder of that page,
+    /// and pages in the language of that page win over ones in other languages.
+    pub fn page_by_link(&self, from: Option<&Page>, name: &str) -> Option<&Page> {
+        match from {
+            Some(from) => {
+                self.resolve(from.lang.as_deref(), &absolute_name(&from.path_name, name)?)
+            }
+            None => self.page_by_name(name),
+        }
+    }
+
+    fn resolve(&self, lang: Option<&str>, name: &str) -> Option<&Page> {
+        let i = resolve_name(
+            &self.pages,
+            &self.pages_by_name,
+            &self.pages_by_folded_name,
+            self.duplicate_names,
+            lang,
+            name,
+        )?;
         Some(&self.pages[i])
     }
 
+    /// Check if a name is shared by pages, without a path telling them apart, with
+    /// `duplicate_names: qualified`.
+    ///
+    /// Only the pages in the language of the linking page count, if there are any.
+    pub fn is_ambiguous(&self, from: Option<&Page>, name: &str) -> bool {
+        let pages = match self.pages_by_name.get(name) {
+            Some(pages) => pages,
+            None => match self.pages_by_folded_name.get(fold_name(name).as_ref()) {
+                Some(pages) => pages,
+                None => return false,
+            },
+        };
+        let lang = from.and_then(|x| x.lang.as_deref());
+        self.duplicate_names == DuplicateNames::Qualified
+            && in_language(&self.pages, pages, lang).len() > 1
+    }
+
+    /// Iterate over the names shared by multiple pages, which makes wikilinks ambiguous.
+    ///
+    /// Translations don't count, as long as each of them is in a different language.
+    pub fn duplicate_names(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &Page>)> {
+        self.pages_by_name
+            .iter()
+            .map(|(name, indices)| (name.as_str(), clashing(&self.pages, indices)))
+            .filter(|(_, indices)| !indices.is_empty())
+            .map(|(name, indices)| (name, indices.into_iter().map(|i| &self.pages[i])))
+    }
+
     /// Iterate over all of the folders
-    pub fn folders<'a>(
-        &'a self,
-    ) -> impl Iterator<Item = (&'a Path, impl Iterator<Item = &'a Page>)> {
+    pub fn folders(&self) -> impl Iterator<Item = (&Path, impl Iterator<Item = &Page>)> {
         self.folders
             .iter()
             .map(|(path, indices)| (path.as_path(), indices.iter().map(|&i| &self.pages[i])))
     }
 
-    /// Iterate over all pages in each tag.
-    pub fn pages_by_tag<'a>(
-        &'a self,
-    ) -> impl Iterator<Item = (&'a str, impl Iterator<Item = &'a Page>)> {
-        self.pages_by_tag
+    /// Iterate over all pages in each tag, along with the name of the tag.
+    ///
+    /// Tags with the same slug, like `Rust` and `rust`, are the same tag. Nested tags,
+    /// like `programming/rust`, also have their pages in each tag containing them.
+    pub fn pages_by_tag(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &Page>)> {
+        self.pages_by_tag.iter().map(|(slug, indices)| {
+            let name = self.tag_names[slug].as_str();
+            (name, indices.iter().map(|&i| &self.pages[i]))
+        })
+    }
+
+    /// Iterate over all pages by each author.
+    pub fn pages_by_author(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &Page>)> {
+        self.pages_by_author
             .iter()
-            .map(|(tag, indices)| (tag.as_str(), indices.iter().map(|&i| &self.pages[i])))
+            .map(|(author, indices)| (author.as_str(), indices.iter().map(|&i| &self.pages[i])))
+    }
+
+    /// Iterate over every series, with its pages, from the first part to the last.
+    pub fn pages_by_series(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &Page>)> {
+        self.pages_by_series
+            .iter()
+            .map(|(series, indices)| (series.as_str(), indices.iter().map(|&i| &self.pages[i])))
+    }
+
+    /// Iterate over the pages in each language, by language.
+    pub fn pages_by_language(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &Page>)> {
+        self.pages_by_language
+            .iter()
+            .map(|(lang, indices)| (lang.as_str(), indices.iter().map(|&i| &self.pages[i])))
+    }
+
+    /// Iterate over the translations of a page into other languages, by language.
+    pub fn translations<'a>(&'a self, page: &Page) -> impl Iterator<Item = &'a Page> {
+        self.translations[page.index]
+            .iter()
+            .map(|&i| &self.pages[i])
+    }
+
+    /// The pages in the same series as a page, including it, in order.
+    pub fn series_of(&self, page: &Page) -> Vec<&Page> {
+        page.front_matter
+            .series
+            .as_ref()
+            .and_then(|series| self.pages_by_series.get(series))
+            .map(|indices| indices.iter().map(|&i| &self.pages[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Group the listed pages into archives, like `2023`, and `2023/01`, by date.
+    ///
+    /// The month archives are only included if `months` is set.
+    pub fn archives(&self, months: bool) -> BTreeMap<String, Vec<&Page>> {
+        let mut out = BTreeMap::<_, Vec<_>>::new();
+        for page in self.pages.iter().filter(|page| page.front_matter.listed()) {
+            for key in archive_keys(published_date(page), months) {
+                out.entry(key).or_default().push(page);
+            }
+        }
+        out
     }
 
     /// Iterate over all the pages that link to this page.
     pub fn backlinks<'a>(&'a self, page: &Page) -> impl Iterator<Item = &'a Page> {
         self.backlinks[page.index].iter().map(|&i| &self.pages[i])
     }
+
+    /// Hash the contents of the pages embedded by a page, and of the pages they embed,
+    /// since the page changes along with them.
+    pub fn hash_embeds(&self, page: &Page, hasher: &mut impl Hasher) -> anyhow::Result<()> {
+        let mut seen = vec![page.index];
+        let mut q = self.embeds[page.index].clone();
+        while let Some(i) = q.pop() {
+            if seen.contains(&i) {
+                continue;
+            }
+            seen.push(i);
+            fs::read(&self.pages[i].in_path)?.hash(hasher);
+            q.extend(&self.embeds[i]);
+        }
+        Ok(())
+    }
+
+    /// Iterate over the pages most related to this page, most related first.
+    pub fn related<'a>(&'a self, page: &Page) -> impl Iterator<Item = &'a Page> {
+        self.related[page.index].iter().map(|&i| &self.pages[i])
+    }
+
+    /// Hash what rendering a page can depend on, when it comes to the other files.
+    ///
+    /// This is the names, titles, and links of pages, along with the backlinks between them,
+    /// and the static files which can be embedded.
+    pub fn hash_links(&self, hasher: &mut impl Hasher) {
+        for page in &self.pages {
+            page.in_path.hash(hasher);
+            page.name.hash(hasher);
+            page.link.hash(hasher);
+            page.front_matter.title.hash(hasher);
+            page.front_matter.listed().hash(hasher);
+            page.front_matter.series.hash(hasher);
+            page.lang.hash(hasher);
+            // Series are ordered by when pages were published, but backlinks show their date.
+            published_date(page).hash(hasher);
+            page.front_matter.date.hash(hasher);
+        }
+        self.backlinks.hash(hasher);
+        self.related.hash(hasher);
+        self.translations.hash(hasher);
+        self.scheduled.hash(hasher);
+        for file in &self.statics {
+            file.in_path.hash(hasher);
+        }
+    }
+}
+
+/// How many related pages to find for each page.
+const RELATED_LIMIT: usize = 5;
+
+/// Find the pages related to each page, leaving out drafts and unlisted pages.
+///
+/// Two pages are more related the more tags they share, and the more pages they
+/// both link to, or get linked from. Linking to each other directly counts as well.
+fn related_pages(
+    pages: &[Page],
+    pages_by_tag: &BTreeMap<String, Vec<PageIndex>>,
+    backlinks: &[Vec<PageIndex>],
+) -> Vec<Vec<PageIndex>> {
+    let mut neighbors = vec![HashSet::new(); pages.len()];
+    for (i, linking) in backlinks.iter().enumerate() {
+        for &j in linking {
+            if i != j {
+                neighbors[i].insert(j);
+                neighbors[j].insert(i);
+            }
+        }
+    }
+    let mut scores = vec![HashMap::<PageIndex, usize>::new(); pages.len()];
+    let groups = pages_by_tag
+        .values()
+        .cloned()
+        .chain(neighbors.iter().map(|x| x.iter().copied().collect()));
+    for group in groups {
+        for &i in &group {
+            for &j in &group {
+                if i != j {
+                    *scores[i].entry(j).or_default() += 1;
+                }
+            }
+        }
+    }
+    for (i, neighbors) in neighbors.iter().enumerate() {
+        for &j in neighbors {
+            *scores[i].entry(j).or_default() += 1;
+        }
+    }
+    scores
+        .into_iter()
+        .map(|scores| {
+            let mut related = scores
+                .into_iter()
+                .filter(|&(j, _)| pages[j].front_matter.listed())
+                .collect::<Vec<_>>();
+            related.sort_by_key(|&(j, score)| {
+                (
+                    Reverse(score),
+                    Reverse(published_date(&pages[j])),
+                    &pages[j].front_matter.title,
+                    &pages[j].in_path,
+                )
+            });
+            related.truncate(RELATED_LIMIT);
+            related.into_iter().map(|(j, _)| j).collect()
+        })
+        .collect()
+}
+
+/// The archives a page with a given date, like `2023-01-31`, belongs to.
+fn archive_keys(date: &str, months: bool) -> Vec<String> {
+    let (Some(year), Some(month)) = (date.get(..4), date.get(5..7)) else {
+        return Vec::new();
+    };
+    let mut out = vec![year.to_string()];
+    if months {
+        out.push(format!("{year}/{month}"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontmatter::Source;
+    use crate::fs_utils::TempDir;
+    use crate::tags::tag_url;
+
+    fn location(path: &str, yaml: &str) -> (String, PathBuf) {
+        let yaml = format!("date: 2024-01-01\n{yaml}");
+        let path = Path::new(path);
+        let front_matter =
+            FrontMatter::try_from_source(path, Some(Source::Yaml(&yaml)), DateSettings::default())
+                .unwrap();
+        page_location(path, &front_matter, None, false, &mut Vec::new())
+    }
+
+    #[test]
+    fn path_links() {
+        let link = path_link(Path::new("Posts/a b/#1 ?50%.png"));
+        assert_eq!(link, "/Posts/a%20b/%231%20%3F50%25.png");
+        assert_eq!(path_link(Path::new("çà.svg")), "/%C3%A7%C3%A0.svg");
+    }
+
+    #[test]
+    fn series() {
+        let dir = TempDir::new("series");
+        for (name, date) in [
+            ("B", "2024-02-01"),
+            ("A", "2024-01-01"),
+            ("C", "2024-03-01"),
+        ] {
+            let yaml = format!("---\ndate: {date}\nseries: Guide\n---\n");
+            fs::write(dir.join(format!("{name}.md")), yaml).unwrap();
+        }
+        fs::write(dir.join("D.md"), "---\nseries: Guide\ndraft: true\n---\n").unwrap();
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let page = site_map.pages().find(|x| x.name == "B").unwrap();
+        let names = site_map
+            .series_of(page)
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn future_pages() {
+        let dir = TempDir::new("future");
+        fs::write(dir.join("Old.md"), "---\ndate: 2024-01-01\n---\n").unwrap();
+        fs::write(dir.join("New.md"), "---\ndate: 9999-01-01\n---\n").unwrap();
+        fs::write(
+            dir.join("Updated.md"),
+            "---\npublished: 2024-01-01\ndate: 9999-01-01\n---\n",
+        )
+        .unwrap();
+        let names = |config: &Config| {
+            let site_map = SiteMap::build(config, &dir, Path::new("")).unwrap();
+            let mut names = site_map.pages().map(|x| x.name.clone()).collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let future = Config {
+            future: true,
+            ..Default::default()
+        };
+        assert_eq!(names(&Config::default()), vec!["Old", "Updated"]);
+        assert_eq!(names(&future), vec!["New", "Old", "Updated"]);
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("out")).unwrap();
+        let old = site_map.page_by_name("Old");
+        assert!(site_map.is_scheduled(old, "new"));
+        assert!(site_map.is_scheduled(old, "./New"));
+        assert!(!site_map.is_scheduled(old, "Old"));
+        let outputs = site_map.scheduled_outputs().collect::<Vec<_>>();
+        assert_eq!(outputs, vec![Path::new("out/new.html")]);
+    }
+
+    #[test]
+    fn stable_order() {
+        let dir = TempDir::new("order");
+        for folder in ["c", "a", "b"] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+            for name in ["Y", "X"] {
+                let yaml = "---\ndate: 2024-01-01\ntags: [t, s]\n---\n";
+                fs::write(dir.join(folder).join(format!("{name}.md")), yaml).unwrap();
+            }
+        }
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let folders = site_map
+            .folders()
+            .map(|(folder, pages)| {
+                let names = pages.map(|x| x.name.as_str()).collect::<Vec<_>>();
+                (folder.display().to_string(), names)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            folders,
+            vec![
+                ("a".to_string(), vec!["Y", "X"]),
+                ("b".to_string(), vec!["Y", "X"]),
+                ("c".to_string(), vec!["Y", "X"])
+            ]
+        );
+        let tags = site_map
+            .pages_by_tag()
+            .map(|(tag, _)| tag)
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec!["s", "t"]);
+    }
+
+    #[test]
+    fn tag_slugs() {
+        let dir = TempDir::new("tag");
+        fs::write(
+            dir.join("A.md"),
+            "---\ntags: [Rust, C++, Hello World/Nested]\n---\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("B.md"),
+            "---\ntags: [rust, \"RUST\", \"!!\"]\n---\n",
+        )
+        .unwrap();
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let tags = site_map
+            .pages_by_tag()
+            .map(|(tag, pages)| (tag, tag_url(tag), pages.count()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![
+                ("C++", "/tag/c/".to_string(), 1),
+                ("Hello World", "/tag/hello-world/".to_string(), 1),
+                (
+                    "Hello World/Nested",
+                    "/tag/hello-world/nested/".to_string(),
+                    1
+                ),
+                ("Rust", "/tag/rust/".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn covers() {
+        let dir = TempDir::new("cover");
+        fs::create_dir_all(dir.join("posts/attachments")).unwrap();
+        fs::create_dir_all(dir.join("static")).unwrap();
+        fs::write(dir.join("posts/attachments/a.png"), "").unwrap();
+        fs::write(dir.join("static/b.png"), "").unwrap();
+        fs::write(dir.join("posts/attachments/f.pdf"), "").unwrap();
+        let files = [
+            ("A", "attachments/a.png"),
+            ("B", "../static/b.png"),
+            ("C", "/static/b.png"),
+            ("D", "https://cdn.example.com/d.png"),
+            ("E", "missing.png"),
+            ("F", "attachments/f.pdf"),
+        ];
+        for (name, cover) in files {
+            fs::write(
+                dir.join(format!("posts/{name}.md")),
+                format!("---\ncover: {cover}\n---\n"),
+            )
+            .unwrap();
+        }
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let cover = |name| site_map.page_by_name(name).unwrap().cover.clone();
+        assert_eq!(cover("A").as_deref(), Some("/posts/attachments/a.png"));
+        assert_eq!(cover("B").as_deref(), Some("/static/b.png"));
+        assert_eq!(cover("C").as_deref(), Some("/static/b.png"));
+        assert_eq!(cover("D").as_deref(), Some("https://cdn.example.com/d.png"));
+        assert_eq!(cover("E"), None);
+        assert_eq!(cover("F"), None);
+        let mut warnings = site_map
+            .warnings()
+            .map(|(_, d)| d.to_string())
+            .collect::<Vec<_>>();
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            vec![
+                "skipping cover `attachments/f.pdf`, which isn't an image",
+                "skipping cover `missing.png`, which isn't a file of the site",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_pages() {
+        let dir = TempDir::new("invalid");
+        fs::write(dir.join("A.md"), "---\ntitle: [a]\n---\n").unwrap();
+        fs::write(dir.join("B.md"), "---\ntitle: B\n---\n").unwrap();
+        fs::write(dir.join("C.md"), "---\ntags: {a: b}\n---\n").unwrap();
+        let error = SiteMap::build(&Config::default(), &dir, Path::new(""))
+            .unwrap_err()
+            .to_string();
+        let lines = error.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "failed to read 2 pages:");
+        assert!(lines[1].ends_with(
+            "A.md:2:8: invalid front matter, in `title`: invalid type: sequence, expected a string"
+        ));
+        assert_eq!(lines[3], "2 | title: [a]");
+        assert!(lines[5].ends_with(
+            "C.md:2:7: invalid front matter, in `tags`: expected a string, or a list of strings"
+        ));
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let dir = TempDir::new("duplicate");
+        fs::create_dir_all(dir.join("notes")).unwrap();
+        fs::create_dir_all(dir.join("archive/old")).unwrap();
+        fs::write(dir.join("Idea.md"), "---\ndate: 2024-01-01\n---\n").unwrap();
+        fs::write(dir.join("notes/Idea.md"), "---\ndate: 2024-02-01\n---\n").unwrap();
+        fs::write(
+            dir.join("archive/old/Idea.md"),
+            "---\ndate: 2024-03-01\n---\n",
+        )
+        .unwrap();
+        let build = |duplicate_names| {
+            let config = Config {
+                duplicate_names,
+                preferred_folders: vec![PathBuf::from("notes"), PathBuf::from("archive")],
+                ..Default::default()
+            };
+            SiteMap::build(&config, &dir, Path::new(""))
+        };
+        let resolve = |site_map: &SiteMap, name| {
+            let page = site_map.page_by_name(name);
+            page.map(|x| x.path_name.clone())
+        };
+        let newest = build(DuplicateNames::Newest).unwrap();
+        let shortest = build(DuplicateNames::Shortest).unwrap();
+        let folders = build(DuplicateNames::Folders).unwrap();
+        let qualified = build(DuplicateNames::Qualified).unwrap();
+        let error = build(DuplicateNames::Error).unwrap_err().to_string();
+        assert_eq!(resolve(&newest, "Idea").unwrap(), "archive/old/Idea");
+        assert_eq!(resolve(&shortest, "Idea").unwrap(), "Idea");
+        assert_eq!(resolve(&folders, "Idea").unwrap(), "notes/Idea");
+        assert_eq!(resolve(&qualified, "Idea"), None);
+        assert!(qualified.is_ambiguous(None, "Idea"));
+        assert!(!newest.is_ambiguous(None, "Idea"));
+        assert_eq!(resolve(&qualified, "notes/Idea").unwrap(), "notes/Idea");
+        assert_eq!(resolve(&qualified, "old/Idea").unwrap(), "archive/old/Idea");
+        assert_eq!(resolve(&qualified, "/Idea").unwrap(), "Idea");
+        assert_eq!(resolve(&qualified, "/old/Idea"), None);
+        assert!(error.starts_with(
+            "pages share names, which `duplicate_names: error` doesn't allow:\n`Idea`: "
+        ));
+    }
+
+    #[test]
+    fn folded_names() {
+        let dir = TempDir::new("folded");
+        fs::create_dir_all(dir.join("Notes")).unwrap();
+        for file in [
+            "My Café.md",
+            "Notes/Sub Note.md",
+            "Résumé.md",
+            "Resume.md",
+            "日本.md",
+            "2×3.md",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let resolve = |name| site_map.page_by_name(name).map(|x| x.path_name.as_str());
+        assert_eq!(resolve("my  cafe"), Some("My Café"));
+        assert_eq!(resolve("MY CAFE\u{301}"), Some("My Café"));
+        assert_eq!(resolve("notes/sub note"), Some("Notes/Sub Note"));
+        assert_eq!(resolve("Résumé"), Some("Résumé"));
+        assert_eq!(resolve("Resume"), Some("Resume"));
+        assert_eq!(resolve("日本"), Some("日本"));
+        assert_eq!(resolve("riben"), None);
+        assert_eq!(resolve("2×3"), Some("2×3"));
+        assert_eq!(resolve("2x3"), None);
+        assert_eq!(fold_name(" My\tCafé "), "my cafe");
+        assert!(matches!(fold_name("my cafe 日本"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn kept_paths() {
+        let dir = TempDir::new("kept-paths");
+        fs::create_dir_all(dir.join("Posts")).unwrap();
+        fs::write(dir.join("Posts/Why Rust?.md"), "").unwrap();
+        fs::write(dir.join("A.md"), "[[Why Rust?]]").unwrap();
+        let link = |keep_paths| {
+            let config = Config {
+                keep_paths,
+                ..Config::default()
+            };
+            let site_map = SiteMap::build(&config, &dir, Path::new("")).unwrap();
+            let page = site_map.page_by_name("Why Rust?").unwrap();
+            let backlinks = site_map.backlinks(page).map(|x| x.name.clone());
+            assert_eq!(backlinks.collect::<Vec<_>>(), ["A"]);
+            (
+                page.link.clone(),
+                page.out_path.strip_prefix("").unwrap().to_path_buf(),
+            )
+        };
+        assert_eq!(
+            link(false),
+            ("/posts/why-rust.html".into(), "posts/why-rust.html".into())
+        );
+        assert_eq!(
+            link(true),
+            (
+                "/Posts/Why%20Rust%3F.html".into(),
+                "Posts/Why Rust?.html".into()
+            )
+        );
+    }
+
+    #[test]
+    fn relative_links() {
+        let dir = TempDir::new("relative");
+        fs::create_dir_all(dir.join("Posts/2023")).unwrap();
+        fs::create_dir_all(dir.join("Other")).unwrap();
+        fs::write(dir.join("Posts/2023/Foo.md"), "[[../../Other/Bar]]").unwrap();
+        fs::write(dir.join("Posts/2023/Baz.md"), "").unwrap();
+        fs::write(dir.join("Other/Bar.md"), "").unwrap();
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let foo = site_map.page_by_name("Foo");
+        let resolve = |name| {
+            site_map
+                .page_by_link(foo, name)
+                .map(|x| x.path_name.as_str())
+        };
+        assert_eq!(resolve("../../Other/Bar"), Some("Other/Bar"));
+        assert_eq!(resolve("./Baz"), Some("Posts/2023/Baz"));
+        assert_eq!(resolve("../2023/./Baz"), Some("Posts/2023/Baz"));
+        assert_eq!(resolve("Posts/2023/Foo"), Some("Posts/2023/Foo"));
+        assert_eq!(resolve("../Baz"), None);
+        assert_eq!(resolve("../../../Other/Bar"), None);
+        let bar = site_map.page_by_name("Bar").unwrap();
+        let backlinks = site_map.backlinks(bar).map(|x| x.name.as_str());
+        assert_eq!(backlinks.collect::<Vec<_>>(), vec!["Foo"]);
+    }
+
+    #[test]
+    fn not_found_page() {
+        let dir = TempDir::new("not-found");
+        fs::create_dir_all(dir.join("posts")).unwrap();
+        fs::write(dir.join("404.md"), "---\nslug: missing\n---\n").unwrap();
+        fs::write(dir.join("posts").join("404.md"), "").unwrap();
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let mut pages = site_map
+            .pages()
+            .map(|x| (x.link.as_str(), x.front_matter.listed()))
+            .collect::<Vec<_>>();
+        pages.sort();
+        assert_eq!(pages, vec![("/404.html", false), ("/posts/404.html", true)]);
+    }
+
+    #[test]
+    fn languages() {
+        let dir = TempDir::new("languages");
+        for folder in ["en", "fr"] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+            fs::write(
+                dir.join(folder).join("Hello.md"),
+                "---
+date: 2024-01-01
+---
+",
+            )
+            .unwrap();
+        }
+        fs::write(
+            dir.join("Hallo.md"),
+            "---
+date: 2024-01-01
+lang: de
+translation_key: Hello
+---
+",
+        )
+        .unwrap();
+        let config = Config {
+            language: Some("en".into()),
+            languages: vec!["en".into(), "fr".into(), "de".into()],
+            duplicate_names: DuplicateNames::Error,
+            ..Default::default()
+        };
+        // Translations sharing a name aren't duplicates.
+        let site_map = SiteMap::build(&config, &dir, Path::new("")).unwrap();
+        assert_eq!(site_map.duplicate_names().count(), 0);
+        let fr = site_map
+            .pages()
+            .find(|x| x.lang.as_deref() == Some("fr"))
+            .unwrap();
+        let linked = site_map.page_by_link(Some(fr), "Hello").unwrap();
+        assert_eq!(linked.link, "/fr/hello.html");
+        let page = site_map.pages().find(|x| x.name == "Hallo").unwrap();
+        assert_eq!(page.link, "/de/hallo.html");
+        let en = site_map
+            .pages()
+            .find(|x| x.lang.as_deref() == Some("en"))
+            .unwrap();
+        assert_eq!(en.link, "/en/hello.html");
+        let translations = site_map
+            .translations(en)
+            .map(|x| (x.lang.as_deref().unwrap(), x.link.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            translations,
+            vec![("de", "/de/hallo.html"), ("fr", "/fr/hello.html")]
+        );
+        let languages = site_map
+            .pages_by_language()
+            .map(|(lang, pages)| (lang, pages.count()))
+            .collect::<Vec<_>>();
+        assert_eq!(languages, vec![("de", 1), ("en", 1), ("fr", 1)]);
+    }
+
+    #[test]
+    fn related() {
+        let dir = TempDir::new("related");
+        let files = [
+            ("A", "tags: [x, y]", "[[C]]"),
+            ("B", "tags: [x, y]", ""),
+            ("C", "tags: [x]", ""),
+            ("D", "tags: [z]", ""),
+            ("E", "tags: [x, y]\ndraft: true", ""),
+        ];
+        for (name, yaml, body) in files {
+            let data = format!("---\ndate: 2024-01-01\n{yaml}\n---\n{body}\n");
+            fs::write(dir.join(format!("{name}.md")), data).unwrap();
+        }
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let related = |name: &str| {
+            let page = site_map.pages().find(|x| x.name == name).unwrap();
+            site_map
+                .related(page)
+                .map(|x| x.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(related("A"), vec!["B", "C"]);
+        assert_eq!(related("C"), vec!["A", "B"]);
+        assert!(related("D").is_empty());
+    }
+
+    #[test]
+    fn backlinks() {
+        let dir = TempDir::new("backlinks");
+        let files = [
+            ("A", "", "[[C]] and [[C#Part]], [[A]]"),
+            ("B", "draft: true", "[[C]]"),
+            ("C", "", ""),
+            ("D", "unlisted: true", "[[C]], [[A]]"),
+        ];
+        for (name, yaml, body) in files {
+            let data = format!("---\ndate: 2024-01-01\n{yaml}\n---\n{body}\n");
+            fs::write(dir.join(format!("{name}.md")), data).unwrap();
+        }
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let backlinks = |name: &str| {
+            let page = site_map.pages().find(|x| x.name == name).unwrap();
+            site_map
+                .backlinks(page)
+                .map(|x| x.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(backlinks("C"), vec!["A"]);
+        assert!(backlinks("A").is_empty());
+        // Unlisted pages still get rendered, and can be linked to.
+        assert!(site_map.pages().any(|x| x.name == "D"));
+    }
+
+    #[test]
+    fn archives() {
+        assert_eq!(archive_keys("2023-01-31", false), vec!["2023"]);
+        assert_eq!(archive_keys("2023-01-31", true), vec!["2023", "2023/01"]);
+        assert!(archive_keys("2023", true).is_empty());
+    }
+
+    #[test]
+    fn permalink_patterns() {
+        let location = |path: &str, yaml: &str, pattern: &str| {
+            let yaml = format!("date: 2024-03-09\n{yaml}");
+            let path = Path::new(path);
+            let dates = DateSettings::default();
+            let front_matter =
+                FrontMatter::try_from_source(path, Some(Source::Yaml(&yaml)), dates).unwrap();
+            page_location(path, &front_matter, Some(pattern), false, &mut Vec::new())
+        };
+        assert_eq!(
+            location("Posts/My Post.md", "", "/:folder/:slug/"),
+            ("/posts/my-post/".into(), "posts/my-post/index.html".into())
+        );
+        assert_eq!(
+            location("My Post.md", "slug: Other", "/:folder/:slug/"),
+            ("/other/".into(), "other/index.html".into())
+        );
+        assert_eq!(
+            location("Posts/My Post.md", "", "/:year/:month/:day/:slug"),
+            (
+                "/2024/03/09/my-post.html".into(),
+                "2024/03/09/my-post.html".into()
+            )
+        );
+        assert_eq!(
+            location("Posts/index.md", "", "/:slug/"),
+            ("/posts/index.html".into(), "posts/index.html".into())
+        );
+        assert_eq!(
+            location("Posts/My Post.md", "permalink: /me/", "/:slug/"),
+            ("/me/".into(), "me/index.html".into())
+        );
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(
+            location("Posts/My Post.md", ""),
+            ("/posts/my-post.html".into(), "posts/my-post.html".into())
+        );
+        assert_eq!(
+            location("Posts/Why Rust?.md", ""),
+            ("/posts/why-rust.html".into(), "posts/why-rust.html".into())
+        );
+        assert_eq!(
+            location("Posts/My Post.md", "slug: Better Name"),
+            (
+                "/posts/better-name.html".into(),
+                "posts/better-name.html".into()
+            )
+        );
+        assert_eq!(
+            location("Posts/My Post.md", "permalink: /about/"),
+            ("/about/".into(), "about/index.html".into())
+        );
+        assert_eq!(
+            location("Posts/My Post.md", "permalink: me"),
+            ("/me.html".into(), "me.html".into())
+        );
+        assert_eq!(
+            location("a.md", "permalink: ../../etc"),
+            ("/a.html".into(), "a.html".into())
+        );
+    }
+
+    #[test]
+    fn aliases() {
+        let yaml = "date: 2024-01-01\naliases: Posts/Old Name\nredirect_from: [/old/, ../x]";
+        let path = Path::new("Posts/New.md");
+        let front_matter =
+            FrontMatter::try_from_source(path, Some(Source::Yaml(yaml)), DateSettings::default())
+                .unwrap();
+        assert_eq!(
+            alias_locations(&front_matter, &mut Vec::new()),
+            vec![
+                (
+                    "/posts/old-name.html".to_string(),
+                    PathBuf::from("posts/old-name.html")
+                ),
+                ("/old/".to_string(), PathBuf::from("old/index.html"))
+            ]
+        );
+    }
+
+    #[test]
+    fn alias_conflicts() {
+        let dir = TempDir::new("alias");
+        let files = [
+            ("A.md", "---\naliases: [Old, Old]\n---\n"),
+            ("B.md", "---\naliases: [old]\n---\n"),
+            ("C.md", "---\naliases: [diagram.svg]\n---\n"),
+            ("diagram.svg", ""),
+        ];
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        let site_map = SiteMap::build(&Config::default(), &dir, Path::new("")).unwrap();
+        let mut warnings = site_map
+            .warnings()
+            .map(|(path, d)| format!("{}: {d}", path.display()))
+            .map(|x| x.replace(&format!("{}/", dir.display()), ""))
+            .collect::<Vec<_>>();
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            vec![
+                "A.md: alias at `old.html` is also written by an alias of `B.md`",
+                "B.md: alias at `old.html` is also written by an alias of `A.md`",
+                "C.md: alias at `diagram.svg` is also written by `diagram.svg`",
+            ]
+        );
+    }
 }
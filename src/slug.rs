@@ -35,21 +35,14 @@ pub fn slugify(s: &str) -> String {
                     result.push('.');
                     prev_was_hyphen = false;
                 }
-                // Convert spaces and underscores to hyphens
-                ' ' | '_' => {
-                    if !prev_was_hyphen {
-                        result.push('-');
-                        prev_was_hyphen = true;
-                    }
+                // Convert spaces and underscores to hyphens, keep existing hyphens,
+                // but collapse consecutive ones.
+                ' ' | '_' | '-' if !prev_was_hyphen => {
+                    result.push('-');
+                    prev_was_hyphen = true;
                 }
-                // Keep existing hyphens (but collapse consecutive ones)
-                '-' => {
-                    if !prev_was_hyphen {
-                        result.push('-');
-                        prev_was_hyphen = true;
-                    }
-                }
-                // Remove all other characters (apostrophes, quotes, colons, etc.)
+                // Remove all other characters (apostrophes, quotes, colons, etc.),
+                // including any hyphen following another one.
                 _ => {}
             }
         }
@@ -0,0 +1,125 @@
+use serde::Serialize;
+
+use crate::slug::slugify;
+
+/// The parts of a nested tag, like `programming/rust`, with their slugs.
+///
+/// Parts with nothing to put in a URL, like the empty one in `a//b`, are skipped.
+fn tag_parts(tag: &str) -> Vec<(&str, String)> {
+    tag.split('/')
+        .map(|part| (part.trim(), slugify(part)))
+        .filter(|(_, slug)| !slug.is_empty())
+        .collect()
+}
+
+fn slug_of(parts: &[(&str, String)]) -> String {
+    let slugs = parts
+        .iter()
+        .map(|(_, slug)| slug.as_str())
+        .collect::<Vec<_>>();
+    slugs.join("/")
+}
+
+/// The slug of a tag, with one level for each part of a nested tag, like `programming/rust`.
+pub fn tag_slug(tag: &str) -> String {
+    slug_of(&tag_parts(tag))
+}
+
+/// The names and slugs of the tags containing a tag, from the outermost one, to the tag itself.
+///
+/// For `programming/rust`, this gives `programming`, and `programming/rust`.
+pub fn tag_ancestors(tag: &str) -> Vec<(String, String)> {
+    let parts = tag_parts(tag);
+    (1..=parts.len())
+        .map(|depth| {
+            let names = parts[..depth]
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>();
+            (names.join("/"), slug_of(&parts[..depth]))
+        })
+        .collect()
+}
+
+/// The URL of the page listing everything with a tag.
+pub fn tag_url(tag: &str) -> String {
+    format!("/tag/{}/", tag_slug(tag))
+}
+
+/// A tag, along with the tags nested inside of it, for showing the tags of a site as a tree.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TagNode {
+    /// The last part of the tag, like `rust` for `programming/rust`.
+    pub name: String,
+    /// The whole tag.
+    pub tag: String,
+    pub url: String,
+    /// How many pages have this tag, or one nested inside of it.
+    pub count: usize,
+    pub children: Vec<TagNode>,
+}
+
+/// Arrange tags, with their number of pages, into a tree following their nesting.
+///
+/// Tags keep the order they're given in, among their siblings. A tag nested inside
+/// of one which isn't given ends up at the top of the tree.
+pub fn tag_tree(tags: &[(&str, usize)]) -> Vec<TagNode> {
+    let mut tags = tags
+        .iter()
+        .map(|&(tag, count)| (tag_parts(tag), tag, count))
+        .collect::<Vec<_>>();
+    // Parents need to be in the tree before their children.
+    tags.sort_by_key(|(parts, _, _)| parts.len());
+    let mut tree = Vec::<TagNode>::new();
+    for (parts, tag, count) in tags {
+        let Some(((name, _), parents)) = parts.split_last() else {
+            continue;
+        };
+        let mut siblings = &mut tree;
+        for depth in 1..=parents.len() {
+            let parent = slug_of(&parts[..depth]);
+            match siblings.iter().position(|x| tag_slug(&x.tag) == parent) {
+                Some(i) => siblings = &mut siblings[i].children,
+                None => break,
+            }
+        }
+        siblings.push(TagNode {
+            name: name.to_string(),
+            tag: tag.to_string(),
+            url: tag_url(tag),
+            count,
+            children: Vec::new(),
+        });
+    }
+    tree
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_tags() {
+        assert_eq!(
+            tag_url("Programming/Rust Lang"),
+            "/tag/programming/rust-lang/"
+        );
+        assert_eq!(tag_url("a//b/"), "/tag/a/b/");
+        assert_eq!(
+            tag_ancestors("Programming/Rust"),
+            vec![
+                ("Programming".to_string(), "programming".to_string()),
+                (
+                    "Programming/Rust".to_string(),
+                    "programming/rust".to_string()
+                )
+            ]
+        );
+        let tree = tag_tree(&[("a", 3), ("a/b", 2), ("a/b/c", 1), ("a/d", 1), ("e", 1)]);
+        let names = |nodes: &[TagNode]| nodes.iter().map(|x| x.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&tree), vec!["a", "e"]);
+        assert_eq!(names(&tree[0].children), vec!["b", "d"]);
+        assert_eq!(tree[0].children[0].children[0].tag, "a/b/c");
+        assert_eq!(tree[0].children[0].children[0].url, "/tag/a/b/c/");
+    }
+}
@@ -0,0 +1,185 @@
+use minijinja::{AutoEscape, Environment, ErrorKind, Template, context};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{
+    config::{Config, HighlightStyle},
+    markdown::HIGHLIGHT_CSS,
+    tags::tag_url,
+};
+
+mod filters;
+
+/// Add all of the templates in a directory, recursively, to an environment.
+///
+/// Each template is named after its path relative to `base`, e.g. `partials/nav.html`.
+fn add_templates(env: &mut Environment<'static>, base: &Path, dir: &Path) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            add_templates(env, base, &path)?;
+            continue;
+        }
+        let name = path
+            .strip_prefix(base)?
+            .iter()
+            .map(|x| x.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let source = fs::read_to_string(&path)?;
+        env.add_template_owned(name, source)?;
+    }
+    Ok(())
+}
+
+/// Create the environment used to render the whole site.
+///
+/// This is the one place where templates, filters, and functions get registered,
+/// so that every template can make use of them, and refer to the others by name.
+///
+/// The site level metadata from the config is available to every template, as `site`.
+/// The URLs of bundles, and fingerprinted files, are available as `assets`, and
+/// through `asset("name")`. When code is highlighted with classes, the link to its
+/// stylesheet is available as `highlight_css`, going to the site's own `highlight.css`
+/// in `static`, if there is one. The URL of the page for a tag is
+/// `tag_url("name")`.
+pub fn environment(
+    template_dir: &Path,
+    config: &Config,
+    assets: &BTreeMap<String, String>,
+) -> anyhow::Result<Environment<'static>> {
+    let mut env = Environment::new();
+    // Values like `body` are already HTML, and templates expect to include them as is.
+    env.set_auto_escape_callback(|_| AutoEscape::None);
+    filters::add_filters(&mut env, config.date_format.as_deref());
+    env.add_global(
+        "site",
+        context! {
+            title => config.title,
+            description => config.description,
+            author => config.author,
+            language => config.language,
+            base_url => config.base_url,
+            params => config.params
+        },
+    );
+    env.add_global("assets", minijinja::Value::from_serialize(assets));
+    if config.highlight_style == HighlightStyle::Classes && cfg!(feature = "highlight") {
+        let css = assets
+            .get(&HIGHLIGHT_CSS[1..])
+            .map_or(HIGHLIGHT_CSS, |x| x.as_str());
+        env.add_global("highlight_css", css.to_string());
+    }
+    let urls = assets.clone();
+    env.add_function("asset", move |name: &str| match urls.get(name) {
+        Some(url) => url.clone(),
+        None => format!("/static/{}", name.trim_start_matches('/')),
+    });
+    env.add_function("tag_url", |tag: &str| tag_url(tag));
+    add_templates(&mut env, template_dir, template_dir)?;
+    Ok(env)
+}
+
+/// Create the environment for the shortcode templates in a directory, like `youtube.html`.
+///
+/// Unlike page templates, these escape the values they include, since these are
+/// just the arguments written in the markdown.
+pub fn shortcodes(shortcode_dir: &Path, config: &Config) -> anyhow::Result<Environment<'static>> {
+    let mut env = Environment::new();
+    filters::add_filters(&mut env, config.date_format.as_deref());
+    if shortcode_dir.is_dir() {
+        add_templates(&mut env, shortcode_dir, shortcode_dir)?;
+    }
+    Ok(env)
+}
+
+/// Get a template which the site doesn't need to provide.
+pub fn optional_template<'env>(
+    env: &'env Environment<'static>,
+    name: &str,
+) -> anyhow::Result<Option<Template<'env, 'env>>> {
+    match env.get_template(name) {
+        Ok(template) => Ok(Some(template)),
+        Err(e) if e.kind() == ErrorKind::TemplateNotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn site_metadata() {
+        let dir = TempDir::new("templates");
+        fs::write(
+            dir.join("index.html"),
+            "{{ site.title }} - {{ site.author.name }} - {{ site.params.color }}",
+        )
+        .unwrap();
+        let config =
+            Config::try_from_yaml("title: Blog\nauthor: { name: Me }\nparams: { color: red }\n")
+                .unwrap();
+        let env = environment(&dir, &config, &BTreeMap::new()).unwrap();
+        let out = env.get_template("index.html").unwrap().render(()).unwrap();
+        assert_eq!(out, "Blog - Me - red");
+    }
+
+    #[test]
+    fn assets() {
+        let dir = TempDir::new("asset");
+        fs::write(
+            dir.join("index.html"),
+            "{{ asset('style.css') }} {{ asset('a.js') }}",
+        )
+        .unwrap();
+        let assets = BTreeMap::from([("style.css".into(), "/static/style.1234.css".into())]);
+        let env = environment(&dir, &Config::default(), &assets).unwrap();
+        let out = env.get_template("index.html").unwrap().render(()).unwrap();
+        assert_eq!(out, "/static/style.1234.css /static/a.js");
+    }
+
+    #[cfg(feature = "highlight")]
+    #[test]
+    fn own_highlight_css() {
+        let dir = TempDir::new("highlight");
+        fs::write(dir.join("index.html"), "{{ highlight_css }}").unwrap();
+        let config = Config::try_from_yaml("highlight_style: classes\n").unwrap();
+        let render = |assets: &BTreeMap<String, String>| {
+            let env = environment(&dir, &config, assets).unwrap();
+            env.get_template("index.html").unwrap().render(()).unwrap()
+        };
+        let theme = render(&BTreeMap::new());
+        let own = render(&BTreeMap::from([(
+            "highlight.css".into(),
+            "/static/highlight.css".into(),
+        )]));
+        assert_eq!(theme, "/highlight.css");
+        assert_eq!(own, "/static/highlight.css");
+    }
+
+    #[test]
+    fn inheritance() {
+        let dir = TempDir::new("inherit");
+        fs::create_dir_all(dir.join("partials")).unwrap();
+        fs::write(
+            dir.join("base.html"),
+            "{% include \"partials/nav.html\" %}[{% block main %}{% endblock %}]",
+        )
+        .unwrap();
+        fs::write(dir.join("partials/nav.html"), "nav").unwrap();
+        fs::write(
+            dir.join("index.html"),
+            "{% extends \"base.html\" %}{% block main %}{{ body }}{% endblock %}",
+        )
+        .unwrap();
+        let env = environment(&dir, &Config::default(), &BTreeMap::new()).unwrap();
+        let out = env
+            .get_template("index.html")
+            .unwrap()
+            .render(context! { body => "body" })
+            .unwrap();
+        assert_eq!(out, "nav[body]");
+    }
+}
@@ -0,0 +1,189 @@
+use minijinja::{Environment, Error, ErrorKind, Value, value::ValueKind};
+use regex::Regex;
+use std::sync::LazyLock;
+use time::{Date, format_description};
+
+use crate::slug;
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidOperation, message)
+}
+
+/// The format dates have, and are shown in, unless the config says otherwise.
+const ISO_DATE: &str = "[year]-[month]-[day]";
+
+/// A day of the month, with its ordinal suffix, like `3rd`.
+fn ordinal_day(day: u8) -> String {
+    let suffix = match (day % 10, day % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{day}{suffix}")
+}
+
+/// Format a date like `2024-01-31` using a `time` format description.
+///
+/// For example, `{{ date | dateformat("[month repr:long] [day padding:none], [year]") }}`
+/// gives `January 31, 2024`. On top of what `time` supports, `[day_ordinal]` gives
+/// days like `31st`.
+fn dateformat(value: &str, format: &str) -> Result<String, Error> {
+    let iso = format_description::parse(ISO_DATE).expect("valid format");
+    let date = value
+        .get(..10)
+        .and_then(|x| Date::parse(x, &iso).ok())
+        .ok_or_else(|| invalid(format!("invalid date: {value}")))?;
+    let parts = format
+        .split("[day_ordinal]")
+        .map(|part| {
+            let description = format_description::parse(part)
+                .map_err(|e| invalid(format!("invalid date format `{format}`: {e}")))?;
+            date.format(&description)
+                .map_err(|e| invalid(e.to_string()))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(parts.join(&ordinal_day(date.day())))
+}
+
+fn slugify(value: &str) -> String {
+    slug::slugify(value)
+}
+
+/// Keep only the first `count` words of some text, adding `…` if anything was cut.
+fn truncate_words(value: &str, count: usize) -> String {
+    let mut words = value.split_whitespace();
+    let mut out = words.by_ref().take(count).collect::<Vec<_>>().join(" ");
+    if words.next().is_some() {
+        out.push('…');
+    }
+    out
+}
+
+static TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// Like `truncate_words`, but for HTML, like the `body` of a page, removing the tags.
+fn excerpt(value: &str, count: Option<usize>) -> String {
+    truncate_words(&TAG_RE.replace_all(value, " "), count.unwrap_or(50))
+}
+
+/// Keep the items where an attribute equals a value, or contains it, for lists like `tags`.
+fn where_(items: Vec<Value>, attr: &str, value: Value) -> Result<Vec<Value>, Error> {
+    let mut out = Vec::new();
+    for item in items {
+        let field = item.get_attr(attr)?;
+        let matches = match field.kind() {
+            ValueKind::Seq => field.try_iter()?.any(|x| x == value),
+            _ => field == value,
+        };
+        if matches {
+            out.push(item);
+        }
+    }
+    Ok(out)
+}
+
+/// Sort items by an attribute, e.g. `{{ items | sort_by("date", true) }}` for newest first.
+fn sort_by(items: Vec<Value>, attr: &str, reverse: Option<bool>) -> Result<Vec<Value>, Error> {
+    let mut keyed = items
+        .into_iter()
+        .map(|item| Ok((item.get_attr(attr)?, item)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    if reverse.unwrap_or(false) {
+        keyed.reverse();
+    }
+    Ok(keyed.into_iter().map(|(_, item)| item).collect())
+}
+
+/// Register all of our filters.
+///
+/// `dateformat` uses `date_format` when it isn't given a format, or ISO dates otherwise.
+pub fn add_filters(env: &mut Environment<'_>, date_format: Option<&str>) {
+    let date_format = date_format.unwrap_or(ISO_DATE).to_string();
+    env.add_filter("dateformat", move |value: &str, format: Option<&str>| {
+        dateformat(value, format.unwrap_or(&date_format))
+    });
+    env.add_filter("slugify", slugify);
+    env.add_filter("truncate_words", truncate_words);
+    env.add_filter("excerpt", excerpt);
+    env.add_filter("where", where_);
+    env.add_filter("sort_by", sort_by);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use minijinja::context;
+
+    fn render(source: &str, ctx: Value) -> String {
+        let mut env = Environment::new();
+        add_filters(&mut env, Some("[day_ordinal] of [month repr:long]"));
+        env.render_str(source, ctx).unwrap()
+    }
+
+    #[test]
+    fn text_filters() {
+        assert_eq!(
+            dateformat("2024-01-31", "[month repr:long] [day padding:none], [year]").unwrap(),
+            "January 31, 2024"
+        );
+        assert!(dateformat("yesterday", "[year]").is_err());
+        assert_eq!(
+            dateformat("2024-03-03", "[month repr:long] [day_ordinal], [year]").unwrap(),
+            "March 3rd, 2024"
+        );
+        assert_eq!(ordinal_day(11), "11th");
+        assert_eq!(ordinal_day(22), "22nd");
+        assert_eq!(
+            render("{{ '2024-01-01' | dateformat }}", Value::UNDEFINED),
+            "1st of January"
+        );
+        assert_eq!(truncate_words("a b  c d", 2), "a b…");
+        assert_eq!(truncate_words("a b", 2), "a b");
+        assert_eq!(excerpt("<p>Some <em>text</em></p>", Some(5)), "Some text");
+        assert_eq!(
+            render("{{ 'Hello World' | slugify }}", Value::UNDEFINED),
+            "hello-world"
+        );
+    }
+
+    #[test]
+    fn list_filters() {
+        let items = vec![
+            context! { title => "A", date => "2024-02-01", tags => vec!["x"] },
+            context! { title => "B", date => "2024-01-01", tags => vec!["x", "y"] },
+            context! { title => "C", date => "2024-03-01", tags => Vec::<String>::new() },
+        ];
+        let ctx = context! { items => items };
+        assert_eq!(
+            render(
+                "{% for x in items | sort_by('date') %}{{ x.title }}{% endfor %}",
+                ctx.clone()
+            ),
+            "BAC"
+        );
+        assert_eq!(
+            render(
+                "{% for x in items | sort_by('date', true) %}{{ x.title }}{% endfor %}",
+                ctx.clone()
+            ),
+            "CAB"
+        );
+        assert_eq!(
+            render(
+                "{% for x in items | where('tags', 'x') %}{{ x.title }}{% endfor %}",
+                ctx.clone()
+            ),
+            "AB"
+        );
+        assert_eq!(
+            render(
+                "{% for x in items | where('title', 'C') %}{{ x.title }}{% endfor %}",
+                ctx
+            ),
+            "C"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    fmt::{self, Write},
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// How many of the slowest pages to show, along with the totals.
+const SLOWEST_PAGES: usize = 10;
+
+/// How long each stage of building a single page took.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PageTimings {
+    /// Reading the markdown file.
+    pub read: Duration,
+    /// Parsing the markdown.
+    pub parse: Duration,
+    /// Running plugins over the markdown.
+    pub plugins: Duration,
+    /// Rendering math with KaTeX.
+    pub math: Duration,
+    /// Writing the markdown as HTML, without the time spent on math.
+    pub markdown: Duration,
+    /// Rendering the template of the page.
+    pub template: Duration,
+    /// Writing the page out.
+    pub write: Duration,
+}
+
+impl PageTimings {
+    fn stages(&self) -> [(&'static str, Duration); 7] {
+        [
+            ("read", self.read),
+            ("parse", self.parse),
+            ("plugins", self.plugins),
+            ("math", self.math),
+            ("markdown", self.markdown),
+            ("template", self.template),
+            ("write", self.write),
+        ]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages().into_iter().map(|(_, x)| x).sum()
+    }
+}
+
+impl AddAssign for PageTimings {
+    fn add_assign(&mut self, rhs: Self) {
+        self.read += rhs.read;
+        self.parse += rhs.parse;
+        self.plugins += rhs.plugins;
+        self.math += rhs.math;
+        self.markdown += rhs.markdown;
+        self.template += rhs.template;
+        self.write += rhs.write;
+    }
+}
+
+/// Run a function, adding how long it took to a duration.
+pub fn timed<T>(duration: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let out = f();
+    *duration += start.elapsed();
+    out
+}
+
+/// How long the stages of a build took, for `--timings`.
+#[derive(Debug, Default)]
+pub struct Timings {
+    /// Finding the pages, and reading their front matter.
+    pub sitemap: Duration,
+    /// Everything, from start to finish.
+    pub total: Duration,
+    pages: Vec<(PathBuf, PageTimings)>,
+}
+
+impl Timings {
+    pub fn add_page(&mut self, path: &Path, timings: PageTimings) {
+        self.pages.push((path.to_path_buf(), timings));
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// A report with the totals for each stage, and the slowest pages.
+///
+/// Stages of different pages run in parallel, so their totals can add up to more
+/// than the time the build took.
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut totals = PageTimings::default();
+        for (_, timings) in &self.pages {
+            totals += *timings;
+        }
+        writeln!(f, "timings for {} pages:", self.pages.len())?;
+        writeln!(f, "  {:<10} {:>10}", "sitemap", millis(self.sitemap))?;
+        for (stage, duration) in totals.stages() {
+            writeln!(f, "  {stage:<10} {:>10}", millis(duration))?;
+        }
+        writeln!(f, "  {:<10} {:>10}", "total", millis(self.total))?;
+        let mut slowest = self.pages.iter().collect::<Vec<_>>();
+        slowest.sort_by_key(|(path, timings)| (std::cmp::Reverse(timings.total()), path));
+        writeln!(f, "slowest pages:")?;
+        for (path, timings) in slowest.into_iter().take(SLOWEST_PAGES) {
+            let mut stages = String::new();
+            for (stage, duration) in timings.stages() {
+                if !duration.is_zero() {
+                    write!(stages, " {stage} {}", millis(duration))?;
+                }
+            }
+            writeln!(
+                f,
+                "  {:>10} {} ({})",
+                millis(timings.total()),
+                path.display(),
+                stages.trim_start()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let mut timings = Timings::default();
+        let page = |ms| PageTimings {
+            math: Duration::from_millis(ms),
+            parse: Duration::from_millis(1),
+            ..Default::default()
+        };
+        timings.add_page(Path::new("a.md"), page(2));
+        timings.add_page(Path::new("b.md"), page(5));
+        let report = timings.to_string();
+        let lines = report
+            .lines()
+            .map(|x| x.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert!(lines.contains(&vec!["math", "7.0ms"]));
+        let slowest = lines
+            .iter()
+            .position(|x| x == &["slowest", "pages:"])
+            .unwrap();
+        assert_eq!(
+            lines[slowest + 1],
+            vec!["6.0ms", "b.md", "(parse", "1.0ms", "math", "5.0ms)"]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How often to look for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// The size and modification time of each file under some folder.
+type Snapshot = BTreeMap<PathBuf, (u64, Option<SystemTime>)>;
+
+/// Record every file under a folder, skipping `skip`, and things like `.git`.
+///
+/// Symlinks are recorded by what they point to, but never descended into, to avoid cycles.
+fn snapshot(dir: &Path, skip: &Path, out: &mut Snapshot) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path == skip || entry.file_name() == ".git" {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(x) if x.is_dir() => snapshot(&path, skip, out),
+            Ok(_) => {
+                if let Ok(meta) = fs::metadata(&path) {
+                    out.insert(path, (meta.len(), meta.modified().ok()));
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Poll the files under a folder, calling `on_change` whenever some of them change.
+///
+/// Files under `skip`, like the output of the site, are ignored. This never returns.
+pub fn watch(dir: &Path, skip: &Path, mut on_change: impl FnMut()) -> ! {
+    // The output folder might be given in another way than the input, e.g. `./out`.
+    let skip = fs::canonicalize(skip).unwrap_or_else(|_| skip.to_path_buf());
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let mut last = Snapshot::new();
+    snapshot(&dir, &skip, &mut last);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let mut current = Snapshot::new();
+        snapshot(&dir, &skip, &mut current);
+        if current != last {
+            last = current;
+            on_change();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_utils::TempDir;
+
+    #[test]
+    fn snapshots() {
+        let dir = TempDir::new("watch");
+        fs::create_dir_all(dir.join("content")).unwrap();
+        fs::create_dir_all(dir.join("out")).unwrap();
+        fs::write(dir.join("content/a.md"), "a").unwrap();
+        fs::write(dir.join("out/a.html"), "a").unwrap();
+        let mut before = Snapshot::new();
+        snapshot(&dir, &dir.join("out"), &mut before);
+        fs::write(dir.join("content/a.md"), "ab").unwrap();
+        let mut after = Snapshot::new();
+        snapshot(&dir, &dir.join("out"), &mut after);
+        assert_eq!(
+            before.keys().collect::<Vec<_>>(),
+            vec![&dir.join("content/a.md")]
+        );
+        assert_ne!(before, after);
+    }
+}
@@ -1,8 +1,8 @@
 use regex::Regex;
-use std::{iter, sync::LazyLock};
+use std::{borrow::Cow, iter, sync::LazyLock};
 
 static RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\[\[([^\|\[\]]+)\|?([^\|\[\]]+)?\]\]").unwrap());
+    LazyLock::new(|| Regex::new(r"(!)?\[\[([^\|\[\]]+)\|?([^\|\[\]]+)?\]\]").unwrap());
 
 /// A Link like `[[Foo]]` in a post.
 ///
@@ -11,6 +11,8 @@ static RE: LazyLock<Regex> =
 pub struct WikiLink<'a> {
     pub display: Option<&'a str>,
     pub name: &'a str,
+    /// Whether this is an embed, like `![[image.png]]`, rather than a link.
+    pub embed: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -20,16 +22,69 @@ pub enum Segment<'a> {
 }
 
 impl<'a> WikiLink<'a> {
-    pub fn display_or_name(&self) -> &'a str {
-        self.display.unwrap_or(self.name)
+    /// The text to show for this link.
+    ///
+    /// Without an explicit display, links to a section, like `[[Page#Section]]`,
+    /// are shown as `Page > Section`, like Obsidian does.
+    pub fn display_or_name(&self) -> Cow<'a, str> {
+        if let Some(display) = self.display {
+            return Cow::Borrowed(display);
+        }
+        match (self.page(), self.anchor()) {
+            (page, None) => Cow::Borrowed(page),
+            ("", Some(anchor)) => Cow::Borrowed(anchor),
+            (page, Some(anchor)) => Cow::Owned(format!("{page} > {anchor}")),
+        }
+    }
+
+    /// The name of the page this links to, without any `#` anchor.
+    ///
+    /// This is empty for links to a section of the same page, like `[[#Section]]`.
+    pub fn page(&self) -> &'a str {
+        match self.name.split_once('#') {
+            None => self.name.trim(),
+            Some((page, _)) => page.trim(),
+        }
+    }
+
+    /// The part of the link after the `#`, like `Section` in `[[Page#Section]]`.
+    pub fn anchor(&self) -> Option<&'a str> {
+        let (_, anchor) = self.name.split_once('#')?;
+        Some(anchor.trim()).filter(|x| !x.is_empty())
+    }
+
+    /// The size of an embed, from a suffix like `|300` or `|300x200`.
+    ///
+    /// This returns the width, and then the height, if there is one.
+    pub fn embed_size(&self) -> Option<(u32, Option<u32>)> {
+        let display = self.display.filter(|_| self.embed)?.trim();
+        match display.split_once('x') {
+            None => Some((display.parse().ok()?, None)),
+            Some((w, h)) => Some((w.parse().ok()?, Some(h.parse().ok()?))),
+        }
     }
 
-    /// Extract all of the links from a string.
+    /// Extract all of the links from markdown, skipping escaped ones, like `\[[Page]]`.
     pub fn extract(data: &'a str) -> impl Iterator<Item = Self> {
-        RE.captures_iter(data).map(|capture| {
-            let name = capture.get(1).unwrap().as_str();
-            let display = capture.get(2).map(|x| x.as_str());
-            Self { display, name }
+        RE.captures_iter(data).filter_map(|capture| {
+            // The brackets come right before the name.
+            let brackets = capture.get(2).unwrap().start() - 2;
+            let backslashes = data[..brackets]
+                .bytes()
+                .rev()
+                .take_while(|&x| x == b'\\')
+                .count();
+            if backslashes % 2 == 1 {
+                return None;
+            }
+            let embed = capture.get(1).is_some();
+            let name = capture.get(2).unwrap().as_str();
+            let display = capture.get(3).map(|x| x.as_str());
+            Some(Self {
+                display,
+                name,
+                embed,
+            })
         })
     }
 
@@ -40,9 +95,8 @@ impl<'a> WikiLink<'a> {
         let mut locs = RE.capture_locations();
         iter::successors(Some((0usize, [None, None])), move |(pos, queue)| {
             // Yield the second item in the queue, if there was one.
-            match queue {
-                [_, rest @ Some(_)] => return Some((*pos, [rest.clone(), None])),
-                [_, None] => {}
+            if let [_, rest @ Some(_)] = queue {
+                return Some((*pos, [rest.clone(), None]));
             }
             // Our current position in the string, which we'll mutate
             let mut pos = *pos;
@@ -71,9 +125,14 @@ impl<'a> WikiLink<'a> {
                     // Regardless, move the position past the end of the capture.
                     pos = c_end;
                     let link = {
-                        let name = locs.get(1).map(|(s, e)| &data[s..e]).unwrap();
-                        let display = locs.get(2).map(|(s, e)| &data[s..e]);
-                        Self { display, name }
+                        let embed = locs.get(1).is_some();
+                        let name = locs.get(2).map(|(s, e)| &data[s..e]).unwrap();
+                        let display = locs.get(3).map(|(s, e)| &data[s..e]);
+                        Self {
+                            display,
+                            name,
+                            embed,
+                        }
                     };
                     queue[q_i] = Some(Segment::Link(link));
                 }
@@ -98,18 +157,22 @@ mod test {
                 WikiLink {
                     name: "One",
                     display: None,
+                    embed: false,
                 },
                 WikiLink {
                     name: "Two",
                     display: Some("TWO"),
+                    embed: false,
                 },
                 WikiLink {
                     name: "Three",
                     display: Some("THREE"),
+                    embed: false,
                 },
                 WikiLink {
                     name: "Four Five",
                     display: None,
+                    embed: false,
                 }
             ]
         )
@@ -127,15 +190,43 @@ mod test {
                 Segment::Normal("start "),
                 Segment::Link(WikiLink {
                     display: None,
-                    name: "link 1"
+                    name: "link 1",
+                    embed: false,
                 }),
                 Segment::Normal(" middle "),
                 Segment::Link(WikiLink {
                     display: None,
-                    name: "link 2"
+                    name: "link 2",
+                    embed: false,
                 }),
                 Segment::Normal(" end"),
             ]
         );
     }
+
+    #[test]
+    fn embeds() {
+        let links =
+            WikiLink::extract("![[a.png|300]] ![[b.png|30x20]] [[c.png|alt]]").collect::<Vec<_>>();
+        assert!(links[0].embed);
+        assert_eq!(links[0].name, "a.png");
+        assert_eq!(links[0].embed_size(), Some((300, None)));
+        assert_eq!(links[1].embed_size(), Some((30, Some(20))));
+        assert!(!links[2].embed);
+        assert_eq!(links[2].embed_size(), None);
+    }
+
+    #[test]
+    fn anchors() {
+        let links = WikiLink::extract("[[Page#Some Section]] [[#Here|there]] [[Page#]]")
+            .collect::<Vec<_>>();
+        assert_eq!(links[0].page(), "Page");
+        assert_eq!(links[0].anchor(), Some("Some Section"));
+        assert_eq!(links[0].display_or_name(), "Page > Some Section");
+        assert_eq!(links[1].page(), "");
+        assert_eq!(links[1].anchor(), Some("Here"));
+        assert_eq!(links[1].display_or_name(), "there");
+        assert_eq!(links[2].anchor(), None);
+        assert_eq!(links[2].display_or_name(), "Page");
+    }
 }